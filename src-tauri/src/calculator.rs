@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
 use sqlx::{SqlitePool, Row};
-use std::collections::HashMap;
 use chrono::prelude::*;
+use crate::lots::{self, ClosedLot, CostBasisMethod, DividendPayment, LotLedger, OversellWarning};
+use crate::manual_assets::MANUAL_ASSET_TYPE;
+use crate::market_calendar;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Holding {
@@ -14,6 +16,69 @@ pub struct Holding {
     pub value: f64,
     pub pnl: f64,
     pub pnl_pct: f64,
+    pub dividend_income: f64,   // net, all time
+    pub yield_on_cost_pct: f64, // trailing 12 months net dividends / remaining cost
+    pub last_updated: Option<String>,
+    pub price_source: Option<String>,
+    pub stale: bool, // price missed its market's expected refresh (or was never fetched)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OpenLot {
+    pub transaction_id: i64,
+    pub corporate_action_id: Option<i64>,
+    pub buy_date: String,
+    pub quantity: f64,
+    pub cost_price: f64,
+    pub current_value: f64,
+    pub unrealized_pnl: f64,
+    pub unrealized_pnl_pct: f64,
+    pub days_held: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SymbolLots {
+    pub symbol: String,
+    pub name: String,
+    pub asset_type: String,
+    pub cost_basis_method: CostBasisMethod,
+    pub current_price: f64,
+    pub lots: Vec<OpenLot>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ClosedTradeStats {
+    pub trade_count: usize,
+    pub win_count: usize,
+    pub loss_count: usize,
+    pub win_rate: f64,
+    pub avg_holding_days: f64,
+    pub avg_gain: f64,
+    pub avg_loss: f64,
+    pub total_realized_pnl: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ClosedTradeReport {
+    pub trades: Vec<ClosedLot>,
+    pub stats: ClosedTradeStats,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DividendTotals {
+    pub key: String, // year ("2024") or symbol
+    pub payments: usize,
+    pub gross: f64,
+    pub withholding_tax: f64,
+    pub net: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DividendReport {
+    pub by_year: Vec<DividendTotals>,
+    pub by_symbol: Vec<DividendTotals>,
+    pub payments: Vec<DividendPayment>,
+    pub total: DividendTotals,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -22,12 +87,17 @@ pub struct PortfolioSummary {
     pub total_value_usd: f64,
     pub unrealized_pnl: f64,
     pub realized_pnl: f64,
+    pub dividend_income: f64,
     pub total_return: f64,
     pub roi_pct: f64,
     pub holdings_count: usize,
     pub top_performer: String,
     pub worst_performer: String,
     pub last_updated: Option<String>,
+    pub total_fees: f64,
+    pub oversell_warnings: Vec<OversellWarning>,
+    pub stale_value: f64,     // part of total_value priced from stale data
+    pub stale_value_pct: f64,
     
     // Performance metrics
     pub daily_change: f64,
@@ -42,145 +112,243 @@ pub struct CalculatorService;
 
 impl CalculatorService {
     pub async fn get_current_holdings(pool: &SqlitePool) -> Result<(Vec<Holding>, f64), Box<dyn std::error::Error>> {
-        let rows = sqlx::query(
-            "SELECT id, transaction_date, asset_type, symbol, transaction_type, quantity, price 
-             FROM transactions 
-             ORDER BY transaction_date ASC, created_at ASC"
-        )
-        .fetch_all(pool)
-        .await?;
+        let ledger = LotLedger::load(pool).await?;
+        let holdings = Self::build_holdings(pool, &ledger).await?;
+        Ok((holdings, ledger.realized_pnl()))
+    }
 
-        let mut fifo_queues: HashMap<String, Vec<(f64, f64, String)>> = HashMap::new(); // symbol -> Vec<(qty, price, type)>
-        let mut realized_pnl = 0.0;
-
-        for row in rows {
-            let symbol: String = row.get("symbol");
-            let tx_type: String = row.get("transaction_type");
-            let qty: f64 = row.get("quantity");
-            let price: f64 = row.get("price");
-            let asset_type: String = row.get("asset_type");
-
-            let symbol = symbol.to_uppercase();
-            let queue = fifo_queues.entry(symbol.clone()).or_insert(Vec::new());
-            
-            let type_upper = tx_type.to_uppercase();
-            if type_upper == "BUY" || type_upper == "ALIM" || type_upper == "ALIŞ" || type_upper == "A" || type_upper == "PURCHASE" {
-                queue.push((qty, price, asset_type));
-            } else { // satim
-                let mut remaining = qty;
-                while remaining > 0.0 && !queue.is_empty() {
-                    let first = &mut queue[0];
-                    if first.0 <= remaining {
-                        realized_pnl += (price - first.1) * first.0;
-                        remaining -= first.0;
-                        queue.remove(0);
-                    } else {
-                        realized_pnl += (price - first.1) * remaining;
-                        first.0 -= remaining;
-                        remaining = 0.0;
-                    }
-                }
-            }
-        }
+    pub async fn get_oversell_warnings(pool: &SqlitePool) -> Result<Vec<OversellWarning>, Box<dyn std::error::Error>> {
+        Ok(LotLedger::load(pool).await?.oversells)
+    }
 
+    async fn build_holdings(pool: &SqlitePool, ledger: &LotLedger) -> Result<Vec<Holding>, Box<dyn std::error::Error>> {
+        let ttm_start = Local::now().date_naive() - chrono::Duration::days(365);
         let mut holdings = Vec::new();
-        for (symbol, queue) in fifo_queues {
-            if queue.is_empty() { continue; }
-            
-            let total_qty: f64 = queue.iter().map(|item| item.0).sum();
+        for (symbol, position) in &ledger.positions {
+            let total_qty = position.quantity();
             if total_qty <= 0.0 { continue; }
 
-            let total_cost: f64 = queue.iter().map(|item| item.0 * item.1).sum();
+            // Remaining lot cost under the position's cost basis method (buy fees included)
+            let total_cost = position.cost();
             let avg_cost = total_cost / total_qty;
-            let asset_type = queue[0].2.clone();
+            let symbol = symbol.clone();
+            let asset_type = position.asset_type.clone();
 
-            let asset_data = sqlx::query("SELECT current_price, name FROM assets WHERE symbol = ?")
-                .bind(&symbol)
-                .fetch_optional(pool)
-                .await?;
-
-            let (curr_price, name) = match asset_data {
-                Some(r) => (r.get::<f64, _>("current_price"), r.get::<String, _>("name")),
-                None => (0.0, symbol.clone()),
-            };
+            let (curr_price, name) = Self::get_price_and_name(pool, &symbol).await?;
+            let (last_updated, price_source) = Self::get_price_status(pool, &symbol).await?;
+            let stale = asset_type != MANUAL_ASSET_TYPE && last_updated
+                .as_deref()
+                .and_then(|l| DateTime::parse_from_rfc3339(l).ok())
+                .is_none_or(|l| market_calendar::is_stale(&asset_type, l, market_calendar::istanbul_now()));
 
             let value = total_qty * curr_price;
             let pnl = value - total_cost;
             let pnl_pct = if total_cost > 0.0 { (pnl / total_cost) * 100.0 } else { 0.0 };
 
+            let dividends = ledger.dividends.iter().filter(|d| d.symbol == symbol);
+            let dividend_income: f64 = dividends.clone().map(|d| d.net).sum();
+            let ttm_dividends: f64 = dividends
+                .filter(|d| lots::parse_date(&d.payment_date).is_some_and(|pd| pd >= ttm_start))
+                .map(|d| d.net)
+                .sum();
+            let yield_on_cost_pct = if total_cost > 0.0 { (ttm_dividends / total_cost) * 100.0 } else { 0.0 };
+
             holdings.push(Holding {
                 symbol, name, asset_type, quantity: total_qty,
-                avg_cost, current_price: curr_price, value, pnl, pnl_pct
+                avg_cost, current_price: curr_price, value, pnl, pnl_pct,
+                dividend_income, yield_on_cost_pct,
+                last_updated, price_source, stale
+            });
+        }
+
+        Ok(holdings)
+    }
+
+    /// Every open lot per held symbol, optionally limited to one symbol.
+    pub async fn get_open_lots(pool: &SqlitePool, symbol: Option<String>) -> Result<Vec<SymbolLots>, Box<dyn std::error::Error>> {
+        let ledger = LotLedger::load(pool).await?;
+        let symbol = symbol.map(|s| s.to_uppercase());
+        let today = Local::now().date_naive();
+
+        let mut result = Vec::new();
+        for (sym, position) in ledger.positions {
+            if symbol.as_ref().is_some_and(|s| s != &sym) { continue; }
+            if position.lots.is_empty() { continue; }
+
+            let (curr_price, name) = Self::get_price_and_name(pool, &sym).await?;
+
+            let mut open_lots: Vec<OpenLot> = position.lots.iter().map(|lot| {
+                let cost = lot.quantity * lot.unit_cost;
+                let current_value = lot.quantity * curr_price;
+                let unrealized_pnl = current_value - cost;
+                OpenLot {
+                    transaction_id: lot.transaction_id,
+                    corporate_action_id: lot.corporate_action_id,
+                    buy_date: lot.buy_date.clone(),
+                    quantity: lot.quantity,
+                    cost_price: lot.unit_cost,
+                    current_value,
+                    unrealized_pnl,
+                    unrealized_pnl_pct: if cost > 0.0 { (unrealized_pnl / cost) * 100.0 } else { 0.0 },
+                    days_held: lots::parse_date(&lot.buy_date).map(|d| (today - d).num_days()),
+                }
+            }).collect();
+            open_lots.sort_by(|a, b| a.buy_date.cmp(&b.buy_date));
+
+            result.push(SymbolLots {
+                symbol: sym,
+                name,
+                asset_type: position.asset_type,
+                cost_basis_method: position.method,
+                current_price: curr_price,
+                lots: open_lots,
             });
         }
 
-        Ok((holdings, realized_pnl))
+        result.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        Ok(result)
+    }
+
+    async fn get_price_and_name(pool: &SqlitePool, symbol: &str) -> Result<(f64, String), Box<dyn std::error::Error>> {
+        let asset_data = sqlx::query("SELECT current_price, name FROM assets WHERE symbol = ?")
+            .bind(symbol)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(match asset_data {
+            Some(r) => (r.get::<f64, _>("current_price"), r.get::<String, _>("name")),
+            None => (0.0, symbol.to_string()),
+        })
+    }
+
+    async fn get_price_status(pool: &SqlitePool, symbol: &str) -> Result<(Option<String>, Option<String>), Box<dyn std::error::Error>> {
+        let row = sqlx::query_as::<_, (Option<String>, Option<String>)>("SELECT last_updated, price_source FROM assets WHERE symbol = ?")
+            .bind(symbol)
+            .fetch_optional(pool)
+            .await?;
+        Ok(row.unwrap_or((None, None)))
     }
 
     pub async fn get_realized_pnl_in_range(pool: &SqlitePool, start_date: Option<String>, end_date: Option<String>) -> Result<f64, Box<dyn std::error::Error>> {
-        let rows = sqlx::query(
-            "SELECT id, transaction_date, asset_type, symbol, transaction_type, quantity, price 
-             FROM transactions 
-             ORDER BY transaction_date ASC, created_at ASC"
-        )
-        .fetch_all(pool)
-        .await?;
+        let range = Self::date_range(start_date, end_date)?;
+        let ledger = LotLedger::load(pool).await?;
 
-        let mut fifo_queues: HashMap<String, Vec<(f64, f64)>> = HashMap::new(); // symbol -> Vec<(qty, price)>
-        let mut realized_pnl = 0.0;
-
-        for row in rows {
-            let symbol: String = row.get("symbol");
-            let tx_date: String = row.get("transaction_date");
-            let tx_type: String = row.get("transaction_type");
-            let qty: f64 = row.get("quantity");
-            let price: f64 = row.get("price");
-
-            let symbol = symbol.to_uppercase();
-            let queue = fifo_queues.entry(symbol.clone()).or_insert(Vec::new());
-            
-            let type_upper = tx_type.to_uppercase();
-            if type_upper == "BUY" || type_upper == "ALIM" || type_upper == "ALIŞ" || type_upper == "A" || type_upper == "PURCHASE" {
-                queue.push((qty, price));
-            } else { // satim
-                let mut remaining = qty;
-                
-                // Check if this sell falls within the range
-                let is_in_range = {
-                    let mut ok = true;
-                    if let Some(start) = &start_date {
-                        if &tx_date < start { ok = false; }
-                    }
-                    if let Some(end) = &end_date {
-                        if &tx_date > end { ok = false; }
-                    }
-                    ok
-                };
+        let realized_pnl = ledger.closed.iter()
+            .filter(|c| Self::is_in_range(&c.sell_date, range))
+            .map(|c| c.realized_pnl)
+            .sum();
+
+        Ok(realized_pnl)
+    }
+
+    /// Matched BUY/SELL lots, newest sell first, with win rate and average gain/loss over the filtered set.
+    pub async fn get_closed_trades(
+        pool: &SqlitePool,
+        symbol: Option<String>,
+        asset_type: Option<String>,
+        start_date: Option<String>,
+        end_date: Option<String>,
+    ) -> Result<ClosedTradeReport, Box<dyn std::error::Error>> {
+        let range = Self::date_range(start_date, end_date)?;
+        let ledger = LotLedger::load(pool).await?;
+        let symbol = symbol.map(|s| s.to_uppercase());
+
+        let mut trades: Vec<ClosedLot> = ledger.closed.into_iter()
+            .filter(|c| symbol.as_ref().is_none_or(|s| s == &c.symbol))
+            .filter(|c| asset_type.as_ref().is_none_or(|t| t == &c.asset_type))
+            .filter(|c| Self::is_in_range(&c.sell_date, range))
+            .collect();
+        trades.sort_by(|a, b| b.sell_date.cmp(&a.sell_date).then(b.sell_transaction_id.cmp(&a.sell_transaction_id)));
+
+        let stats = Self::closed_trade_stats(&trades);
+        Ok(ClosedTradeReport { trades, stats })
+    }
 
-                while remaining > 0.0 && !queue.is_empty() {
-                    let first = &mut queue[0];
-                    if first.0 <= remaining {
-                        if is_in_range {
-                            realized_pnl += (price - first.1) * first.0;
-                        }
-                        remaining -= first.0;
-                        queue.remove(0);
-                    } else {
-                        if is_in_range {
-                            realized_pnl += (price - first.1) * remaining;
-                        }
-                        first.0 -= remaining;
-                        remaining = 0.0;
+    /// Dividend income grouped by payment year and by symbol, optionally limited to one year/symbol.
+    pub async fn get_dividend_report(pool: &SqlitePool, year: Option<i32>, symbol: Option<String>) -> Result<DividendReport, Box<dyn std::error::Error>> {
+        let ledger = LotLedger::load(pool).await?;
+        let symbol = symbol.map(|s| s.to_uppercase());
+        let year_of = |d: &DividendPayment| lots::parse_date(&d.payment_date).map(|pd| pd.year());
+
+        let mut payments: Vec<DividendPayment> = ledger.dividends.into_iter()
+            .filter(|d| symbol.as_ref().is_none_or(|s| s == &d.symbol))
+            .filter(|d| year.is_none() || year_of(d) == year)
+            .collect();
+        payments.sort_by(|a, b| b.payment_date.cmp(&a.payment_date));
+
+        let mut by_year: Vec<DividendTotals> = Vec::new();
+        let mut by_symbol: Vec<DividendTotals> = Vec::new();
+        let mut total = DividendTotals { key: "TOTAL".to_string(), ..Default::default() };
+        for d in &payments {
+            let year_key = year_of(d).map(|y| y.to_string()).unwrap_or_else(|| "-".to_string());
+            for (groups, key) in [(&mut by_year, year_key), (&mut by_symbol, d.symbol.clone())] {
+                let idx = match groups.iter().position(|g| g.key == key) {
+                    Some(i) => i,
+                    None => {
+                        groups.push(DividendTotals { key, ..Default::default() });
+                        groups.len() - 1
                     }
-                }
+                };
+                Self::add_dividend(&mut groups[idx], d);
             }
+            Self::add_dividend(&mut total, d);
         }
+        by_year.sort_by(|a, b| b.key.cmp(&a.key));
+        by_symbol.sort_by(|a, b| b.net.partial_cmp(&a.net).unwrap_or(std::cmp::Ordering::Equal));
 
-        Ok(realized_pnl)
+        Ok(DividendReport { by_year, by_symbol, payments, total })
+    }
+
+    fn add_dividend(totals: &mut DividendTotals, d: &DividendPayment) {
+        totals.payments += 1;
+        totals.gross += d.gross;
+        totals.withholding_tax += d.withholding_tax;
+        totals.net += d.net;
+    }
+
+    fn closed_trade_stats(trades: &[ClosedLot]) -> ClosedTradeStats {
+        if trades.is_empty() {
+            return ClosedTradeStats::default();
+        }
+
+        let gains: Vec<f64> = trades.iter().map(|t| t.realized_pnl).filter(|p| *p > 0.0).collect();
+        let losses: Vec<f64> = trades.iter().map(|t| t.realized_pnl).filter(|p| *p < 0.0).collect();
+        let holding: Vec<i64> = trades.iter().filter_map(|t| t.holding_days).collect();
+
+        ClosedTradeStats {
+            trade_count: trades.len(),
+            win_count: gains.len(),
+            loss_count: losses.len(),
+            win_rate: (gains.len() as f64 / trades.len() as f64) * 100.0,
+            avg_holding_days: if holding.is_empty() { 0.0 } else { holding.iter().sum::<i64>() as f64 / holding.len() as f64 },
+            avg_gain: if gains.is_empty() { 0.0 } else { gains.iter().sum::<f64>() / gains.len() as f64 },
+            avg_loss: if losses.is_empty() { 0.0 } else { losses.iter().sum::<f64>() / losses.len() as f64 },
+            total_realized_pnl: trades.iter().map(|t| t.realized_pnl).sum(),
+        }
+    }
+
+    // Parses the optional range bounds; any accepted date layout works
+    fn date_range(start_date: Option<String>, end_date: Option<String>) -> Result<(Option<NaiveDate>, Option<NaiveDate>), String> {
+        let parse = |value: Option<String>, label: &str| match value.as_deref().map(str::trim) {
+            None | Some("") => Ok(None),
+            Some(v) => lots::parse_date(v).map(Some).ok_or_else(|| format!("Invalid {} date: {}", label, v)),
+        };
+        Ok((parse(start_date, "start")?, parse(end_date, "end")?))
+    }
+
+    // Check if a date falls within the (inclusive, optional) range; unreadable dates only match an open range
+    fn is_in_range(date: &str, (start, end): (Option<NaiveDate>, Option<NaiveDate>)) -> bool {
+        if start.is_none() && end.is_none() {
+            return true;
+        }
+        lots::parse_date(date).is_some_and(|d| start.is_none_or(|s| d >= s) && end.is_none_or(|e| d <= e))
     }
 
     pub async fn get_portfolio_summary(pool: &SqlitePool) -> Result<PortfolioSummary, Box<dyn std::error::Error>> {
-        let (holdings, realized_pnl) = Self::get_current_holdings(pool).await?;
+        let ledger = LotLedger::load(pool).await?;
+        let holdings = Self::build_holdings(pool, &ledger).await?;
+        let realized_pnl = ledger.realized_pnl();
+        let dividend_income = ledger.dividend_income();
         let total_value: f64 = holdings.iter().map(|h| h.value).sum();
         let total_pnl: f64 = holdings.iter().map(|h| h.pnl).sum();
         let total_cost: f64 = holdings.iter().map(|h| h.quantity * h.avg_cost).sum();
@@ -198,6 +366,8 @@ impl CalculatorService {
         let total_value_usd = if usd_rate > 0.0 { total_value / usd_rate } else { 0.0 };
         
         let roi_pct = if total_cost > 0.0 { (total_pnl / total_cost) * 100.0 } else { 0.0 };
+        let stale_value: f64 = holdings.iter().filter(|h| h.stale).map(|h| h.value).sum();
+        let stale_value_pct = if total_value > 0.0 { (stale_value / total_value) * 100.0 } else { 0.0 };
 
         let mut top = "-".to_string();
         let mut worst = "-".to_string();
@@ -216,20 +386,36 @@ impl CalculatorService {
         let last_updated = last_update_row
             .and_then(|r| r.get::<Option<String>, _>("last_updated"));
 
+        // Total commissions/BSMV paid on trades, already reflected in cost basis and realized PnL.
+        // Fees on dividends are netted out of dividend income instead, so they aren't counted twice
+        let total_fees: f64 = sqlx::query_scalar::<_, f64>(
+            "SELECT COALESCE(SUM(fees), 0.0) FROM transactions
+             WHERE transaction_type != 'DIVIDEND' AND COALESCE(is_dividend, 0) = 0",
+        )
+            .fetch_one(pool)
+            .await?;
+
         // Save Snapshot
         Self::save_snapshot(pool, total_value, total_value_usd).await?;
 
         // Calculate Performance Changes
-        let (daily, daily_pct) = Self::get_performance_change(pool, total_value, 1).await?;
-        let (weekly, weekly_pct) = Self::get_performance_change(pool, total_value, 7).await?;
-        let (monthly, monthly_pct) = Self::get_performance_change(pool, total_value, 30).await?;
+        // Daily change is measured against the close before the latest business day, so weekends
+        // and holidays keep showing the last session's move instead of dropping to zero
+        let today = Local::now().date_naive();
+        let previous_close = market_calendar::previous_business_day(market_calendar::last_business_day(today));
+        let (daily, daily_pct) = Self::get_performance_change(pool, total_value, previous_close).await?;
+        let (weekly, weekly_pct) = Self::get_performance_change(pool, total_value, today - chrono::Duration::days(7)).await?;
+        let (monthly, monthly_pct) = Self::get_performance_change(pool, total_value, today - chrono::Duration::days(30)).await?;
 
         Ok(PortfolioSummary {
             total_value, total_value_usd, unrealized_pnl: total_pnl,
-            realized_pnl, total_return: total_pnl + realized_pnl,
+            realized_pnl, dividend_income,
+            total_return: total_pnl + realized_pnl + dividend_income,
             roi_pct, holdings_count: holdings.len(),
             top_performer: top, worst_performer: worst,
-            last_updated,
+            last_updated, total_fees,
+            oversell_warnings: ledger.oversells,
+            stale_value, stale_value_pct,
             daily_change: daily, daily_change_pct: daily_pct,
             weekly_change: weekly, weekly_change_pct: weekly_pct,
             monthly_change: monthly, monthly_change_pct: monthly_pct
@@ -254,10 +440,12 @@ impl CalculatorService {
         Ok(())
     }
 
-    // Helper to get change vs N days ago
-    async fn get_performance_change(pool: &SqlitePool, current_val: f64, days_ago: i64) -> Result<(f64, f64), Box<dyn std::error::Error>> {
-        let date_query = format!("SELECT total_value_tl FROM portfolio_snapshots WHERE snapshot_date <= date('now', '-{} days') ORDER BY snapshot_date DESC LIMIT 1", days_ago);
-        let row = sqlx::query(&date_query).fetch_optional(pool).await?;
+    // Helper to get change vs the last snapshot on or before a date
+    async fn get_performance_change(pool: &SqlitePool, current_val: f64, since: NaiveDate) -> Result<(f64, f64), Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT total_value_tl FROM portfolio_snapshots WHERE snapshot_date <= ? ORDER BY snapshot_date DESC LIMIT 1")
+            .bind(since.format("%Y-%m-%d").to_string())
+            .fetch_optional(pool)
+            .await?;
         if let Some(r) = row {
             let old_val: f64 = r.get("total_value_tl");
             if old_val > 0.0 {
@@ -304,3 +492,167 @@ impl CalculatorService {
         Ok((0.0, 0.0))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn memory_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::create_schema(&pool).await.unwrap();
+        pool
+    }
+
+    // (date, asset type, symbol, transaction type, quantity, price, fees)
+    async fn insert_all(pool: &SqlitePool, rows: &[(&str, &str, &str, &str, f64, f64, f64)]) {
+        for &(date, asset_type, symbol, kind, quantity, price, fees) in rows {
+            sqlx::query(
+                "INSERT INTO transactions (transaction_date, asset_type, symbol, transaction_type, quantity, price, total_value, fees)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(date)
+            .bind(asset_type)
+            .bind(symbol)
+            .bind(kind)
+            .bind(quantity)
+            .bind(price)
+            .bind(quantity * price)
+            .bind(fees)
+            .execute(pool)
+            .await
+            .unwrap();
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    // A win on THYAO (+480 over 60 days), a loss on GARAN (-200 over 10) and a partial fund sell (+50 over 152)
+    async fn trade_pool() -> SqlitePool {
+        let pool = memory_pool().await;
+        insert_all(&pool, &[
+            ("2024-01-01", "hisse", "THYAO", "BUY", 10.0, 100.0, 10.0),
+            ("2024-01-10", "fon", "AAK", "BUY", 100.0, 2.0, 0.0),
+            ("2024-02-01", "hisse", "GARAN", "BUY", 20.0, 50.0, 0.0),
+            ("2024-02-11", "hisse", "GARAN", "SELL", 20.0, 40.0, 0.0),
+            ("2024-03-01", "hisse", "THYAO", "SELL", 10.0, 150.0, 10.0),
+            ("2024-04-01", "hisse", "THYAO", "DIVIDEND", 10.0, 2.0, 0.0),
+            ("2024-06-10", "fon", "AAK", "SELL", 50.0, 3.0, 0.0),
+        ])
+        .await;
+        pool
+    }
+
+    #[tokio::test]
+    async fn closed_trades_report_pnl_holding_and_win_rate() {
+        let pool = trade_pool().await;
+        let report = CalculatorService::get_closed_trades(&pool, None, None, None, None).await.unwrap();
+
+        let trades: Vec<_> = report.trades.iter().map(|t| (t.symbol.as_str(), t.holding_days, t.quantity)).collect();
+        assert_eq!(trades, vec![("AAK", Some(152), 50.0), ("THYAO", Some(60), 10.0), ("GARAN", Some(10), 20.0)]);
+        // Fees are in the unit prices: 101 a share in, 149 a share out
+        assert_close(report.trades[1].buy_price, 101.0);
+        assert_close(report.trades[1].sell_price, 149.0);
+        assert_close(report.trades[1].realized_pnl, 480.0);
+        assert_close(report.trades[2].realized_pnl, -200.0);
+
+        let stats = &report.stats;
+        assert_eq!((stats.trade_count, stats.win_count, stats.loss_count), (3, 2, 1));
+        assert_close(stats.win_rate, 200.0 / 3.0);
+        assert_close(stats.avg_holding_days, 74.0);
+        assert_close(stats.avg_gain, 265.0);
+        assert_close(stats.avg_loss, -200.0);
+        assert_close(stats.total_realized_pnl, 330.0);
+    }
+
+    #[tokio::test]
+    async fn closed_trades_filter_by_symbol_type_and_sell_date() {
+        let pool = trade_pool().await;
+
+        let thyao = CalculatorService::get_closed_trades(&pool, Some("thyao".to_string()), None, None, None).await.unwrap();
+        assert_eq!(thyao.trades.len(), 1);
+        assert_close(thyao.stats.win_rate, 100.0);
+        assert_close(thyao.stats.avg_loss, 0.0);
+
+        let funds = CalculatorService::get_closed_trades(&pool, None, Some("fon".to_string()), None, None).await.unwrap();
+        assert_eq!(funds.trades[0].symbol, "AAK");
+
+        // Both bounds are inclusive and take any accepted date layout
+        let march = CalculatorService::get_closed_trades(&pool, None, None, Some("2024-02-12".to_string()), Some("01.03.2024".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(march.trades.len(), 1);
+        assert_eq!(march.trades[0].symbol, "THYAO");
+
+        let none = CalculatorService::get_closed_trades(&pool, Some("ASELS".to_string()), None, None, None).await.unwrap();
+        assert!(none.trades.is_empty());
+        assert_eq!((none.stats.trade_count, none.stats.win_rate), (0, 0.0));
+
+        assert!(CalculatorService::get_closed_trades(&pool, None, None, Some("someday".to_string()), None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn holdings_flag_prices_that_missed_their_refresh() {
+        let pool = memory_pool().await;
+        insert_all(&pool, &[
+            ("2024-01-02", "hisse", "THYAO", "BUY", 1.0, 100.0, 0.0),
+            ("2024-01-02", "hisse", "GARAN", "BUY", 1.0, 100.0, 0.0),
+            ("2024-01-02", "hisse", "ASELS", "BUY", 1.0, 100.0, 0.0),
+            ("2024-01-02", "kripto", "BTC-C", "BUY", 1.0, 100.0, 0.0),
+            ("2024-01-02", "fon", "AAK", "BUY", 1.0, 100.0, 0.0),
+            ("2024-01-02", "manuel", "ARSA", "BUY", 1.0, 100.0, 0.0),
+        ])
+        .await;
+        let now = Local::now();
+        for (symbol, asset_type, last_updated) in [
+            ("THYAO", "hisse", now.to_rfc3339()),
+            ("GARAN", "hisse", "2020-01-02T10:00:00+03:00".to_string()),
+            ("BTC-C", "kripto", (now - chrono::Duration::hours(30)).to_rfc3339()),
+            ("AAK", "fon", "not a timestamp".to_string()),
+            // Valued by the user: an old valuation is never stale
+            ("ARSA", "manuel", "2020-01-02T10:00:00+03:00".to_string()),
+        ] {
+            sqlx::query("INSERT INTO assets (symbol, name, asset_type, current_price, last_updated) VALUES (?, ?, ?, 100, ?)")
+                .bind(symbol)
+                .bind(symbol)
+                .bind(asset_type)
+                .bind(last_updated)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        let ledger = LotLedger::load(&pool).await.unwrap();
+        let mut stale: Vec<(String, bool)> = CalculatorService::build_holdings(&pool, &ledger)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|h| (h.symbol, h.stale))
+            .collect();
+        stale.sort();
+        let expected = [("AAK", true), ("ARSA", false), ("ASELS", true), ("BTC-C", true), ("GARAN", true), ("THYAO", false)];
+        assert_eq!(stale, expected.map(|(s, flag)| (s.to_string(), flag)));
+    }
+
+    #[tokio::test]
+    async fn total_fees_leave_out_dividend_fees() {
+        let pool = memory_pool().await;
+        insert_all(&pool, &[
+            ("2024-01-01", "hisse", "THYAO", "BUY", 10.0, 100.0, 10.0),
+            ("2024-03-01", "hisse", "THYAO", "SELL", 5.0, 150.0, 7.5),
+            ("2024-04-01", "hisse", "THYAO", "DIVIDEND", 5.0, 2.0, 1.0),
+        ])
+        .await;
+
+        let summary = CalculatorService::get_portfolio_summary(&pool).await.unwrap();
+        assert_close(summary.total_fees, 17.5);
+        // The dividend fee is already out of dividend income: 10 gross - 1 fee
+        assert_close(summary.dividend_income, 9.0);
+    }
+}
@@ -0,0 +1,293 @@
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::lots;
+
+/// Split, bonus issue (bedelsiz) or rights issue (bedelli) on a symbol.
+///
+/// `ratio` semantics by `action_type`:
+/// - `split`: shares after per share before (2.0 for 1:2, 0.1 for a 10:1 reverse split)
+/// - `bonus`: free shares per share held (1.0 = %100 bedelsiz)
+/// - `rights`: subscribed shares per share held (0.5 = %50 bedelli), paid at `subscription_price`
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct CorporateAction {
+    pub id: i64,
+    pub symbol: String,
+    pub action_type: String,
+    pub ex_date: String,
+    pub ratio: f64,
+    pub subscription_price: Option<f64>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewCorporateAction {
+    pub symbol: String,
+    pub action_type: String,
+    pub ex_date: String,
+    pub ratio: f64,
+    pub subscription_price: Option<f64>,
+    pub notes: Option<String>,
+}
+
+impl CorporateAction {
+    pub const SPLIT: &'static str = "split";
+    pub const BONUS: &'static str = "bonus";
+    pub const RIGHTS: &'static str = "rights";
+
+    /// Share count multiplier for actions that don't bring in new money (splits and bonus issues).
+    pub fn quantity_multiplier(&self) -> Option<f64> {
+        match self.action_type.as_str() {
+            Self::SPLIT => Some(self.ratio),
+            Self::BONUS => Some(1.0 + self.ratio),
+            _ => None,
+        }
+    }
+
+    pub fn is_rights(&self) -> bool {
+        self.action_type == Self::RIGHTS
+    }
+
+    pub async fn load_all(pool: &SqlitePool) -> Result<Vec<CorporateAction>, sqlx::Error> {
+        sqlx::query_as::<_, CorporateAction>(
+            "SELECT id, symbol, action_type, ex_date, ratio, subscription_price, notes
+             FROM corporate_actions
+             ORDER BY ex_date ASC, id ASC",
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+/// Product of split/bonus multipliers with an ex-date after `date`: converts a quantity on
+/// `date` into today's share terms (and divides prices the same way). An unreadable date is
+/// left unadjusted.
+pub fn adjustment_factor(actions: &[CorporateAction], symbol: &str, date: &str) -> f64 {
+    let Some(date) = lots::parse_date(date) else { return 1.0 };
+    actions
+        .iter()
+        .filter(|a| a.symbol.eq_ignore_ascii_case(symbol))
+        .filter(|a| lots::parse_date(&a.ex_date).is_some_and(|ex| ex > date))
+        .filter_map(|a| a.quantity_multiplier())
+        .product()
+}
+
+pub struct CorporateActionService;
+
+impl CorporateActionService {
+    pub async fn list(pool: &SqlitePool, symbol: Option<String>) -> Result<Vec<CorporateAction>, Box<dyn std::error::Error>> {
+        let symbol = symbol.map(|s| s.to_uppercase());
+        Ok(CorporateAction::load_all(pool)
+            .await?
+            .into_iter()
+            .filter(|a| symbol.as_ref().is_none_or(|s| s == &a.symbol))
+            .collect())
+    }
+
+    /// Stores the action and back-adjusts stored price history for splits and bonus issues.
+    pub async fn add(pool: &SqlitePool, action: NewCorporateAction) -> Result<i64, Box<dyn std::error::Error>> {
+        let action_type = action.action_type.trim().to_lowercase();
+        if ![CorporateAction::SPLIT, CorporateAction::BONUS, CorporateAction::RIGHTS].contains(&action_type.as_str()) {
+            return Err(format!("Unknown corporate action type: {}", action.action_type).into());
+        }
+        if action.ratio <= 0.0 {
+            return Err("Corporate action ratio must be positive".into());
+        }
+        if action_type == CorporateAction::RIGHTS && action.subscription_price.is_none_or(|p| p < 0.0) {
+            return Err("Rights issue requires a subscription price".into());
+        }
+        let ex_date = lots::parse_date(&action.ex_date)
+            .ok_or_else(|| format!("Invalid ex-date: {}", action.ex_date))?
+            .format("%Y-%m-%d")
+            .to_string();
+        let stored = CorporateAction {
+            id: 0,
+            symbol: action.symbol.trim().to_uppercase(),
+            action_type,
+            ex_date,
+            ratio: action.ratio,
+            subscription_price: action.subscription_price,
+            notes: action.notes,
+        };
+        let multiplier = stored.quantity_multiplier();
+
+        let mut tx = pool.begin().await?;
+        let result = sqlx::query(
+            "INSERT INTO corporate_actions (symbol, action_type, ex_date, ratio, subscription_price, notes, history_adjusted)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&stored.symbol)
+        .bind(&stored.action_type)
+        .bind(&stored.ex_date)
+        .bind(stored.ratio)
+        .bind(stored.subscription_price)
+        .bind(&stored.notes)
+        .bind(multiplier.is_some())
+        .execute(&mut *tx)
+        .await?;
+
+        if let Some(m) = multiplier {
+            // Pre-ex closes are restated in post-action shares so charts don't show a fake crash
+            sqlx::query("UPDATE asset_price_history SET price = price / ? WHERE symbol = ? AND snapshot_date < ?")
+                .bind(m)
+                .bind(&stored.symbol)
+                .bind(&stored.ex_date)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Deletes the action and reverts its price history adjustment.
+    pub async fn delete(pool: &SqlitePool, id: i64) -> Result<(), Box<dyn std::error::Error>> {
+        let action = sqlx::query_as::<_, CorporateAction>(
+            "SELECT id, symbol, action_type, ex_date, ratio, subscription_price, notes FROM corporate_actions WHERE id = ?",
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+        let Some(action) = action else { return Ok(()) };
+        let history_adjusted: bool = sqlx::query_scalar("SELECT history_adjusted FROM corporate_actions WHERE id = ?")
+            .bind(id)
+            .fetch_one(pool)
+            .await?;
+
+        let mut tx = pool.begin().await?;
+        if let Some(m) = action.quantity_multiplier().filter(|_| history_adjusted) {
+            sqlx::query("UPDATE asset_price_history SET price = price * ? WHERE symbol = ? AND snapshot_date < ?")
+                .bind(m)
+                .bind(&action.symbol)
+                .bind(&action.ex_date)
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query("DELETE FROM corporate_actions WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn memory_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::create_schema(&pool).await.unwrap();
+        pool
+    }
+
+    fn action(symbol: &str, action_type: &str, ex_date: &str, ratio: f64) -> CorporateAction {
+        CorporateAction {
+            id: 0,
+            symbol: symbol.to_string(),
+            action_type: action_type.to_string(),
+            ex_date: ex_date.to_string(),
+            ratio,
+            subscription_price: Some(10.0),
+            notes: None,
+        }
+    }
+
+    fn new_action(action_type: &str, ex_date: &str, ratio: f64, subscription_price: Option<f64>) -> NewCorporateAction {
+        NewCorporateAction {
+            symbol: "thyao ".to_string(),
+            action_type: action_type.to_string(),
+            ex_date: ex_date.to_string(),
+            ratio,
+            subscription_price,
+            notes: None,
+        }
+    }
+
+    async fn history(pool: &SqlitePool) -> Vec<(String, String, f64)> {
+        sqlx::query_as("SELECT symbol, snapshot_date, price FROM asset_price_history ORDER BY symbol, snapshot_date")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn adjustment_factor_compares_dates_not_strings() {
+        let actions = [
+            action("THYAO", "split", "2024-03-01", 2.0),
+            action("THYAO", "rights", "2024-04-01", 0.5),
+            action("THYAO", "bonus", "2024-06-01", 1.0),
+            action("GARAN", "split", "2024-03-01", 10.0),
+        ];
+        assert_eq!(adjustment_factor(&actions, "thyao", "2024-02-29"), 4.0);
+        // On the ex-date the split has already happened
+        assert_eq!(adjustment_factor(&actions, "THYAO", "2024-03-01"), 2.0);
+        assert_eq!(adjustment_factor(&actions, "THYAO", "01.03.2024"), 2.0);
+        assert_eq!(adjustment_factor(&actions, "THYAO", "2024-03-01T10:30:00"), 2.0);
+        assert_eq!(adjustment_factor(&actions, "THYAO", "29/02/2024"), 4.0);
+        assert_eq!(adjustment_factor(&actions, "THYAO", "2024-07-01"), 1.0);
+        assert_eq!(adjustment_factor(&actions, "ASELS", "2024-01-01"), 1.0);
+        assert_eq!(adjustment_factor(&actions, "THYAO", "not a date"), 1.0);
+    }
+
+    #[tokio::test]
+    async fn price_history_is_rescaled_on_add_and_restored_on_delete() {
+        let pool = memory_pool().await;
+        for (symbol, date, price) in [("THYAO", "2024-02-29", 200.0), ("THYAO", "2024-03-01", 100.0), ("GARAN", "2024-02-29", 50.0)] {
+            sqlx::query("INSERT INTO asset_price_history (symbol, price, snapshot_date) VALUES (?, ?, ?)")
+                .bind(symbol)
+                .bind(price)
+                .bind(date)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        let original = history(&pool).await;
+
+        let split = CorporateActionService::add(&pool, new_action("Split", "01.03.2024", 2.0, None)).await.unwrap();
+        let stored = CorporateActionService::list(&pool, Some("thyao".to_string())).await.unwrap();
+        assert_eq!((stored[0].symbol.as_str(), stored[0].action_type.as_str(), stored[0].ex_date.as_str()), ("THYAO", "split", "2024-03-01"));
+        // Only closes before the ex-date are restated
+        assert_eq!(
+            history(&pool).await,
+            vec![
+                ("GARAN".to_string(), "2024-02-29".to_string(), 50.0),
+                ("THYAO".to_string(), "2024-02-29".to_string(), 100.0),
+                ("THYAO".to_string(), "2024-03-01".to_string(), 100.0),
+            ]
+        );
+
+        // A rights issue brings in new money and leaves history alone
+        let rights = CorporateActionService::add(&pool, new_action("rights", "2024-03-15", 0.5, Some(10.0))).await.unwrap();
+        let adjusted: bool = sqlx::query_scalar("SELECT history_adjusted FROM corporate_actions WHERE id = ?")
+            .bind(rights)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert!(!adjusted);
+        CorporateActionService::delete(&pool, rights).await.unwrap();
+
+        CorporateActionService::delete(&pool, split).await.unwrap();
+        assert_eq!(history(&pool).await, original);
+        assert!(CorporateActionService::list(&pool, None).await.unwrap().is_empty());
+        // Deleting an unknown id is a no-op
+        CorporateActionService::delete(&pool, split).await.unwrap();
+        assert_eq!(history(&pool).await, original);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_actions() {
+        let pool = memory_pool().await;
+        assert!(CorporateActionService::add(&pool, new_action("merger", "2024-03-01", 2.0, None)).await.is_err());
+        assert!(CorporateActionService::add(&pool, new_action("split", "2024-03-01", 0.0, None)).await.is_err());
+        assert!(CorporateActionService::add(&pool, new_action("rights", "2024-03-01", 0.5, None)).await.is_err());
+        assert!(CorporateActionService::add(&pool, new_action("split", "someday", 2.0, None)).await.is_err());
+        assert!(CorporateActionService::list(&pool, None).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,332 @@
+use encoding_rs::WINDOWS_1254;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::path::Path;
+
+use crate::importer::{ImportService, ImportStrategy, ImportedTransaction, TransactionImportReport};
+use crate::providers;
+
+/// CSV columns, in order; the fields of `importer::TransactionExport`.
+pub const CSV_COLUMNS: [&str; 12] = [
+    "transaction_date",
+    "asset_type",
+    "symbol",
+    "transaction_type",
+    "quantity",
+    "price",
+    "total_value",
+    "fees",
+    "currency",
+    "broker",
+    "notes",
+    "withholding_tax",
+];
+const REQUIRED_COLUMNS: [&str; 5] = ["transaction_date", "symbol", "transaction_type", "quantity", "price"];
+const NUMERIC_COLUMNS: [&str; 5] = ["quantity", "price", "total_value", "fees", "withholding_tax"];
+
+// Header and (line number, cells) of each data record
+pub(crate) type CsvRows = (Vec<String>, Vec<(usize, Vec<String>)>);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CsvEncoding {
+    #[default]
+    #[serde(rename = "utf-8", alias = "utf8")]
+    Utf8,
+    // What Turkish-locale Excel saves "CSV (virgülle ayrılmış)" as
+    #[serde(rename = "windows-1254", alias = "cp1254", alias = "windows1254")]
+    Windows1254,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    pub encoding: CsvEncoding,
+    pub decimal_comma: bool, // export only; imports accept both separators
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions { delimiter: ',', encoding: CsvEncoding::Utf8, decimal_comma: false }
+    }
+}
+
+impl CsvOptions {
+    pub(crate) fn delimiter_byte(&self) -> Result<u8, String> {
+        u8::try_from(self.delimiter)
+            .ok()
+            .filter(|b| b.is_ascii() && !matches!(b, b'"' | b'\n' | b'\r'))
+            .ok_or_else(|| format!("Unsupported CSV delimiter: {:?}", self.delimiter))
+    }
+}
+
+pub struct CsvService;
+
+impl CsvService {
+    /// Writes every transaction to `path`, one row per transaction under a `CSV_COLUMNS` header.
+    /// UTF-8 files start with a BOM so Excel detects the encoding. Returns the number of rows written.
+    pub async fn export_transactions(pool: &SqlitePool, path: &Path, options: &CsvOptions) -> Result<usize, Box<dyn std::error::Error>> {
+        let rows = sqlx::query_as::<_, ImportedTransaction>(
+            "SELECT transaction_date, asset_type, symbol, transaction_type, quantity, price, total_value, fees, currency,
+                    broker, notes, is_dividend, withholding_tax
+             FROM transactions ORDER BY transaction_date, id",
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut writer = csv::WriterBuilder::new().delimiter(options.delimiter_byte()?).from_writer(Vec::new());
+        writer.write_record(CSV_COLUMNS)?;
+        let number = |v: f64| {
+            let s = v.to_string();
+            if options.decimal_comma { s.replace('.', ",") } else { s }
+        };
+        for t in &rows {
+            writer.write_record([
+                t.transaction_date.clone(),
+                t.asset_type.clone(),
+                t.symbol.clone(),
+                crate::db_transaction_type(crate::ui_transaction_type(&t.transaction_type)).to_string(),
+                number(t.quantity),
+                number(t.price),
+                number(t.total_value.unwrap_or(t.quantity * t.price)),
+                number(t.fees.unwrap_or(0.0)),
+                t.currency.clone().unwrap_or_else(|| "TRY".to_string()),
+                t.broker.clone().unwrap_or_default(),
+                t.notes.clone().unwrap_or_default(),
+                number(t.withholding_tax.unwrap_or(0.0)),
+            ])?;
+        }
+        let text = String::from_utf8(writer.into_inner()?)?;
+
+        let bytes = match options.encoding {
+            CsvEncoding::Utf8 => ["\u{feff}", &text].concat().into_bytes(),
+            // Characters outside the code page become numeric character references
+            CsvEncoding::Windows1254 => WINDOWS_1254.encode(&text).0.into_owned(),
+        };
+        std::fs::write(path, bytes)?;
+        Ok(rows.len())
+    }
+
+    /// Reads a CSV with a `CSV_COLUMNS` header (any order, extra columns ignored) and imports it
+    /// like a JSON backup. Numbers may use a decimal comma ("1.234,56"); dates any accepted layout.
+    pub async fn import_transactions(
+        pool: &SqlitePool,
+        path: &Path,
+        options: &CsvOptions,
+        strategy: ImportStrategy,
+        dry_run: bool,
+        skip_invalid: bool,
+        backup_dir: &Path,
+    ) -> Result<TransactionImportReport, Box<dyn std::error::Error>> {
+        let text = Self::decode(&std::fs::read(path)?, options.encoding)?;
+        let rows = Self::parse_records(&text, options)?;
+        ImportService::import_records(pool, rows, strategy, dry_run, skip_invalid, backup_dir).await
+    }
+
+    pub(crate) fn decode(bytes: &[u8], encoding: CsvEncoding) -> Result<String, String> {
+        match encoding {
+            CsvEncoding::Utf8 => std::str::from_utf8(bytes)
+                .map(|s| s.trim_start_matches('\u{feff}').to_string())
+                .map_err(|_| "Dosya UTF-8 değil; kodlama olarak Windows-1254 seçip tekrar deneyin".to_string()),
+            CsvEncoding::Windows1254 => Ok(WINDOWS_1254.decode_without_bom_handling(bytes).0.into_owned()),
+        }
+    }
+
+    /// Maps each CSV record onto `TransactionRecord` field names, tagged with its line number.
+    /// Empty cells are left out so optional fields fall back to their defaults.
+    fn parse_records(text: &str, options: &CsvOptions) -> Result<Vec<(usize, serde_json::Value)>, Box<dyn std::error::Error>> {
+        let (headers, records) = Self::read_rows(text, options.delimiter_byte()?)?;
+        let headers: Vec<String> = headers.iter().map(|h| h.to_lowercase()).collect();
+        let missing: Vec<&str> = REQUIRED_COLUMNS.into_iter().filter(|c| !headers.iter().any(|h| h == c)).collect();
+        if !missing.is_empty() {
+            return Err(format!("CSV başlığında eksik kolon: {}", missing.join(", ")).into());
+        }
+
+        let mut rows = Vec::new();
+        for (line, record) in records {
+            let mut row = serde_json::Map::new();
+            for (header, cell) in headers.iter().zip(&record) {
+                if cell.is_empty() || !CSV_COLUMNS.contains(&header.as_str()) {
+                    continue;
+                }
+                let key = match header.as_str() {
+                    "transaction_date" => "date",
+                    "transaction_type" => "type",
+                    "total_value" => "total",
+                    other => other,
+                };
+                // Unparsable numbers stay strings and are reported by the row validation
+                let number = if NUMERIC_COLUMNS.contains(&header.as_str()) { providers::parse_tr_float(cell) } else { None };
+                let value = match number {
+                    Some(n) => serde_json::json!(n),
+                    None => serde_json::json!(cell),
+                };
+                row.insert(key.to_string(), value);
+            }
+            rows.push((line, serde_json::Value::Object(row)));
+        }
+        Ok(rows)
+    }
+
+    /// Splits CSV text into its trimmed header and trimmed data records, each tagged with its line
+    /// number in the file. Blank records are skipped.
+    pub(crate) fn read_rows(text: &str, delimiter: u8) -> Result<CsvRows, csv::Error> {
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .flexible(true)
+            .from_reader(text.as_bytes());
+        let headers = reader.headers()?.iter().map(|h| h.trim().to_string()).collect();
+
+        let bytes = text.as_bytes();
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            let record = record?;
+            if record.iter().all(|cell| cell.trim().is_empty()) {
+                continue;
+            }
+            // The reader's line count and offset both include skipped blank lines; count past them
+            let mut offset = record.position().map_or(0, |p| p.byte() as usize).min(bytes.len());
+            while matches!(bytes.get(offset), Some(b'\n' | b'\r')) {
+                offset += 1;
+            }
+            let line = bytes[..offset].iter().filter(|b| **b == b'\n').count() + 1;
+            rows.push((line, record.iter().map(|cell| cell.trim().to_string()).collect()));
+        }
+        Ok((headers, rows))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::importer::RowStatus;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::path::PathBuf;
+
+    async fn memory_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::create_schema(&pool).await.unwrap();
+        pool
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("csv_io-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    async fn transactions(pool: &SqlitePool) -> Vec<ImportedTransaction> {
+        sqlx::query_as(
+            "SELECT transaction_date, asset_type, symbol, transaction_type, quantity, price, total_value, fees, currency,
+                    broker, notes, is_dividend, withholding_tax
+             FROM transactions ORDER BY id",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn encoding_names_match_the_frontend() {
+        for (json, encoding) in [
+            ("utf-8", CsvEncoding::Utf8),
+            ("utf8", CsvEncoding::Utf8),
+            ("windows-1254", CsvEncoding::Windows1254),
+            ("cp1254", CsvEncoding::Windows1254),
+        ] {
+            let options: CsvOptions =
+                serde_json::from_str(&format!(r#"{{"delimiter":";","encoding":"{}","decimal_comma":true}}"#, json)).unwrap();
+            assert_eq!(options.encoding, encoding, "{}", json);
+        }
+        assert_eq!(serde_json::to_string(&CsvEncoding::Utf8).unwrap(), r#""utf-8""#);
+        assert_eq!(serde_json::to_string(&CsvEncoding::Windows1254).unwrap(), r#""windows-1254""#);
+    }
+
+    #[test]
+    fn line_numbers_count_skipped_blank_lines() {
+        let text = "symbol,quantity\n\nTHYAO,1\r\n\r\n;\n  ,  \nASELS,2\n\n";
+        let (headers, rows) = CsvService::read_rows(text, b',').unwrap();
+        assert_eq!(headers, ["symbol", "quantity"]);
+        let lines: Vec<usize> = rows.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [3, 5, 7]);
+        assert_eq!(rows[2].1, ["ASELS", "2"]);
+    }
+
+    #[tokio::test]
+    async fn import_reports_file_line_numbers() {
+        let pool = memory_pool().await;
+        let dir = scratch_dir("lines");
+        let path = dir.join("lines.csv");
+        std::fs::write(
+            &path,
+            "transaction_date;symbol;transaction_type;quantity;price\n\n31.01.2024;THYAO;Alış;10;250,5\n\n\n01.02.2024;ASELS;X;1;1\n",
+        )
+        .unwrap();
+        let options = CsvOptions { delimiter: ';', ..CsvOptions::default() };
+
+        let report = CsvService::import_transactions(&pool, &path, &options, ImportStrategy::SkipDuplicates, true, false, &dir)
+            .await
+            .unwrap();
+        let rows: Vec<(usize, RowStatus)> = report.rows.iter().map(|r| (r.row, r.status)).collect();
+        assert_eq!(rows, [(3, RowStatus::New), (6, RowStatus::Invalid)]);
+        assert!(report.issues.iter().any(|i| i.row == 6));
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn windows_1254_decimal_comma_export_round_trips() {
+        let source = memory_pool().await;
+        for (date, symbol, kind, quantity, price, fees, notes) in [
+            ("2024-01-31", "THYAO", "BUY", 10.0, 250.5, 12.75, "İlk alım; şirket hesabı"),
+            ("2024-03-15", "TTE", "SELL", 1234.5678, 1.234567, 0.0, "Çeyrek ödeme"),
+            ("2024-04-01", "THYAO", "DIVIDEND", 10.0, 4.25, 0.0, ""),
+        ] {
+            sqlx::query(
+                "INSERT INTO transactions (transaction_date, asset_type, symbol, transaction_type, quantity, price,
+                                           total_value, fees, currency, broker, notes, is_dividend, withholding_tax)
+                 VALUES (?, 'hisse', ?, ?, ?, ?, ?, ?, 'TRY', 'Garanti BBVA', ?, ?, ?)",
+            )
+            .bind(date)
+            .bind(symbol)
+            .bind(kind)
+            .bind(quantity)
+            .bind(price)
+            .bind(quantity * price)
+            .bind(fees)
+            .bind((!notes.is_empty()).then_some(notes)) // CSV has no NULL; an empty cell reads back as one
+            .bind(kind == "DIVIDEND")
+            .bind(if kind == "DIVIDEND" { 4.25 } else { 0.0 })
+            .execute(&source)
+            .await
+            .unwrap();
+        }
+
+        let dir = scratch_dir("round-trip");
+        let path = dir.join("export.csv");
+        let options = CsvOptions { delimiter: ';', encoding: CsvEncoding::Windows1254, decimal_comma: true };
+        assert_eq!(CsvService::export_transactions(&source, &path, &options).await.unwrap(), 3);
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(std::str::from_utf8(&bytes).is_err(), "Turkish letters should be single 1254 bytes");
+        let text = CsvService::decode(&bytes, CsvEncoding::Windows1254).unwrap();
+        assert!(text.contains("250,5;") && text.contains("\"İlk alım; şirket hesabı\""));
+
+        let target = memory_pool().await;
+        let report = CsvService::import_transactions(&target, &path, &options, ImportStrategy::SkipDuplicates, false, false, &dir)
+            .await
+            .unwrap();
+        assert_eq!(report.transactions.written, 3);
+        assert_eq!(transactions(&target).await, transactions(&source).await);
+
+        // Importing the same file again finds only duplicates
+        let again = CsvService::import_transactions(&target, &path, &options, ImportStrategy::SkipDuplicates, false, false, &dir)
+            .await
+            .unwrap();
+        assert_eq!((again.transactions.duplicates, again.transactions.written), (3, 0));
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -1,7 +1,222 @@
-use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions, SqlitePool};
+use chrono::Local;
+use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions, SqliteConnection, SqlitePool};
 use std::fs;
+use std::path::{Path, PathBuf};
 use tauri::Manager;
 
+use crate::lots;
+
+/// One forward schema change. Column additions are checked first because databases created
+/// before versioning may already have some of them.
+enum Step {
+    Sql(&'static str),
+    AddColumn {
+        table: &'static str,
+        column: &'static str,
+        definition: &'static str,
+    },
+    // Rewrites readable dates in `column` to ISO `YYYY-MM-DD` using the app's date parser
+    NormalizeDates {
+        table: &'static str,
+        column: &'static str,
+    },
+}
+
+struct Migration {
+    version: i64,
+    name: &'static str,
+    steps: &'static [Step],
+}
+
+// Append only: a released migration is never edited, changes go into a new version.
+// Version 1 is the schema shipped before versioning, so unversioned databases start at 0.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        steps: &[
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS transactions (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    transaction_date DATE NOT NULL,
+                    asset_type TEXT NOT NULL,
+                    symbol TEXT NOT NULL,
+                    transaction_type TEXT NOT NULL,
+                    quantity REAL NOT NULL,
+                    price REAL NOT NULL,
+                    total_value REAL,
+                    fees REAL DEFAULT 0,
+                    currency TEXT DEFAULT 'TRY',
+                    broker TEXT,
+                    notes TEXT,
+                    is_dividend BOOLEAN DEFAULT 0
+                )",
+            ),
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS assets (
+                    symbol TEXT PRIMARY KEY,
+                    name TEXT,
+                    asset_type TEXT,
+                    current_price REAL,
+                    day_change REAL DEFAULT 0,
+                    last_updated TIMESTAMP,
+                    market TEXT,
+                    sector TEXT
+                )",
+            ),
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS portfolio_snapshots (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    snapshot_date DATE UNIQUE NOT NULL,
+                    total_value_tl REAL NOT NULL,
+                    total_value_usd REAL NOT NULL,
+                    total_cost_basis REAL DEFAULT 0,
+                    realized_pnl REAL DEFAULT 0,
+                    unrealized_pnl REAL DEFAULT 0,
+                    cash_balance REAL DEFAULT 0,
+                    total_return_pct REAL,
+                    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+                )",
+            ),
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS asset_price_history (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    symbol TEXT,
+                    price REAL,
+                    snapshot_date DATE,
+                    UNIQUE(symbol, snapshot_date)
+                )",
+            ),
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS tefas_daily_tracking (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    symbol TEXT,
+                    price REAL,
+                    day_change REAL,
+                    snapshot_date DATE,
+                    UNIQUE(symbol, snapshot_date)
+                )",
+            ),
+        ],
+    },
+    Migration {
+        version: 2,
+        name: "cost_basis_settings",
+        steps: &[
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS settings (
+                    key TEXT PRIMARY KEY,
+                    value TEXT NOT NULL
+                )",
+            ),
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS lot_selections (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    sell_transaction_id INTEGER NOT NULL,
+                    buy_transaction_id INTEGER NOT NULL,
+                    quantity REAL NOT NULL
+                )",
+            ),
+        ],
+    },
+    Migration {
+        version: 3,
+        name: "dividend_withholding_tax",
+        steps: &[Step::AddColumn { table: "transactions", column: "withholding_tax", definition: "REAL DEFAULT 0" }],
+    },
+    Migration {
+        version: 4,
+        name: "corporate_actions",
+        steps: &[Step::Sql(
+            "CREATE TABLE IF NOT EXISTS corporate_actions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                symbol TEXT NOT NULL,
+                action_type TEXT NOT NULL,
+                ex_date DATE NOT NULL,
+                ratio REAL NOT NULL,
+                subscription_price REAL,
+                notes TEXT,
+                history_adjusted BOOLEAN DEFAULT 0,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )",
+        )],
+    },
+    Migration {
+        version: 5,
+        name: "price_backfill_progress",
+        steps: &[Step::Sql(
+            "CREATE TABLE IF NOT EXISTS price_backfill_progress (
+                symbol TEXT NOT NULL,
+                source TEXT NOT NULL,
+                start_date DATE NOT NULL,
+                last_completed_date DATE,
+                fund_type TEXT,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (symbol, source)
+            )",
+        )],
+    },
+    Migration {
+        version: 6,
+        name: "update_log",
+        // One row per source per market data update, to see when and why a source started failing
+        steps: &[Step::Sql(
+            "CREATE TABLE IF NOT EXISTS update_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                update_type TEXT NOT NULL,
+                source TEXT NOT NULL,
+                started_at TEXT NOT NULL,
+                duration_ms INTEGER NOT NULL DEFAULT 0,
+                skipped_cached BOOLEAN NOT NULL DEFAULT 0,
+                rows_parsed INTEGER NOT NULL DEFAULT 0,
+                rows_upserted INTEGER NOT NULL DEFAULT 0,
+                changed_symbols TEXT NOT NULL DEFAULT '[]',
+                requests TEXT NOT NULL DEFAULT '[]',
+                errors TEXT NOT NULL DEFAULT '[]'
+            )",
+        )],
+    },
+    Migration {
+        version: 7,
+        name: "fund_detail_cache",
+        // Computed fund detail views (history, performance, risk) for offline use
+        steps: &[Step::Sql(
+            "CREATE TABLE IF NOT EXISTS fund_detail_cache (
+                code TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                fetched_at TEXT NOT NULL,
+                PRIMARY KEY (code, kind)
+            )",
+        )],
+    },
+    Migration {
+        version: 8,
+        name: "asset_price_source",
+        steps: &[Step::AddColumn { table: "assets", column: "price_source", definition: "TEXT" }],
+    },
+    Migration {
+        version: 9,
+        name: "normalize_transaction_dates",
+        // Dates typed or imported as "31.01.2024" or "31/01/2024 10:00" broke sorting and range filters
+        steps: &[Step::NormalizeDates { table: "transactions", column: "transaction_date" }],
+    },
+    Migration {
+        version: 10,
+        name: "import_profiles",
+        // Column mappings for broker/bank spreadsheet exports, stored as `ImportProfile` JSON
+        steps: &[Step::Sql(
+            "CREATE TABLE IF NOT EXISTS import_profiles (
+                name TEXT PRIMARY KEY,
+                profile TEXT NOT NULL,
+                last_used_at TEXT,
+                updated_at TEXT NOT NULL
+            )",
+        )],
+    },
+];
+
 pub async fn init_db(
     app_handle: &tauri::AppHandle,
 ) -> Result<SqlitePool, Box<dyn std::error::Error>> {
@@ -18,85 +233,343 @@ pub async fn init_db(
         .log_statements(log::LevelFilter::Debug);
 
     let pool = SqlitePool::connect_with(options).await?;
+    migrate(&pool, &app_dir.join("backups")).await?;
 
-    // Create tables if they don't exist
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS transactions (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-            transaction_date DATE NOT NULL,
-            asset_type TEXT NOT NULL,
-            symbol TEXT NOT NULL,
-            transaction_type TEXT NOT NULL,
-            quantity REAL NOT NULL,
-            price REAL NOT NULL,
-            total_value REAL,
-            fees REAL DEFAULT 0,
-            currency TEXT DEFAULT 'TRY',
-            broker TEXT,
-            notes TEXT,
-            is_dividend BOOLEAN DEFAULT 0
-        )",
-    )
-    .execute(&pool)
-    .await?;
+    Ok(pool)
+}
 
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS assets (
-            symbol TEXT PRIMARY KEY,
-            name TEXT,
-            asset_type TEXT,
-            current_price REAL,
-            day_change REAL DEFAULT 0,
-            last_updated TIMESTAMP,
-            market TEXT,
-            sector TEXT
-        )",
-    )
-    .execute(&pool)
-    .await?;
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
 
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS portfolio_snapshots (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            snapshot_date DATE UNIQUE NOT NULL,
-            total_value_tl REAL NOT NULL,
-            total_value_usd REAL NOT NULL,
-            total_cost_basis REAL DEFAULT 0,
-            realized_pnl REAL DEFAULT 0,
-            unrealized_pnl REAL DEFAULT 0,
-            cash_balance REAL DEFAULT 0,
-            total_return_pct REAL,
-            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-        )",
-    )
-    .execute(&pool)
-    .await?;
+/// Highest applied migration; 0 for empty and pre-versioning databases.
+pub async fn schema_version(pool: &SqlitePool) -> Result<i64, sqlx::Error> {
+    let versioned: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'")
+        .fetch_one(pool)
+        .await?;
+    if versioned == 0 {
+        return Ok(0);
+    }
+    sqlx::query_scalar("SELECT COALESCE(MAX(version), 0) FROM schema_version")
+        .fetch_one(pool)
+        .await
+}
 
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS asset_price_history (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            symbol TEXT,
-            price REAL,
-            snapshot_date DATE,
-            UNIQUE(symbol, snapshot_date)
-        )",
-    )
-    .execute(&pool)
-    .await?;
+/// Backs up a non-empty database into `backup_dir` when migrations are pending, then applies them.
+/// Returns the backup path, if one was written.
+pub async fn migrate(pool: &SqlitePool, backup_dir: &Path) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    let current = schema_version(pool).await?;
+    if current > latest_version() {
+        return Err(format!(
+            "Database schema version {} is newer than this app supports ({})",
+            current,
+            latest_version()
+        )
+        .into());
+    }
+    if current == latest_version() {
+        return Ok(None);
+    }
+
+    let tables: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'")
+        .fetch_one(pool)
+        .await?;
+    let backup = if tables > 0 { Some(backup(pool, backup_dir, &format!("v{}", current)).await?) } else { None };
+
+    create_schema(pool).await?;
+    Ok(backup)
+}
 
+/// Consistent copy of the live database (WAL included) taken with `VACUUM INTO`, named
+/// `portfolio-{label}-{timestamp}.db`.
+pub async fn backup(pool: &SqlitePool, backup_dir: &Path, label: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    fs::create_dir_all(backup_dir)?;
+    let path = backup_dir.join(format!("portfolio-{}-{}.db", label, Local::now().format("%Y%m%d-%H%M%S")));
+    if path.exists() {
+        fs::remove_file(&path)?;
+    }
+    sqlx::query("VACUUM INTO ?")
+        .bind(path.to_string_lossy().to_string())
+        .execute(pool)
+        .await?;
+    Ok(path)
+}
+
+/// Applies pending migrations in one transaction, without a backup. Separate from `init_db` so
+/// tests can use an in-memory pool.
+pub async fn create_schema(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
     sqlx::query(
-        "CREATE TABLE IF NOT EXISTS tefas_daily_tracking (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            symbol TEXT,
-            price REAL,
-            day_change REAL,
-            snapshot_date DATE,
-            UNIQUE(symbol, snapshot_date)
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at TEXT NOT NULL
         )",
     )
-    .execute(&pool)
+    .execute(&mut *tx)
     .await?;
+    let current: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(version), 0) FROM schema_version")
+        .fetch_one(&mut *tx)
+        .await?;
 
-    Ok(pool)
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        for step in migration.steps {
+            match step {
+                Step::Sql(sql) => {
+                    sqlx::query(sql).execute(&mut *tx).await?;
+                }
+                Step::AddColumn { table, column, definition } => {
+                    add_column_if_missing(&mut tx, table, column, definition).await?;
+                }
+                Step::NormalizeDates { table, column } => {
+                    normalize_dates(&mut tx, table, column).await?;
+                }
+            }
+        }
+        sqlx::query("INSERT INTO schema_version (version, name, applied_at) VALUES (?, ?, ?)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(Local::now().to_rfc3339())
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn add_column_if_missing(
+    conn: &mut SqliteConnection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), sqlx::Error> {
+    let exists: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?")
+            .bind(table)
+            .bind(column)
+            .fetch_one(&mut *conn)
+            .await?;
+    if exists == 0 {
+        sqlx::query(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            table, column, definition
+        ))
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Unreadable values are left as they are; the app skips them in date-based reports.
+async fn normalize_dates(conn: &mut SqliteConnection, table: &str, column: &str) -> Result<(), sqlx::Error> {
+    let rows: Vec<(i64, String)> = sqlx::query_as(&format!("SELECT rowid, {} FROM {} WHERE {} IS NOT NULL", column, table, column))
+        .fetch_all(&mut *conn)
+        .await?;
+    for (rowid, value) in rows {
+        let Some(iso) = lots::normalize_date(&value).filter(|iso| *iso != value) else { continue };
+        sqlx::query(&format!("UPDATE {} SET {} = ? WHERE rowid = ?", table, column))
+            .bind(iso)
+            .bind(rowid)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn memory_pool() -> SqlitePool {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    async fn v0_pool() -> SqlitePool {
+        let pool = memory_pool().await;
+        sqlx::raw_sql(include_str!("../tests/fixtures/schema_v0.sql"))
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool
+    }
+
+    async fn columns(pool: &SqlitePool, table: &str) -> Vec<String> {
+        sqlx::query_scalar("SELECT name FROM pragma_table_info(?)")
+            .bind(table)
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    async fn table_exists(pool: &SqlitePool, table: &str) -> bool {
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?")
+            .bind(table)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+            == 1
+    }
+
+    #[test]
+    fn migration_versions_are_sequential() {
+        for (i, m) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(m.version, i as i64 + 1, "{}", m.name);
+        }
+    }
+
+    #[tokio::test]
+    async fn migrates_v0_fixture_to_latest() {
+        let pool = v0_pool().await;
+        assert_eq!(schema_version(&pool).await.unwrap(), 0);
+
+        create_schema(&pool).await.unwrap();
+
+        assert_eq!(schema_version(&pool).await.unwrap(), latest_version());
+        let applied: Vec<i64> = sqlx::query_scalar("SELECT version FROM schema_version ORDER BY version")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(applied, (1..=latest_version()).collect::<Vec<_>>());
+
+        assert!(columns(&pool, "transactions").await.contains(&"withholding_tax".to_string()));
+        assert!(columns(&pool, "assets").await.contains(&"price_source".to_string()));
+        for table in ["settings", "lot_selections", "corporate_actions", "price_backfill_progress", "update_log", "fund_detail_cache", "import_profiles"] {
+            assert!(table_exists(&pool, table).await, "{}", table);
+        }
+
+        // Existing rows survive and pick up the new column defaults
+        let rows: Vec<(String, f64, f64)> =
+            sqlx::query_as("SELECT symbol, quantity, withholding_tax FROM transactions ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[2], ("THYAO".to_string(), 40.0, 0.0));
+        let history: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM asset_price_history")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(history, 2);
+        let price: f64 = sqlx::query_scalar("SELECT current_price FROM assets WHERE symbol = 'THYAO'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(price, 298.75);
+    }
+
+    #[tokio::test]
+    async fn rerunning_migrations_is_a_no_op() {
+        let pool = v0_pool().await;
+        create_schema(&pool).await.unwrap();
+        create_schema(&pool).await.unwrap();
+
+        let applied: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM schema_version")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(applied, MIGRATIONS.len() as i64);
+    }
+
+    #[tokio::test]
+    async fn unversioned_database_with_later_columns_migrates() {
+        // Development builds added columns with ALTER TABLE before versioning existed
+        let pool = v0_pool().await;
+        sqlx::query("ALTER TABLE transactions ADD COLUMN withholding_tax REAL DEFAULT 0")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE settings (key TEXT PRIMARY KEY, value TEXT NOT NULL)")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        create_schema(&pool).await.unwrap();
+        assert_eq!(schema_version(&pool).await.unwrap(), latest_version());
+    }
+
+    #[tokio::test]
+    async fn normalizes_transaction_dates() {
+        let pool = v0_pool().await;
+        for date in ["31.01.2024", "05/03/2024 14:30", "2024-06-01T10:00:00+03:00", "not a date"] {
+            sqlx::query(
+                "INSERT INTO transactions (transaction_date, asset_type, symbol, transaction_type, quantity, price)
+                 VALUES (?, 'hisse', 'ASELS', 'BUY', 1, 1)",
+            )
+            .bind(date)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        create_schema(&pool).await.unwrap();
+
+        let dates: Vec<String> = sqlx::query_scalar("SELECT transaction_date FROM transactions WHERE symbol = 'ASELS' ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(dates, ["2024-01-31", "2024-03-05", "2024-06-01", "not a date"]);
+    }
+
+    #[tokio::test]
+    async fn empty_database_needs_no_backup() {
+        let pool = memory_pool().await;
+        let dir = std::env::temp_dir().join(format!("portfolio-migrate-empty-{}", std::process::id()));
+
+        assert_eq!(migrate(&pool, &dir).await.unwrap(), None);
+        assert_eq!(schema_version(&pool).await.unwrap(), latest_version());
+        assert!(!dir.exists());
+    }
+
+    #[tokio::test]
+    async fn backs_up_before_migrating() {
+        let dir = std::env::temp_dir().join(format!("portfolio-migrate-backup-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let options = SqliteConnectOptions::new()
+            .filename(dir.join("portfolio.db"))
+            .create_if_missing(true)
+            .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal);
+        let pool = SqlitePool::connect_with(options).await.unwrap();
+        sqlx::raw_sql(include_str!("../tests/fixtures/schema_v0.sql"))
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let backup = migrate(&pool, &dir.join("backups")).await.unwrap().expect("backup path");
+        assert!(backup.file_name().unwrap().to_string_lossy().starts_with("portfolio-v0-"));
+
+        // The backup is the untouched version 0 database
+        let copy = SqlitePool::connect_with(SqliteConnectOptions::new().filename(&backup)).await.unwrap();
+        assert_eq!(schema_version(&copy).await.unwrap(), 0);
+        let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM transactions")
+            .fetch_one(&copy)
+            .await
+            .unwrap();
+        assert_eq!(rows, 3);
+        copy.close().await;
+
+        // Up to date: nothing to back up
+        assert_eq!(migrate(&pool, &dir.join("backups")).await.unwrap(), None);
+        pool.close().await;
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn refuses_newer_schema() {
+        let pool = memory_pool().await;
+        create_schema(&pool).await.unwrap();
+        sqlx::query("INSERT INTO schema_version (version, name, applied_at) VALUES (?, 'future', '')")
+            .bind(latest_version() + 1)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert!(migrate(&pool, &std::env::temp_dir()).await.is_err());
+    }
 }
@@ -0,0 +1,333 @@
+use chrono::{DateTime, Datelike, Local, Months, NaiveDate};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::lots;
+use crate::market_calendar;
+use crate::scraper::ScraperService;
+
+const TRADING_DAYS_PER_YEAR: f64 = 252.0;
+// A period start falling on a weekend or holiday still counts if history begins within this many days
+const PERIOD_START_TOLERANCE_DAYS: i64 = 7;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FundHistoryPoint {
+    pub date: String,
+    pub close: f64,
+}
+
+/// Period returns in percent; `None` when local history doesn't reach back far enough.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FundPerformance {
+    pub daily_return: Option<f64>,
+    pub return_1m: Option<f64>,
+    pub return_3m: Option<f64>,
+    pub return_6m: Option<f64>,
+    pub return_1y: Option<f64>,
+    pub return_3y: Option<f64>,
+    pub return_5y: Option<f64>,
+    pub return_ytd: Option<f64>,
+}
+
+/// Risk metrics over the last year of daily closes. Volatility and drawdown are percentages.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FundRisk {
+    pub annualized_volatility: Option<f64>,
+    pub sharpe_ratio: Option<f64>,
+    pub sortino_ratio: Option<f64>,
+    pub max_drawdown: Option<f64>, // negative, e.g. -12.5 for a 12.5% peak-to-trough fall
+}
+
+impl FundPerformance {
+    pub fn from_history(history: &[(NaiveDate, f64)]) -> Self {
+        let Some(&(last_date, last_price)) = history.last() else { return Self::default() };
+        let since = |start: Option<NaiveDate>| {
+            start
+                .and_then(|s| price_at(history, s))
+                .map(|p| (last_price / p - 1.0) * 100.0)
+        };
+        let months_back = |m: u32| last_date.checked_sub_months(Months::new(m));
+
+        Self {
+            daily_return: history
+                .len()
+                .checked_sub(2)
+                .map(|i| (last_price / history[i].1 - 1.0) * 100.0),
+            return_1m: since(months_back(1)),
+            return_3m: since(months_back(3)),
+            return_6m: since(months_back(6)),
+            return_1y: since(months_back(12)),
+            return_3y: since(months_back(36)),
+            return_5y: since(months_back(60)),
+            return_ytd: since(NaiveDate::from_ymd_opt(last_date.year() - 1, 12, 31)),
+        }
+    }
+}
+
+impl FundRisk {
+    pub fn from_history(history: &[(NaiveDate, f64)], risk_free_rate_pct: f64) -> Self {
+        let Some(&(last_date, _)) = history.last() else { return Self::default() };
+        let window_start = last_date.checked_sub_months(Months::new(12)).unwrap_or(last_date);
+        let window: Vec<f64> = history
+            .iter()
+            .filter(|(d, _)| *d >= window_start)
+            .map(|(_, p)| *p)
+            .collect();
+        let returns: Vec<f64> = window.windows(2).map(|w| w[1] / w[0] - 1.0).collect();
+        if returns.len() < 2 {
+            return Self::default();
+        }
+
+        let n = returns.len() as f64;
+        let daily_rf = risk_free_rate_pct / 100.0 / TRADING_DAYS_PER_YEAR;
+        let mean = returns.iter().sum::<f64>() / n;
+        let std_dev = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt();
+        let downside_dev = (returns.iter().map(|r| (r - daily_rf).min(0.0).powi(2)).sum::<f64>() / n).sqrt();
+        let annualize = TRADING_DAYS_PER_YEAR.sqrt();
+        let ratio = |dev: f64| (dev > 0.0).then(|| (mean - daily_rf) / dev * annualize);
+
+        let mut peak = window[0];
+        let mut max_drawdown = 0.0_f64;
+        for &price in &window {
+            peak = peak.max(price);
+            max_drawdown = max_drawdown.min(price / peak - 1.0);
+        }
+
+        Self {
+            annualized_volatility: Some(std_dev * annualize * 100.0),
+            sharpe_ratio: ratio(std_dev),
+            sortino_ratio: ratio(downside_dev),
+            max_drawdown: Some(max_drawdown * 100.0),
+        }
+    }
+}
+
+/// Close on or before `date`, or the first close if history starts just after it.
+fn price_at(history: &[(NaiveDate, f64)], date: NaiveDate) -> Option<f64> {
+    let idx = history.partition_point(|(d, _)| *d <= date);
+    if idx > 0 {
+        return Some(history[idx - 1].1);
+    }
+    history
+        .first()
+        .filter(|(d, _)| (*d - date).num_days() <= PERIOD_START_TOLERANCE_DAYS)
+        .map(|(_, p)| *p)
+}
+
+pub struct FundService;
+
+impl FundService {
+    const HISTORY: &'static str = "history";
+    const PERFORMANCE: &'static str = "performance";
+    const RISK: &'static str = "risk";
+
+    pub async fn get_history(pool: &SqlitePool, code: &str) -> Result<Vec<FundHistoryPoint>, Box<dyn std::error::Error>> {
+        Self::load(pool, code, Self::HISTORY).await
+    }
+
+    pub async fn get_performance(pool: &SqlitePool, code: &str) -> Result<FundPerformance, Box<dyn std::error::Error>> {
+        Self::load(pool, code, Self::PERFORMANCE).await
+    }
+
+    pub async fn get_risk(pool: &SqlitePool, code: &str) -> Result<FundRisk, Box<dyn std::error::Error>> {
+        Self::load(pool, code, Self::RISK).await
+    }
+
+    /// Serves the cached result while no newer TEFAS price can exist; otherwise refreshes and falls back to an older cache entry
+    /// when the refresh produced nothing. With no cache at all the TEFAS error is reported, if there was one.
+    async fn load<T: DeserializeOwned>(pool: &SqlitePool, code: &str, kind: &str) -> Result<T, Box<dyn std::error::Error>> {
+        let code = code.trim().to_uppercase();
+        if let Some(value) = Self::cached(pool, &code, kind, true).await? {
+            return Ok(value);
+        }
+        let backfill_error = Self::refresh(pool, &code).await?;
+        Self::cached(pool, &code, kind, false).await?.ok_or_else(|| match backfill_error {
+            Some(e) => format!("No price history for {}: {}", code, e).into(),
+            None => format!("No price history for {}", code).into(),
+        })
+    }
+
+    async fn cached<T: DeserializeOwned>(
+        pool: &SqlitePool,
+        code: &str,
+        kind: &str,
+        fresh_only: bool,
+    ) -> Result<Option<T>, Box<dyn std::error::Error>> {
+        let row = sqlx::query_as::<_, (String, String)>(
+            "SELECT payload, fetched_at FROM fund_detail_cache WHERE code = ? AND kind = ?",
+        )
+        .bind(code)
+        .bind(kind)
+        .fetch_optional(pool)
+        .await?;
+        let Some((payload, fetched_at)) = row else { return Ok(None) };
+
+        // TEFAS publishes one price per business day, so a result stays current until the next publication
+        let fresh = DateTime::parse_from_rfc3339(&fetched_at)
+            .is_ok_and(|dt| !market_calendar::has_new_prices_since("fon", dt, market_calendar::istanbul_now()));
+        if fresh_only && !fresh {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&payload)?))
+    }
+
+    /// Pulls missing closes from TEFAS, then recomputes and caches all three views from local history.
+    /// Returns the backfill error, if any; it only matters to the caller when nothing could be cached.
+    async fn refresh(pool: &SqlitePool, code: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        // Network failures don't stop the refresh: whatever history is already stored is still usable
+        let backfill_error = match ScraperService::new().backfill_recent_tefas_history(pool, code).await {
+            Ok(result) => result.error,
+            Err(e) => Some(e.to_string()),
+        };
+
+        let history: Vec<(NaiveDate, f64)> = sqlx::query_as::<_, (String, f64)>(
+            "SELECT snapshot_date, price FROM asset_price_history WHERE symbol = ? AND price > 0 ORDER BY snapshot_date ASC",
+        )
+        .bind(code)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .filter_map(|(d, p)| lots::parse_date(&d).map(|d| (d, p)))
+        .collect();
+        if history.is_empty() {
+            return Ok(backfill_error);
+        }
+
+        let risk_free_rate_pct: f64 = sqlx::query_scalar::<_, String>("SELECT value FROM settings WHERE key = 'risk_free_rate_pct'")
+            .fetch_optional(pool)
+            .await?
+            .and_then(|v| v.trim().replace(',', ".").parse().ok())
+            .unwrap_or(0.0);
+
+        let points: Vec<FundHistoryPoint> = history
+            .iter()
+            .map(|(d, p)| FundHistoryPoint { date: d.format("%Y-%m-%d").to_string(), close: *p })
+            .collect();
+        let payloads = [
+            (Self::HISTORY, serde_json::to_string(&points)?),
+            (Self::PERFORMANCE, serde_json::to_string(&FundPerformance::from_history(&history))?),
+            (Self::RISK, serde_json::to_string(&FundRisk::from_history(&history, risk_free_rate_pct))?),
+        ];
+
+        let fetched_at = Local::now().to_rfc3339();
+        let mut tx = pool.begin().await?;
+        for (kind, payload) in payloads {
+            sqlx::query(
+                "INSERT INTO fund_detail_cache (code, kind, payload, fetched_at)
+                 VALUES (?, ?, ?, ?)
+                 ON CONFLICT(code, kind) DO UPDATE SET payload = excluded.payload, fetched_at = excluded.fetched_at",
+            )
+            .bind(code)
+            .bind(kind)
+            .bind(payload)
+            .bind(&fetched_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(backfill_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn series(points: &[(&str, f64)]) -> Vec<(NaiveDate, f64)> {
+        points.iter().map(|&(d, p)| (date(d), p)).collect()
+    }
+
+    fn close(actual: Option<f64>, expected: f64) -> bool {
+        actual.is_some_and(|a| (a - expected).abs() < 1e-9)
+    }
+
+    #[test]
+    fn period_starts_on_closed_days_use_the_previous_close() {
+        // 1M back from 2024-05-23 is 2024-04-23, a public holiday; the 04-22 close is used
+        let history = series(&[("2024-04-22", 100.0), ("2024-04-24", 101.0), ("2024-05-22", 108.0), ("2024-05-23", 110.0)]);
+        let performance = FundPerformance::from_history(&history);
+        assert!(close(performance.return_1m, 10.0), "{:?}", performance.return_1m);
+        assert!(close(performance.daily_return, (110.0 / 108.0 - 1.0) * 100.0));
+        assert_eq!(performance.return_3m, None);
+
+        // 1M back from 2024-03-11 is a Sunday; Friday's close is used
+        let history = series(&[("2024-02-08", 50.0), ("2024-02-09", 40.0), ("2024-02-12", 45.0), ("2024-03-11", 50.0)]);
+        let performance = FundPerformance::from_history(&history);
+        assert!(close(performance.return_1m, 25.0), "{:?}", performance.return_1m);
+    }
+
+    #[test]
+    fn period_start_tolerance_cutoff() {
+        let history = series(&[("2024-04-30", 100.0), ("2024-05-23", 110.0)]);
+        assert_eq!(price_at(&history, date("2024-04-23")), Some(100.0));
+        assert!(close(FundPerformance::from_history(&history).return_1m, 10.0));
+
+        // History starting 8 days after the period start doesn't cover the period
+        let history = series(&[("2024-05-01", 100.0), ("2024-05-23", 110.0)]);
+        assert_eq!(price_at(&history, date("2024-04-23")), None);
+        assert_eq!(FundPerformance::from_history(&history).return_1m, None);
+
+        assert_eq!(price_at(&history, date("2024-05-10")), Some(100.0));
+        assert_eq!(price_at(&history, date("2024-06-01")), Some(110.0));
+        assert_eq!(price_at(&[], date("2024-06-01")), None);
+    }
+
+    #[test]
+    fn ytd_is_anchored_to_the_last_close_of_the_previous_year() {
+        let history = series(&[("2023-12-29", 50.0), ("2024-01-02", 55.0), ("2024-03-11", 60.0)]);
+        assert!(close(FundPerformance::from_history(&history).return_ytd, 20.0));
+
+        // Without a close from last year, the first close of the year stands in
+        let history = series(&[("2024-01-02", 50.0), ("2024-03-11", 60.0)]);
+        assert!(close(FundPerformance::from_history(&history).return_ytd, 20.0));
+        assert_eq!(FundPerformance::from_history(&history).return_1y, None);
+
+        let empty = FundPerformance::from_history(&[]);
+        assert_eq!((empty.daily_return, empty.return_ytd), (None, None));
+    }
+
+    #[test]
+    fn max_drawdown_is_negative_peak_to_trough() {
+        let history = series(&[("2024-01-02", 100.0), ("2024-01-03", 120.0), ("2024-01-04", 90.0), ("2024-01-05", 110.0)]);
+        let risk = FundRisk::from_history(&history, 0.0);
+        assert!(close(risk.max_drawdown, -25.0), "{:?}", risk.max_drawdown);
+
+        let rising = series(&[("2024-01-02", 100.0), ("2024-01-03", 101.0), ("2024-01-04", 103.0)]);
+        assert_eq!(FundRisk::from_history(&rising, 0.0).max_drawdown, Some(0.0));
+    }
+
+    #[test]
+    fn ratios_are_none_without_deviation() {
+        let flat = series(&[("2024-01-02", 100.0), ("2024-01-03", 100.0), ("2024-01-04", 100.0), ("2024-01-05", 100.0)]);
+        let risk = FundRisk::from_history(&flat, 0.0);
+        assert_eq!(risk.annualized_volatility, Some(0.0));
+        assert_eq!((risk.sharpe_ratio, risk.sortino_ratio), (None, None));
+
+        // Never below the risk-free rate: no downside deviation, so only Sharpe is defined
+        let rising = series(&[("2024-01-02", 100.0), ("2024-01-03", 101.0), ("2024-01-04", 103.0)]);
+        let risk = FundRisk::from_history(&rising, 0.0);
+        assert!(risk.sharpe_ratio.is_some_and(|s| s > 0.0));
+        assert_eq!(risk.sortino_ratio, None);
+
+        // The same series against a high risk-free rate is all downside
+        let risk = FundRisk::from_history(&rising, 5000.0);
+        assert!(risk.sortino_ratio.is_some_and(|s| s < 0.0));
+    }
+
+    #[test]
+    fn risk_needs_three_points_within_the_last_year() {
+        assert_eq!(FundRisk::from_history(&[], 0.0).annualized_volatility, None);
+        let two = series(&[("2024-01-02", 100.0), ("2024-01-03", 90.0)]);
+        let risk = FundRisk::from_history(&two, 0.0);
+        assert_eq!((risk.annualized_volatility, risk.max_drawdown), (None, None));
+
+        // The oldest close falls outside the one-year window
+        let stale = series(&[("2022-12-30", 80.0), ("2024-01-02", 100.0), ("2024-01-03", 90.0)]);
+        assert_eq!(FundRisk::from_history(&stale, 0.0).max_drawdown, None);
+    }
+}
@@ -0,0 +1,1438 @@
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::db;
+use crate::lots;
+
+// Tables a foreign database contributes; everything else (caches, logs) is rebuilt locally
+const IMPORTED_TABLES: [&str; 3] = ["transactions", "assets", "portfolio_snapshots"];
+// Every type the app writes; `altin` and `diger` come from the add-transaction form and stay unpriced
+const KNOWN_ASSET_TYPES: [&str; 9] = ["hisse", "fon", "doviz", "emtia", "kripto", "endeks", "manuel", "altin", "diger"];
+// Manual assets and generated canlidoviz codes keep Turkish letters and spaces, so only the length is checked
+const MAX_SYMBOL_LEN: usize = 64;
+
+/// What to do with rows that already exist locally.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStrategy {
+    Append, // insert every valid transaction, even ones that look like duplicates
+    #[serde(alias = "merge")]
+    SkipDuplicates, // add new rows, keep local ones on duplicates and conflicts
+    Replace,        // wipe the imported tables first, then take the source as is
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct TableImportStats {
+    pub source_rows: usize,
+    pub new_rows: usize,
+    pub duplicates: usize,
+    pub conflicts: usize,
+    pub invalid: usize,
+    pub written: usize,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RowStatus {
+    New,
+    Duplicate,
+    Conflict,
+    Invalid,
+}
+
+/// Outcome of one row of an import file; `row` is the record number for JSON and the line number for CSV.
+#[derive(Debug, Serialize, Clone)]
+pub struct ImportRow {
+    pub row: usize,
+    pub status: RowStatus,
+    pub date: Option<String>,
+    pub symbol: Option<String>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueSeverity {
+    Error,   // the row can't be imported
+    Warning, // imported, but something was assumed or looks off
+}
+
+/// A validation finding on one import row, worded for both UI languages.
+#[derive(Debug, Serialize, Clone)]
+pub struct ImportIssue {
+    pub row: usize,
+    pub severity: IssueSeverity,
+    pub field: Option<String>,
+    pub message_tr: String,
+    pub message_en: String,
+}
+
+impl ImportIssue {
+    fn error(row: usize, field: &str, message_tr: String, message_en: String) -> Self {
+        ImportIssue { row, severity: IssueSeverity::Error, field: Some(field.to_string()), message_tr, message_en }
+    }
+
+    fn warning(row: usize, field: &str, message_tr: String, message_en: String) -> Self {
+        ImportIssue { row, severity: IssueSeverity::Warning, field: Some(field.to_string()), message_tr, message_en }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TransactionImportReport {
+    pub strategy: ImportStrategy,
+    pub dry_run: bool,
+    pub skip_invalid: bool,
+    pub backup_path: Option<String>,
+    pub transactions: TableImportStats,
+    pub rows: Vec<ImportRow>,
+    pub issues: Vec<ImportIssue>,
+}
+
+/// A source row whose identity matches a local row but whose details differ.
+#[derive(Debug, Serialize, Clone)]
+pub struct ImportConflict {
+    pub table: String,
+    pub key: String,
+    pub existing: String,
+    pub incoming: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DatabaseImportReport {
+    pub source_path: String,
+    pub source_version: i64,
+    pub strategy: ImportStrategy,
+    pub dry_run: bool,
+    pub backup_path: Option<String>,
+    pub transactions: TableImportStats,
+    pub assets: TableImportStats,
+    pub snapshots: TableImportStats,
+    pub conflicts: Vec<ImportConflict>,
+}
+
+/// The exported columns of a transaction; JSON backups and CSV files carry exactly these.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TransactionExport {
+    pub transaction_date: String,
+    pub asset_type: String,
+    pub symbol: String,
+    pub transaction_type: String,
+    pub quantity: f64,
+    pub price: f64,
+    pub total_value: Option<f64>,
+    pub fees: Option<f64>,
+    pub currency: Option<String>,
+    pub broker: Option<String>,
+    pub notes: Option<String>,
+    pub withholding_tax: Option<f64>,
+}
+
+/// A transaction in current-schema terms, whichever schema it was read from.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct ImportedTransaction {
+    pub transaction_date: String,
+    pub asset_type: String,
+    pub symbol: String,
+    pub transaction_type: String,
+    pub quantity: f64,
+    pub price: f64,
+    pub total_value: Option<f64>,
+    pub fees: Option<f64>,
+    pub currency: Option<String>,
+    pub broker: Option<String>,
+    pub notes: Option<String>,
+    pub is_dividend: Option<bool>,
+    pub withholding_tax: Option<f64>,
+}
+
+impl ImportedTransaction {
+    /// Identity of a trade: the same date, symbol, side, quantity, price and broker is the same row,
+    /// however its date or transaction type label was written.
+    pub fn fingerprint(&self) -> String {
+        let date = lots::parse_date(&self.transaction_date)
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .unwrap_or_else(|| self.transaction_date.trim().to_string());
+        format!(
+            "{}|{}|{}|{:.8}|{:.8}|{}",
+            date,
+            self.symbol.trim().to_uppercase(),
+            known_transaction_type(&self.transaction_type)
+                .unwrap_or_else(|| crate::db_transaction_type(crate::ui_transaction_type(&self.transaction_type))),
+            self.quantity,
+            self.price,
+            self.broker.as_deref().unwrap_or("").trim().to_lowercase()
+        )
+    }
+
+    /// Fields outside the fingerprint that make two matching rows disagree.
+    fn details(&self) -> String {
+        format!(
+            "asset_type={} fees={:.2} withholding_tax={:.2} notes={}",
+            self.asset_type,
+            self.fees.unwrap_or(0.0),
+            self.withholding_tax.unwrap_or(0.0),
+            self.notes.as_deref().unwrap_or("")
+        )
+    }
+}
+
+/// One transaction as written by `ImportService::export_json` (a `TransactionExport` with the asset name);
+/// CSV rows are mapped onto the same fields.
+#[derive(Debug, Deserialize)]
+struct TransactionRecord {
+    // Missing required fields are defaulted so validation can name them
+    #[serde(default)]
+    date: String,
+    #[serde(default)]
+    symbol: String,
+    name: Option<String>,
+    asset_type: Option<String>,
+    #[serde(rename = "type", default)]
+    transaction_type: String,
+    #[serde(default)]
+    quantity: f64,
+    #[serde(default)]
+    price: f64,
+    total: Option<f64>,
+    notes: Option<String>,
+    fees: Option<f64>,
+    currency: Option<String>,
+    broker: Option<String>,
+    withholding_tax: Option<f64>,
+}
+
+impl TransactionRecord {
+    /// Checks the row before anything is written; rows with errors are never imported.
+    fn validate(&self, row: usize, today: NaiveDate) -> Vec<ImportIssue> {
+        let mut issues = Vec::new();
+
+        let date = self.date.trim();
+        if date.is_empty() || date == "-" {
+            issues.push(ImportIssue::error(row, "date", "Tarih eksik".into(), "Date is missing".into()));
+        } else {
+            match lots::parse_date(date) {
+                None => issues.push(ImportIssue::error(
+                    row,
+                    "date",
+                    format!("Tarih okunamadı: '{}' (YYYY-AA-GG, GG.AA.YYYY veya GG/AA/YYYY bekleniyordu)", date),
+                    format!("Unreadable date '{}' (expected YYYY-MM-DD, DD.MM.YYYY or DD/MM/YYYY)", date),
+                )),
+                Some(d) if d > today => issues.push(ImportIssue::warning(
+                    row,
+                    "date",
+                    format!("İleri tarihli işlem: {}", date),
+                    format!("Transaction is dated in the future: {}", date),
+                )),
+                Some(_) => {}
+            }
+        }
+
+        let symbol = self.symbol.trim();
+        if symbol.is_empty() {
+            issues.push(ImportIssue::error(row, "symbol", "Sembol eksik".into(), "Symbol is missing".into()));
+        } else if symbol.chars().count() > MAX_SYMBOL_LEN {
+            issues.push(ImportIssue::error(
+                row,
+                "symbol",
+                format!("Sembol çok uzun: '{}' (en fazla {} karakter)", symbol, MAX_SYMBOL_LEN),
+                format!("Symbol '{}' is too long (at most {} characters)", symbol, MAX_SYMBOL_LEN),
+            ));
+        } else if symbol != self.symbol || symbol != symbol.to_uppercase() {
+            issues.push(ImportIssue::warning(
+                row,
+                "symbol",
+                format!("Sembol '{}' olarak düzeltildi", symbol.to_uppercase()),
+                format!("Symbol normalized to '{}'", symbol.to_uppercase()),
+            ));
+        }
+
+        if known_transaction_type(&self.transaction_type).is_none() {
+            issues.push(ImportIssue::error(
+                row,
+                "type",
+                format!("Bilinmeyen işlem tipi: '{}' (Alış, Satış veya Temettü bekleniyordu)", self.transaction_type),
+                format!("Unknown transaction type '{}' (expected buy, sell or dividend)", self.transaction_type),
+            ));
+        }
+
+        for (field, value, tr, en) in [
+            ("quantity", self.quantity, "Miktar", "Quantity"),
+            ("price", self.price, "Fiyat", "Price"),
+        ] {
+            if !value.is_finite() || value <= 0.0 {
+                issues.push(ImportIssue::error(
+                    row,
+                    field,
+                    format!("{} sıfırdan büyük olmalı: {}", tr, value),
+                    format!("{} must be positive: {}", en, value),
+                ));
+            }
+        }
+        for (field, value, tr, en) in [
+            ("fees", self.fees, "Komisyon", "Fees"),
+            ("withholding_tax", self.withholding_tax, "Stopaj", "Withholding tax"),
+        ] {
+            if value.is_some_and(|v| !v.is_finite() || v < 0.0) {
+                issues.push(ImportIssue::error(
+                    row,
+                    field,
+                    format!("{} negatif olamaz: {}", tr, value.unwrap_or_default()),
+                    format!("{} can't be negative: {}", en, value.unwrap_or_default()),
+                ));
+            }
+        }
+
+        match self.asset_type.as_deref().map(str::trim) {
+            Some(t) if !KNOWN_ASSET_TYPES.contains(&t) => issues.push(ImportIssue::error(
+                row,
+                "asset_type",
+                format!("Bilinmeyen varlık tipi: '{}'", t),
+                format!("Unknown asset type '{}'", t),
+            )),
+            Some(_) => {}
+            None if !symbol.is_empty() => {
+                let guess = infer_asset_type(symbol, self.name.as_deref().unwrap_or(symbol));
+                issues.push(ImportIssue::warning(
+                    row,
+                    "asset_type",
+                    format!("Varlık tipi belirtilmemiş, '{}' varsayıldı", guess),
+                    format!("No asset type given, assumed '{}'", guess),
+                ));
+            }
+            None => {}
+        }
+
+        // Totals are recomputed from quantity and price when missing; a stated one that disagrees is suspicious
+        if let Some(total) = self.total {
+            let expected = self.quantity * self.price;
+            if (total - expected).abs() > 0.01 && (total - expected).abs() > expected.abs() * 0.01 {
+                issues.push(ImportIssue::warning(
+                    row,
+                    "total",
+                    format!("Toplam ({:.2}) miktar x fiyat ({:.2}) ile uyuşmuyor", total, expected),
+                    format!("Total ({:.2}) doesn't match quantity x price ({:.2})", total, expected),
+                ));
+            }
+        }
+
+        issues
+    }
+
+    /// Maps a validated row to current-schema terms; returns the display name alongside.
+    fn into_imported(self) -> (ImportedTransaction, String) {
+        let symbol = self.symbol.trim().to_uppercase();
+        let tx_type = known_transaction_type(&self.transaction_type).unwrap_or("SELL");
+        let name = self.name.unwrap_or_else(|| symbol.clone());
+        let asset_type = match self.asset_type {
+            Some(t) => t.trim().to_string(),
+            None => infer_asset_type(&symbol, &name),
+        };
+        let imported = ImportedTransaction {
+            transaction_date: lots::normalize_date(&self.date).unwrap_or(self.date),
+            asset_type,
+            symbol,
+            transaction_type: tx_type.to_string(),
+            quantity: self.quantity,
+            price: self.price,
+            total_value: Some(self.total.unwrap_or(self.quantity * self.price)),
+            fees: Some(self.fees.unwrap_or(0.0)),
+            currency: Some(self.currency.unwrap_or_else(|| "TRY".to_string())),
+            broker: self.broker,
+            notes: self.notes,
+            is_dividend: Some(tx_type == "DIVIDEND"),
+            withholding_tax: Some(self.withholding_tax.unwrap_or(0.0)),
+        };
+        (imported, name)
+    }
+}
+
+/// Stored transaction type for the labels an import may use, or None for anything unrecognized
+/// (unlike `ui_transaction_type`, which reads every unknown label as a sell).
+fn known_transaction_type(label: &str) -> Option<&'static str> {
+    // "ALIŞ" lower-cases to "aliş" (dotted i), so compare without the Turkish letters
+    let folded: String = label
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter(|c| *c != '\u{307}')
+        .map(|c| match c {
+            'ı' => 'i',
+            'ş' => 's',
+            'ü' => 'u',
+            c => c,
+        })
+        .collect();
+    match folded.as_str() {
+        "buy" | "alis" => Some("BUY"),
+        "sell" | "satis" => Some("SELL"),
+        "dividend" | "temettu" => Some("DIVIDEND"),
+        _ => None,
+    }
+}
+
+/// Guesses the asset type of rows exported without one.
+fn infer_asset_type(symbol: &str, name: &str) -> String {
+    let sym = symbol.to_uppercase();
+    let n = name.to_lowercase();
+    if sym == "USD" || sym == "EUR" || sym == "GBP" || sym == "CHF" {
+        "doviz".to_string()
+    } else if sym == "GA" || sym == "CE" || sym == "ATA" || sym == "RA5" || sym == "22" || sym == "YRG" || n.contains("altın") || n.contains("bilezik") {
+        "emtia".to_string()
+    } else if sym.ends_with("-C") {
+        "kripto".to_string()
+    } else if sym.len() == 3 || (sym.len() == 4 && sym.chars().any(|c| c.is_numeric())) {
+        "fon".to_string()
+    } else {
+        "hisse".to_string()
+    }
+}
+
+/// How an incoming transaction relates to the local ones.
+enum RowMatch {
+    New,
+    Duplicate,
+    Conflict { existing: String },
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct ImportedAsset {
+    symbol: String,
+    name: Option<String>,
+    asset_type: Option<String>,
+    current_price: Option<f64>,
+    day_change: Option<f64>,
+    last_updated: Option<String>,
+    market: Option<String>,
+    sector: Option<String>,
+    price_source: Option<String>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct ImportedSnapshot {
+    snapshot_date: String,
+    total_value_tl: f64,
+    total_value_usd: f64,
+    total_cost_basis: Option<f64>,
+    realized_pnl: Option<f64>,
+    unrealized_pnl: Option<f64>,
+    cash_balance: Option<f64>,
+    total_return_pct: Option<f64>,
+}
+
+pub struct ImportService;
+
+impl ImportService {
+    /// Reads a `portfolio.db` from an older version or another machine (opened read-only), maps its
+    /// transactions, assets and snapshots onto the current schema and merges or replaces local data.
+    /// With `dry_run` only the report is produced; otherwise the local database is backed up first.
+    pub async fn migrate_database(
+        pool: &SqlitePool,
+        source_path: &Path,
+        strategy: ImportStrategy,
+        dry_run: bool,
+        backup_dir: &Path,
+    ) -> Result<DatabaseImportReport, Box<dyn std::error::Error>> {
+        if !source_path.is_file() {
+            return Err(format!("Database file not found: {}", source_path.display()).into());
+        }
+        let source = SqlitePool::connect_with(SqliteConnectOptions::new().filename(source_path).read_only(true)).await?;
+        let loaded = Self::load_source(&source).await;
+        source.close().await;
+        let (source_version, transactions, assets, snapshots) = loaded?;
+
+        let mut report = DatabaseImportReport {
+            source_path: source_path.display().to_string(),
+            source_version,
+            strategy,
+            dry_run,
+            backup_path: None,
+            transactions: TableImportStats::default(),
+            assets: TableImportStats::default(),
+            snapshots: TableImportStats::default(),
+            conflicts: Vec::new(),
+        };
+
+        let new_transactions = Self::classify_transactions(pool, &transactions, &mut report).await?;
+        let new_assets = Self::classify_assets(pool, &assets, &mut report).await?;
+        let new_snapshots = Self::classify_snapshots(pool, &snapshots, &mut report).await?;
+        if dry_run {
+            return Ok(report);
+        }
+
+        report.backup_path = Some(db::backup(pool, backup_dir, "before-import").await?.display().to_string());
+
+        // Assets and snapshots are keyed by symbol and date, so only replace can overwrite them
+        let (transactions, assets, snapshots) = match strategy {
+            ImportStrategy::Append => (transactions, new_assets, new_snapshots),
+            ImportStrategy::SkipDuplicates => (new_transactions, new_assets, new_snapshots),
+            ImportStrategy::Replace => (transactions, assets, snapshots),
+        };
+        let mut tx = pool.begin().await?;
+        if strategy == ImportStrategy::Replace {
+            // Lot selections point at local transaction ids that are about to disappear
+            for table in ["lot_selections", "transactions", "assets", "portfolio_snapshots"] {
+                sqlx::query(&format!("DELETE FROM {}", table)).execute(&mut *tx).await?;
+            }
+        }
+        report.assets.written = Self::insert_assets(&mut tx, &assets).await?;
+        report.transactions.written = Self::insert_transactions(&mut tx, &transactions).await?;
+        report.snapshots.written = Self::insert_snapshots(&mut tx, &snapshots).await?;
+        tx.commit().await?;
+
+        Ok(report)
+    }
+
+    /// Every transaction as the JSON backup `import_json` reads: `TransactionExport` plus the asset's name and type.
+    pub async fn export_json(pool: &SqlitePool) -> Result<String, Box<dyn std::error::Error>> {
+        let rows = sqlx::query_as::<_, TransactionExport>(
+            "SELECT transaction_date, asset_type, symbol, transaction_type, quantity, price, total_value, fees, currency,
+                    broker, notes, withholding_tax
+             FROM transactions",
+        )
+        .fetch_all(pool)
+        .await?;
+
+        // Get asset names for export too
+        let assets = sqlx::query_as::<_, (String, String, String)>("SELECT symbol, name, asset_type FROM assets")
+            .fetch_all(pool)
+            .await
+            .unwrap_or_default();
+        let asset_map: HashMap<String, (String, String)> = assets.into_iter().map(|(s, n, at)| (s, (n, at))).collect();
+
+        let enhanced: Vec<serde_json::Value> = rows
+            .iter()
+            .map(|t| {
+                let (name, asset_type) = asset_map
+                    .get(&t.symbol)
+                    .cloned()
+                    .unwrap_or_else(|| (t.symbol.clone(), t.asset_type.clone()));
+                serde_json::json!({
+                    "date": t.transaction_date,
+                    "symbol": t.symbol,
+                    "name": name,
+                    "asset_type": asset_type,
+                    "type": crate::ui_transaction_type(&t.transaction_type),
+                    "quantity": t.quantity,
+                    "price": t.price,
+                    "total": t.total_value.unwrap_or(t.quantity * t.price),
+                    "notes": t.notes,
+                    "fees": t.fees.unwrap_or(0.0),
+                    "withholding_tax": t.withholding_tax.unwrap_or(0.0),
+                    "currency": t.currency.as_deref().unwrap_or("TRY"),
+                    "broker": t.broker
+                })
+            })
+            .collect();
+
+        let export_data = serde_json::json!({
+            "transactions": enhanced,
+            "exported_at": format!("{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs())
+        });
+        Ok(serde_json::to_string_pretty(&export_data)?)
+    }
+
+    /// Imports an `export_json` file (or a bare list of its transactions).
+    pub async fn import_json(
+        pool: &SqlitePool,
+        json_data: &str,
+        strategy: ImportStrategy,
+        dry_run: bool,
+        skip_invalid: bool,
+        backup_dir: &Path,
+    ) -> Result<TransactionImportReport, Box<dyn std::error::Error>> {
+        let value: serde_json::Value = serde_json::from_str(json_data)
+            .map_err(|e| format!("Geçersiz JSON formatı. Yedek dosyası beklenen yapıda değil. Hata: {}", e))?;
+        let raw_rows = match value {
+            serde_json::Value::Array(list) => list,
+            serde_json::Value::Object(mut obj) => match obj.remove("transactions") {
+                Some(serde_json::Value::Array(list)) => list,
+                _ => return Err("Geçersiz JSON formatı: 'transactions' listesi bulunamadı".into()),
+            },
+            _ => return Err("Geçersiz JSON formatı: işlem listesi bekleniyordu".into()),
+        };
+        let rows = raw_rows.into_iter().enumerate().map(|(i, raw)| (i + 1, raw)).collect();
+        Self::import_records(pool, rows, strategy, dry_run, skip_invalid, backup_dir).await
+    }
+
+    /// Imports `TransactionRecord`-shaped rows tagged with their row number in the source file. Every
+    /// row is validated, then matched against local transactions by fingerprint, so re-importing the
+    /// same file adds nothing under `SkipDuplicates`. Rows with errors abort a real import unless
+    /// `skip_invalid` is set, in which case only the valid rows are written.
+    pub(crate) async fn import_records(
+        pool: &SqlitePool,
+        raw_rows: Vec<(usize, serde_json::Value)>,
+        strategy: ImportStrategy,
+        dry_run: bool,
+        skip_invalid: bool,
+        backup_dir: &Path,
+    ) -> Result<TransactionImportReport, Box<dyn std::error::Error>> {
+        let mut report = TransactionImportReport {
+            strategy,
+            dry_run,
+            skip_invalid,
+            backup_path: None,
+            transactions: TableImportStats { source_rows: raw_rows.len(), ..Default::default() },
+            rows: Vec::new(),
+            issues: Vec::new(),
+        };
+        let today = Local::now().date_naive();
+        let mut valid: Vec<(usize, ImportedTransaction, String)> = Vec::new();
+        for (row, raw) in raw_rows {
+            let field = |name: &str| raw.get(name).and_then(|v| v.as_str()).map(str::to_string);
+            let (date, symbol) = (field("date"), field("symbol"));
+            let issues = match serde_json::from_value::<TransactionRecord>(raw) {
+                Ok(t) => {
+                    let issues = t.validate(row, today);
+                    if !issues.iter().any(|i| i.severity == IssueSeverity::Error) {
+                        let (imported, name) = t.into_imported();
+                        valid.push((report.rows.len(), imported, name));
+                    }
+                    issues
+                }
+                Err(e) => vec![ImportIssue {
+                    row,
+                    severity: IssueSeverity::Error,
+                    field: None,
+                    message_tr: format!("Satır okunamadı: {}", e),
+                    message_en: format!("Row could not be read: {}", e),
+                }],
+            };
+            let first_error = issues.iter().find(|i| i.severity == IssueSeverity::Error);
+            if first_error.is_some() {
+                report.transactions.invalid += 1;
+            }
+            report.rows.push(ImportRow {
+                row,
+                status: if first_error.is_some() { RowStatus::Invalid } else { RowStatus::New },
+                date,
+                symbol,
+                reason: first_error.map(|i| i.message_tr.clone()),
+            });
+            report.issues.extend(issues);
+        }
+
+        let incoming: Vec<ImportedTransaction> = valid.iter().map(|(_, t, _)| t.clone()).collect();
+        let matches = Self::match_transactions(pool, &incoming).await?;
+        let stats = &mut report.transactions;
+        for ((idx, t, _), m) in valid.iter().zip(&matches) {
+            let row = &mut report.rows[*idx];
+            row.date = Some(t.transaction_date.clone());
+            row.symbol = Some(t.symbol.clone());
+            match m {
+                RowMatch::New => stats.new_rows += 1,
+                RowMatch::Duplicate => {
+                    stats.duplicates += 1;
+                    row.status = RowStatus::Duplicate;
+                    row.reason = Some(format!("Aynı işlem zaten kayıtlı: {}", t.fingerprint()));
+                }
+                RowMatch::Conflict { existing } => {
+                    stats.conflicts += 1;
+                    row.status = RowStatus::Conflict;
+                    row.reason = Some(format!("Kayıtlı: {} / Dosyada: {}", existing, t.details()));
+                }
+            }
+        }
+        if dry_run {
+            return Ok(report);
+        }
+        if report.transactions.invalid > 0 && !skip_invalid {
+            let first = report.issues.iter().find(|i| i.severity == IssueSeverity::Error);
+            return Err(format!(
+                "{} satır geçersiz (ilk hata, satır {}: {}). Yalnızca geçerli satırları almak için geçersizleri atlayın",
+                report.transactions.invalid,
+                first.map(|i| i.row).unwrap_or_default(),
+                first.map(|i| i.message_tr.as_str()).unwrap_or_default()
+            )
+            .into());
+        }
+
+        let selected: Vec<(ImportedTransaction, String)> = valid
+            .into_iter()
+            .zip(matches)
+            .filter(|(_, m)| strategy != ImportStrategy::SkipDuplicates || matches!(m, RowMatch::New))
+            .map(|((_, t, name), _)| (t, name))
+            .collect();
+
+        report.backup_path = Some(db::backup(pool, backup_dir, "before-import").await?.display().to_string());
+        let mut tx = pool.begin().await?;
+        if strategy == ImportStrategy::Replace {
+            for table in ["lot_selections", "transactions"] {
+                sqlx::query(&format!("DELETE FROM {}", table)).execute(&mut *tx).await?;
+            }
+        }
+        let rows: Vec<ImportedTransaction> = selected.iter().map(|(t, _)| t.clone()).collect();
+        report.transactions.written = Self::insert_transactions(&mut tx, &rows).await?;
+        for (t, name) in &selected {
+            // The export carries the display name and type, which win over whatever was stored
+            sqlx::query(
+                "INSERT INTO assets (symbol, name, asset_type, current_price, last_updated)
+                 VALUES (?, ?, ?, ?, datetime('now'))
+                 ON CONFLICT(symbol) DO UPDATE SET name = excluded.name, asset_type = excluded.asset_type",
+            )
+            .bind(&t.symbol)
+            .bind(name)
+            .bind(&t.asset_type)
+            .bind(t.price)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(report)
+    }
+
+    /// Detects the source schema version and reads its rows, defaulting columns it doesn't have yet.
+    async fn load_source(
+        source: &SqlitePool,
+    ) -> Result<(i64, Vec<ImportedTransaction>, Vec<ImportedAsset>, Vec<ImportedSnapshot>), Box<dyn std::error::Error>> {
+        let version = db::schema_version(source).await?;
+        if version > db::latest_version() {
+            return Err(format!(
+                "Source database schema version {} is newer than this app supports ({})",
+                version,
+                db::latest_version()
+            )
+            .into());
+        }
+
+        let mut columns: HashMap<&str, HashSet<String>> = HashMap::new();
+        for table in IMPORTED_TABLES {
+            let names: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info(?)")
+                .bind(table)
+                .fetch_all(source)
+                .await?;
+            columns.insert(table, names.into_iter().collect());
+        }
+        if columns["transactions"].is_empty() {
+            return Err("Source file is not a portfolio database (no transactions table)".into());
+        }
+        let select = |table: &str, wanted: &[&str]| {
+            let present = &columns[table];
+            let list: Vec<String> = wanted
+                .iter()
+                .map(|c| if present.contains(*c) { c.to_string() } else { format!("NULL AS {}", c) })
+                .collect();
+            format!("SELECT {} FROM {}", list.join(", "), table)
+        };
+
+        let transactions = sqlx::query_as::<_, ImportedTransaction>(&format!(
+            "{} ORDER BY transaction_date, id",
+            select(
+                "transactions",
+                &[
+                    "transaction_date", "asset_type", "symbol", "transaction_type", "quantity", "price", "total_value",
+                    "fees", "currency", "broker", "notes", "is_dividend", "withholding_tax",
+                ]
+            )
+        ))
+        .fetch_all(source)
+        .await?;
+
+        let assets = if columns["assets"].is_empty() {
+            Vec::new()
+        } else {
+            sqlx::query_as::<_, ImportedAsset>(&select(
+                "assets",
+                &["symbol", "name", "asset_type", "current_price", "day_change", "last_updated", "market", "sector", "price_source"],
+            ))
+            .fetch_all(source)
+            .await?
+        };
+
+        let snapshots = if columns["portfolio_snapshots"].is_empty() {
+            Vec::new()
+        } else {
+            sqlx::query_as::<_, ImportedSnapshot>(&format!(
+                "{} ORDER BY snapshot_date",
+                select(
+                    "portfolio_snapshots",
+                    &[
+                        "snapshot_date", "total_value_tl", "total_value_usd", "total_cost_basis", "realized_pnl",
+                        "unrealized_pnl", "cash_balance", "total_return_pct",
+                    ]
+                )
+            ))
+            .fetch_all(source)
+            .await?
+        };
+
+        Ok((version, transactions, assets, snapshots))
+    }
+
+    /// Matches incoming rows against local ones by fingerprint, one local row per incoming row, so
+    /// two identical fills on the same day stay two rows.
+    async fn match_transactions(pool: &SqlitePool, incoming: &[ImportedTransaction]) -> Result<Vec<RowMatch>, sqlx::Error> {
+        let existing = sqlx::query_as::<_, ImportedTransaction>(
+            "SELECT transaction_date, asset_type, symbol, transaction_type, quantity, price, total_value,
+                    fees, currency, broker, notes, is_dividend, withholding_tax
+             FROM transactions",
+        )
+        .fetch_all(pool)
+        .await?;
+        let mut unmatched: HashMap<String, Vec<ImportedTransaction>> = HashMap::new();
+        for t in existing {
+            unmatched.entry(t.fingerprint()).or_default().push(t);
+        }
+
+        Ok(incoming
+            .iter()
+            .map(|t| match unmatched.get_mut(&t.fingerprint()).and_then(|rows| rows.pop()) {
+                Some(local) if local.details() == t.details() => RowMatch::Duplicate,
+                Some(local) => RowMatch::Conflict { existing: local.details() },
+                None => RowMatch::New,
+            })
+            .collect())
+    }
+
+    /// Returns the rows a skip-duplicates merge would add.
+    async fn classify_transactions(
+        pool: &SqlitePool,
+        incoming: &[ImportedTransaction],
+        report: &mut DatabaseImportReport,
+    ) -> Result<Vec<ImportedTransaction>, sqlx::Error> {
+        let matches = Self::match_transactions(pool, incoming).await?;
+        let stats = &mut report.transactions;
+        stats.source_rows = incoming.len();
+        let mut new_rows = Vec::new();
+        for (t, m) in incoming.iter().zip(matches) {
+            match m {
+                RowMatch::Duplicate => stats.duplicates += 1,
+                RowMatch::Conflict { existing } => {
+                    stats.conflicts += 1;
+                    report.conflicts.push(ImportConflict {
+                        table: "transactions".to_string(),
+                        key: t.fingerprint(),
+                        existing,
+                        incoming: t.details(),
+                    });
+                }
+                RowMatch::New => {
+                    stats.new_rows += 1;
+                    new_rows.push(t.clone());
+                }
+            }
+        }
+        Ok(new_rows)
+    }
+
+    /// An asset is the same when its symbol matches; a different asset type is a conflict.
+    async fn classify_assets(
+        pool: &SqlitePool,
+        incoming: &[ImportedAsset],
+        report: &mut DatabaseImportReport,
+    ) -> Result<Vec<ImportedAsset>, sqlx::Error> {
+        let existing: HashMap<String, Option<String>> =
+            sqlx::query_as::<_, (String, Option<String>)>("SELECT symbol, asset_type FROM assets")
+                .fetch_all(pool)
+                .await?
+                .into_iter()
+                .map(|(symbol, asset_type)| (symbol.to_uppercase(), asset_type))
+                .collect();
+
+        let stats = &mut report.assets;
+        stats.source_rows = incoming.len();
+        let mut new_rows = Vec::new();
+        for a in incoming {
+            match existing.get(&a.symbol.to_uppercase()) {
+                Some(local) if local == &a.asset_type => stats.duplicates += 1,
+                Some(local) => {
+                    stats.conflicts += 1;
+                    report.conflicts.push(ImportConflict {
+                        table: "assets".to_string(),
+                        key: a.symbol.clone(),
+                        existing: format!("asset_type={}", local.as_deref().unwrap_or("")),
+                        incoming: format!("asset_type={}", a.asset_type.as_deref().unwrap_or("")),
+                    });
+                }
+                None => {
+                    stats.new_rows += 1;
+                    new_rows.push(a.clone());
+                }
+            }
+        }
+        Ok(new_rows)
+    }
+
+    /// Snapshots are keyed by date; differing totals on the same date are a conflict.
+    async fn classify_snapshots(
+        pool: &SqlitePool,
+        incoming: &[ImportedSnapshot],
+        report: &mut DatabaseImportReport,
+    ) -> Result<Vec<ImportedSnapshot>, sqlx::Error> {
+        let existing: HashMap<String, (f64, f64)> =
+            sqlx::query_as::<_, (String, f64, f64)>("SELECT snapshot_date, total_value_tl, total_value_usd FROM portfolio_snapshots")
+                .fetch_all(pool)
+                .await?
+                .into_iter()
+                .map(|(date, tl, usd)| (date, (tl, usd)))
+                .collect();
+
+        let stats = &mut report.snapshots;
+        stats.source_rows = incoming.len();
+        let mut new_rows = Vec::new();
+        for s in incoming {
+            match existing.get(&s.snapshot_date) {
+                Some(&(tl, usd)) if (tl - s.total_value_tl).abs() < 0.005 && (usd - s.total_value_usd).abs() < 0.005 => {
+                    stats.duplicates += 1
+                }
+                Some(&(tl, usd)) => {
+                    stats.conflicts += 1;
+                    report.conflicts.push(ImportConflict {
+                        table: "portfolio_snapshots".to_string(),
+                        key: s.snapshot_date.clone(),
+                        existing: format!("total_value_tl={:.2} total_value_usd={:.2}", tl, usd),
+                        incoming: format!("total_value_tl={:.2} total_value_usd={:.2}", s.total_value_tl, s.total_value_usd),
+                    });
+                }
+                None => {
+                    stats.new_rows += 1;
+                    new_rows.push(s.clone());
+                }
+            }
+        }
+        Ok(new_rows)
+    }
+
+    async fn insert_transactions(conn: &mut SqliteConnection, rows: &[ImportedTransaction]) -> Result<usize, sqlx::Error> {
+        for t in rows {
+            let tx_type = crate::db_transaction_type(crate::ui_transaction_type(&t.transaction_type));
+            // Legacy rows may carry Turkish day-first dates; unreadable ones are kept verbatim
+            let date = lots::normalize_date(&t.transaction_date).unwrap_or_else(|| t.transaction_date.clone());
+            sqlx::query(
+                "INSERT INTO transactions (transaction_date, asset_type, symbol, transaction_type, quantity, price, total_value,
+                    fees, currency, broker, notes, is_dividend, withholding_tax)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&date)
+            .bind(&t.asset_type)
+            .bind(&t.symbol)
+            .bind(tx_type)
+            .bind(t.quantity)
+            .bind(t.price)
+            .bind(t.total_value.unwrap_or(t.quantity * t.price))
+            .bind(t.fees.unwrap_or(0.0))
+            .bind(t.currency.as_deref().unwrap_or("TRY"))
+            .bind(&t.broker)
+            .bind(&t.notes)
+            .bind(t.is_dividend.unwrap_or(tx_type == "DIVIDEND"))
+            .bind(t.withholding_tax.unwrap_or(0.0))
+            .execute(&mut *conn)
+            .await?;
+
+            // Symbols the source had no assets row for still need one to show up in holdings
+            sqlx::query(
+                "INSERT OR IGNORE INTO assets (symbol, name, asset_type, current_price, day_change)
+                 VALUES (?, ?, ?, ?, 0)",
+            )
+            .bind(&t.symbol)
+            .bind(&t.symbol)
+            .bind(&t.asset_type)
+            .bind(t.price)
+            .execute(&mut *conn)
+            .await?;
+        }
+        Ok(rows.len())
+    }
+
+    async fn insert_assets(conn: &mut SqliteConnection, rows: &[ImportedAsset]) -> Result<usize, sqlx::Error> {
+        for a in rows {
+            sqlx::query(
+                "INSERT INTO assets (symbol, name, asset_type, current_price, day_change, last_updated, market, sector, price_source)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&a.symbol)
+            .bind(&a.name)
+            .bind(&a.asset_type)
+            .bind(a.current_price)
+            .bind(a.day_change.unwrap_or(0.0))
+            .bind(&a.last_updated)
+            .bind(&a.market)
+            .bind(&a.sector)
+            .bind(&a.price_source)
+            .execute(&mut *conn)
+            .await?;
+        }
+        Ok(rows.len())
+    }
+
+    async fn insert_snapshots(conn: &mut SqliteConnection, rows: &[ImportedSnapshot]) -> Result<usize, sqlx::Error> {
+        for s in rows {
+            sqlx::query(
+                "INSERT INTO portfolio_snapshots (snapshot_date, total_value_tl, total_value_usd, total_cost_basis, realized_pnl,
+                    unrealized_pnl, cash_balance, total_return_pct)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&s.snapshot_date)
+            .bind(s.total_value_tl)
+            .bind(s.total_value_usd)
+            .bind(s.total_cost_basis.unwrap_or(0.0))
+            .bind(s.realized_pnl.unwrap_or(0.0))
+            .bind(s.unrealized_pnl.unwrap_or(0.0))
+            .bind(s.cash_balance.unwrap_or(0.0))
+            .bind(s.total_return_pct)
+            .execute(&mut *conn)
+            .await?;
+        }
+        Ok(rows.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::path::PathBuf;
+
+    async fn memory_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        db::create_schema(&pool).await.unwrap();
+        pool
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("importer-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    async fn transactions(pool: &SqlitePool) -> Vec<ImportedTransaction> {
+        sqlx::query_as(
+            "SELECT transaction_date, asset_type, symbol, transaction_type, quantity, price, total_value, fees, currency,
+                    broker, notes, is_dividend, withholding_tax
+             FROM transactions ORDER BY id",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap()
+    }
+
+    async fn insert(pool: &SqlitePool, asset_type: &str, symbol: &str, name: &str, kind: &str, quantity: f64, price: f64) {
+        sqlx::query(
+            "INSERT INTO transactions (transaction_date, asset_type, symbol, transaction_type, quantity, price, total_value,
+                                       fees, currency, broker, notes, is_dividend, withholding_tax)
+             VALUES ('2024-02-01', ?, ?, ?, ?, ?, ?, 1.5, 'TRY', 'Ziraat', 'not', ?, 0)",
+        )
+        .bind(asset_type)
+        .bind(symbol)
+        .bind(kind)
+        .bind(quantity)
+        .bind(price)
+        .bind(quantity * price)
+        .bind(kind == "DIVIDEND")
+        .execute(pool)
+        .await
+        .unwrap();
+        sqlx::query("INSERT OR IGNORE INTO assets (symbol, name, asset_type, current_price) VALUES (?, ?, ?, ?)")
+            .bind(symbol)
+            .bind(name)
+            .bind(asset_type)
+            .bind(price)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    fn trade(date: &str, symbol: &str, kind: &str, quantity: f64, price: f64) -> ImportedTransaction {
+        ImportedTransaction {
+            transaction_date: date.to_string(),
+            asset_type: "hisse".to_string(),
+            symbol: symbol.to_string(),
+            transaction_type: kind.to_string(),
+            quantity,
+            price,
+            total_value: Some(quantity * price),
+            fees: Some(0.0),
+            currency: Some("TRY".to_string()),
+            broker: None,
+            notes: None,
+            is_dividend: Some(false),
+            withholding_tax: Some(0.0),
+        }
+    }
+
+    #[test]
+    fn fingerprint_ignores_date_layout_and_type_labels() {
+        let base = trade("2024-01-31", "THYAO", "BUY", 100.0, 250.5).fingerprint();
+        for (date, symbol, kind) in [
+            ("31.01.2024", "THYAO", "BUY"),
+            ("31/01/2024", " thyao ", "Alış"),
+            ("2024-01-31 14:30", "THYAO", "buy"),
+            ("2024-01-31T10:00:00", "Thyao", "ALIŞ"),
+            ("2024-01-31", "THYAO", "alis"),
+        ] {
+            assert_eq!(trade(date, symbol, kind, 100.0, 250.5).fingerprint(), base, "{} {} {}", date, symbol, kind);
+        }
+
+        assert_ne!(trade("2024-02-01", "THYAO", "BUY", 100.0, 250.5).fingerprint(), base);
+        assert_ne!(trade("2024-01-31", "THYAO", "Satış", 100.0, 250.5).fingerprint(), base);
+        assert_ne!(trade("2024-01-31", "THYAO", "BUY", 100.0, 250.25).fingerprint(), base);
+        let mut other_broker = trade("2024-01-31", "THYAO", "BUY", 100.0, 250.5);
+        other_broker.broker = Some("Garanti".to_string());
+        assert_ne!(other_broker.fingerprint(), base);
+    }
+
+    #[tokio::test]
+    async fn identical_fills_match_one_to_one() {
+        let pool = memory_pool().await;
+        // Two identical same-day fills are stored; the file has three
+        insert(&pool, "hisse", "ASELS", "Aselsan", "BUY", 10.0, 50.0).await;
+        insert(&pool, "hisse", "ASELS", "Aselsan", "BUY", 10.0, 50.0).await;
+        let stored = transactions(&pool).await;
+        let incoming = vec![stored[0].clone(), stored[0].clone(), stored[0].clone()];
+
+        let matches = ImportService::match_transactions(&pool, &incoming).await.unwrap();
+        assert!(matches!(matches[..], [RowMatch::Duplicate, RowMatch::Duplicate, RowMatch::New]));
+
+        // A matching fill whose fees differ is a conflict, not a duplicate
+        let mut refeed = stored[0].clone();
+        refeed.fees = Some(9.0);
+        let matches = ImportService::match_transactions(&pool, &[refeed]).await.unwrap();
+        assert!(matches!(&matches[..], [RowMatch::Conflict { .. }]));
+    }
+
+    #[tokio::test]
+    async fn reimporting_the_same_file_writes_nothing() {
+        let pool = memory_pool().await;
+        let dir = scratch_dir("reimport");
+        let json = r#"{"transactions": [
+            {"date": "31.01.2024", "symbol": "THYAO", "asset_type": "hisse", "type": "buy", "quantity": 100, "price": 250.5},
+            {"date": "31.01.2024", "symbol": "THYAO", "asset_type": "hisse", "type": "buy", "quantity": 100, "price": 250.5},
+            {"date": "2024-03-01", "symbol": "THYAO", "asset_type": "hisse", "type": "sell", "quantity": 50, "price": 300}
+        ]}"#;
+
+        let first = ImportService::import_json(&pool, json, ImportStrategy::SkipDuplicates, false, false, &dir)
+            .await
+            .unwrap();
+        assert_eq!(first.transactions.written, 3);
+        assert!(first.backup_path.is_some());
+
+        let second = ImportService::import_json(&pool, json, ImportStrategy::SkipDuplicates, false, false, &dir)
+            .await
+            .unwrap();
+        assert_eq!((second.transactions.duplicates, second.transactions.written), (3, 0));
+        assert!(second.rows.iter().all(|r| r.status == RowStatus::Duplicate));
+        assert_eq!(transactions(&pool).await.len(), 3);
+
+        // Append keeps the old behaviour of adding everything again
+        let append = ImportService::import_json(&pool, json, ImportStrategy::Append, false, false, &dir)
+            .await
+            .unwrap();
+        assert_eq!(append.transactions.written, 3);
+        assert_eq!(transactions(&pool).await.len(), 6);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    fn issue<'a>(report: &'a TransactionImportReport, row: usize, field: &str) -> &'a ImportIssue {
+        report
+            .issues
+            .iter()
+            .find(|i| i.row == row && i.field.as_deref() == Some(field))
+            .unwrap_or_else(|| panic!("no {} issue on row {}: {:?}", field, row, report.issues))
+    }
+
+    #[tokio::test]
+    async fn invalid_rows_are_reported_in_both_languages() {
+        let pool = memory_pool().await;
+        let dir = scratch_dir("validation");
+        let json = r#"[
+            {"date": "-", "symbol": "THYAO", "asset_type": "hisse", "type": "buy", "quantity": 1, "price": 10},
+            {"date": "2024-01-02", "symbol": "THYAO", "asset_type": "hisse", "type": "buy", "quantity": 0, "price": 10},
+            {"date": "2024-01-03", "symbol": "THYAO", "asset_type": "hisse", "type": "transfer", "quantity": 1, "price": 10},
+            {"date": "2024-01-04", "symbol": " asels", "asset_type": "hisse", "type": "Alış", "quantity": 5, "price": 40},
+            {"date": "05.01.2024", "symbol": "THYAO", "asset_type": "hisse", "type": "SATIŞ", "quantity": 1, "price": 12}
+        ]"#;
+
+        let report = ImportService::import_json(&pool, json, ImportStrategy::SkipDuplicates, true, false, &dir)
+            .await
+            .unwrap();
+        let statuses: Vec<RowStatus> = report.rows.iter().map(|r| r.status).collect();
+        assert_eq!(statuses, [RowStatus::Invalid, RowStatus::Invalid, RowStatus::Invalid, RowStatus::New, RowStatus::New]);
+        assert_eq!((report.transactions.invalid, report.transactions.new_rows), (3, 2));
+
+        let date = issue(&report, 1, "date");
+        assert_eq!(date.severity, IssueSeverity::Error);
+        assert_eq!((date.message_tr.as_str(), date.message_en.as_str()), ("Tarih eksik", "Date is missing"));
+        let quantity = issue(&report, 2, "quantity");
+        assert_eq!(quantity.severity, IssueSeverity::Error);
+        assert_eq!(quantity.message_tr, "Miktar sıfırdan büyük olmalı: 0");
+        assert_eq!(quantity.message_en, "Quantity must be positive: 0");
+        let kind = issue(&report, 3, "type");
+        assert_eq!(kind.severity, IssueSeverity::Error);
+        assert!(kind.message_tr.contains("'transfer'") && kind.message_en.contains("'transfer'"));
+        assert_eq!(report.rows[2].reason.as_deref(), Some(kind.message_tr.as_str()));
+        let symbol = issue(&report, 4, "symbol");
+        assert_eq!(symbol.severity, IssueSeverity::Warning);
+        assert_eq!(symbol.message_en, "Symbol normalized to 'ASELS'");
+        assert_eq!(report.rows[3].symbol.as_deref(), Some("ASELS"));
+
+        // A real import refuses invalid rows unless told to skip them
+        let refused = ImportService::import_json(&pool, json, ImportStrategy::SkipDuplicates, false, false, &dir).await;
+        assert!(refused.unwrap_err().to_string().starts_with("3 satır geçersiz (ilk hata, satır 1: Tarih eksik)"));
+        assert!(transactions(&pool).await.is_empty());
+
+        let skipped = ImportService::import_json(&pool, json, ImportStrategy::SkipDuplicates, false, true, &dir)
+            .await
+            .unwrap();
+        assert_eq!((skipped.transactions.invalid, skipped.transactions.written), (3, 2));
+        let stored: Vec<(String, String, String)> = transactions(&pool)
+            .await
+            .into_iter()
+            .map(|t| (t.transaction_date, t.symbol, t.transaction_type))
+            .collect();
+        assert_eq!(
+            stored,
+            [
+                ("2024-01-04".to_string(), "ASELS".to_string(), "BUY".to_string()),
+                ("2024-01-05".to_string(), "THYAO".to_string(), "SELL".to_string()),
+            ]
+        );
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn json_export_round_trips_every_asset_type() {
+        let source = memory_pool().await;
+        for (i, asset_type) in KNOWN_ASSET_TYPES.iter().enumerate() {
+            let symbol = format!("SYM{}", i);
+            insert(&source, asset_type, &symbol, &format!("{} varlığı", asset_type), "BUY", 10.0, 2.5 + i as f64).await;
+        }
+        insert(&source, "altin", "BILEZIK", "22 Ayar Bilezik", "SELL", 2.0, 3000.0).await;
+        insert(&source, "hisse", "SYM0", "hisse varlığı", "DIVIDEND", 10.0, 1.2).await;
+
+        let json = ImportService::export_json(&source).await.unwrap();
+        let dir = scratch_dir("round-trip");
+        let target = memory_pool().await;
+        let report = ImportService::import_json(&target, &json, ImportStrategy::SkipDuplicates, false, false, &dir)
+            .await
+            .unwrap();
+
+        assert_eq!(report.transactions.invalid, 0, "{:?}", report.issues);
+        assert_eq!(report.transactions.written, KNOWN_ASSET_TYPES.len() + 2);
+        assert_eq!(transactions(&target).await, transactions(&source).await);
+        let name: String = sqlx::query_scalar("SELECT name FROM assets WHERE symbol = 'BILEZIK'")
+            .fetch_one(&target)
+            .await
+            .unwrap();
+        assert_eq!(name, "22 Ayar Bilezik");
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    // A pre-versioning portfolio.db on disk, as `migrate_database` opens it
+    async fn v0_source(dir: &Path) -> PathBuf {
+        let path = dir.join("legacy.db");
+        let source = SqlitePool::connect_with(SqliteConnectOptions::new().filename(&path).create_if_missing(true))
+            .await
+            .unwrap();
+        sqlx::raw_sql(include_str!("../tests/fixtures/schema_v0.sql"))
+            .execute(&source)
+            .await
+            .unwrap();
+        source.close().await;
+        path
+    }
+
+    // One row of the fixture unchanged, one with a different fee, one unrelated; AAK is typed differently
+    // and the 2024-06-12 snapshot disagrees. File-backed, since the backup is a `VACUUM INTO` copy
+    async fn local_portfolio(dir: &Path) -> SqlitePool {
+        let pool = SqlitePool::connect_with(SqliteConnectOptions::new().filename(dir.join("portfolio.db")).create_if_missing(true))
+            .await
+            .unwrap();
+        db::create_schema(&pool).await.unwrap();
+        sqlx::raw_sql(
+            "INSERT INTO transactions (id, transaction_date, asset_type, symbol, transaction_type, quantity, price, total_value, fees, is_dividend)
+             VALUES (1, '2024-01-15', 'hisse', 'THYAO', 'BUY', 100, 250.5, 25050, 12.5, 0),
+                    (2, '2024-06-10', 'hisse', 'THYAO', 'SELL', 40, 310, 12400, 9, 0),
+                    (3, '2024-02-20', 'hisse', 'GARAN', 'BUY', 50, 60, 3000, 0, 0);
+             INSERT INTO lot_selections (sell_transaction_id, buy_transaction_id, quantity) VALUES (2, 1, 40);
+             INSERT INTO assets (symbol, name, asset_type, current_price)
+             VALUES ('THYAO', 'Türk Hava Yolları', 'hisse', 300), ('AAK', 'AAK', 'hisse', 1.5), ('GARAN', 'Garanti', 'hisse', 65);
+             INSERT INTO portfolio_snapshots (snapshot_date, total_value_tl, total_value_usd) VALUES ('2024-06-12', 19000, 590);",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        pool
+    }
+
+    async fn count(pool: &SqlitePool, table: &str) -> i64 {
+        sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn migrating_a_v0_database_merges_only_new_rows() {
+        let dir = scratch_dir("migrate-merge");
+        let source = v0_source(&dir).await;
+        let pool = local_portfolio(&dir).await;
+
+        let report = ImportService::migrate_database(&pool, &source, ImportStrategy::SkipDuplicates, false, &dir.join("backups"))
+            .await
+            .unwrap();
+        assert_eq!(report.source_version, 0);
+        assert_eq!(
+            (report.transactions.source_rows, report.transactions.duplicates, report.transactions.conflicts, report.transactions.new_rows),
+            (3, 1, 1, 1)
+        );
+        assert_eq!(report.transactions.written, 1);
+        assert_eq!((report.assets.duplicates, report.assets.conflicts, report.assets.written), (1, 1, 0));
+        assert_eq!((report.snapshots.conflicts, report.snapshots.written), (1, 0));
+        assert!(std::path::Path::new(report.backup_path.as_deref().unwrap()).is_file());
+
+        let conflicts: Vec<(&str, &str)> = report.conflicts.iter().map(|c| (c.table.as_str(), c.key.as_str())).collect();
+        assert_eq!(
+            conflicts,
+            [
+                ("transactions", "2024-06-10|THYAO|SELL|40.00000000|310.00000000|"),
+                ("assets", "AAK"),
+                ("portfolio_snapshots", "2024-06-12"),
+            ]
+        );
+        assert_eq!(report.conflicts[0].existing, "asset_type=hisse fees=9.00 withholding_tax=0.00 notes=");
+        assert_eq!(report.conflicts[0].incoming, "asset_type=hisse fees=6.20 withholding_tax=0.00 notes=");
+
+        // The new AAK buy is added; conflicting local rows are kept as they were
+        assert_eq!(count(&pool, "transactions").await, 4);
+        let fee: f64 = sqlx::query_scalar("SELECT fees FROM transactions WHERE id = 2").fetch_one(&pool).await.unwrap();
+        assert_eq!(fee, 9.0);
+        let aak: String = sqlx::query_scalar("SELECT asset_type FROM assets WHERE symbol = 'AAK'").fetch_one(&pool).await.unwrap();
+        assert_eq!(aak, "hisse");
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn migrating_with_append_keeps_duplicates() {
+        let dir = scratch_dir("migrate-append");
+        let source = v0_source(&dir).await;
+        let pool = local_portfolio(&dir).await;
+
+        let report = ImportService::migrate_database(&pool, &source, ImportStrategy::Append, false, &dir.join("backups"))
+            .await
+            .unwrap();
+        assert_eq!(report.transactions.written, 3);
+        // Assets and snapshots are keyed, so append only adds what is missing
+        assert_eq!((report.assets.written, report.snapshots.written), (0, 0));
+        assert_eq!(count(&pool, "transactions").await, 6);
+        assert_eq!(count(&pool, "portfolio_snapshots").await, 1);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn migrating_with_replace_swaps_in_the_source() {
+        let dir = scratch_dir("migrate-replace");
+        let source = v0_source(&dir).await;
+        let pool = local_portfolio(&dir).await;
+
+        let report = ImportService::migrate_database(&pool, &source, ImportStrategy::Replace, false, &dir.join("backups"))
+            .await
+            .unwrap();
+        assert_eq!((report.transactions.written, report.assets.written, report.snapshots.written), (3, 2, 1));
+
+        let symbols: Vec<String> = sqlx::query_scalar("SELECT symbol FROM transactions ORDER BY transaction_date")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(symbols, ["THYAO", "AAK", "THYAO"]);
+        let fee: f64 = sqlx::query_scalar("SELECT fees FROM transactions WHERE transaction_type = 'SELL'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(fee, 6.2);
+        let aak: String = sqlx::query_scalar("SELECT asset_type FROM assets WHERE symbol = 'AAK'").fetch_one(&pool).await.unwrap();
+        assert_eq!(aak, "fon");
+        let total: f64 = sqlx::query_scalar("SELECT total_value_tl FROM portfolio_snapshots").fetch_one(&pool).await.unwrap();
+        assert_eq!(total, 19335.0);
+        // Selections pointed at the replaced local ids
+        assert_eq!(count(&pool, "lot_selections").await, 0);
+        assert_eq!(count(&pool, "assets").await, 2);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn migration_dry_run_writes_nothing() {
+        let dir = scratch_dir("migrate-dry-run");
+        let source = v0_source(&dir).await;
+        let pool = local_portfolio(&dir).await;
+
+        for strategy in [ImportStrategy::SkipDuplicates, ImportStrategy::Replace] {
+            let report = ImportService::migrate_database(&pool, &source, strategy, true, &dir.join("backups"))
+                .await
+                .unwrap();
+            assert_eq!(report.transactions.new_rows, 1);
+            assert_eq!(report.conflicts.len(), 3);
+            assert_eq!(report.transactions.written + report.assets.written + report.snapshots.written, 0);
+            assert!(report.backup_path.is_none());
+        }
+        assert_eq!(count(&pool, "transactions").await, 3);
+        assert_eq!(count(&pool, "lot_selections").await, 1);
+        assert!(!dir.join("backups").exists());
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn migration_rejects_a_newer_schema() {
+        let dir = scratch_dir("migrate-newer");
+        let path = dir.join("future.db");
+        let source = SqlitePool::connect_with(SqliteConnectOptions::new().filename(&path).create_if_missing(true))
+            .await
+            .unwrap();
+        db::create_schema(&source).await.unwrap();
+        sqlx::query("INSERT INTO schema_version (version, name, applied_at) VALUES (?, 'future', '2030-01-01')")
+            .bind(db::latest_version() + 1)
+            .execute(&source)
+            .await
+            .unwrap();
+        source.close().await;
+
+        let pool = local_portfolio(&dir).await;
+        let err = ImportService::migrate_database(&pool, &path, ImportStrategy::SkipDuplicates, false, &dir.join("backups"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("newer than this app supports"), "{}", err);
+        assert_eq!(count(&pool, "transactions").await, 3);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn json_export_round_trips_symbols_the_app_creates() {
+        let source = memory_pool().await;
+        // A manual asset keeps spaces and Turkish letters; canlidoviz codes may be generated from the name
+        insert(&source, "manuel", "KADIKÖY DAİRE", "Kadıköy'deki daire", "BUY", 1.0, 4_500_000.0).await;
+        insert(&source, "altin", "ÇEYRE", "Çeyrek Altın", "BUY", 4.0, 5200.0).await;
+        insert(&source, "kripto", "BTC-C", "Bitcoin", "BUY", 0.01, 3_400_000.0).await;
+
+        let json = ImportService::export_json(&source).await.unwrap();
+        let dir = scratch_dir("round-trip-symbols");
+        let target = memory_pool().await;
+        let report = ImportService::import_json(&target, &json, ImportStrategy::SkipDuplicates, false, false, &dir)
+            .await
+            .unwrap();
+
+        assert!(report.issues.iter().all(|i| i.field.as_deref() != Some("symbol")), "{:?}", report.issues);
+        assert_eq!(report.transactions.written, 3);
+        assert_eq!(transactions(&target).await, transactions(&source).await);
+        let name: String = sqlx::query_scalar("SELECT name FROM assets WHERE symbol = 'KADIKÖY DAİRE'")
+            .fetch_one(&target)
+            .await
+            .unwrap();
+        assert_eq!(name, "Kadıköy'deki daire");
+
+        // Only an overlong symbol is refused
+        let record: TransactionRecord = serde_json::from_value(serde_json::json!({
+            "date": "2024-01-02", "symbol": "X".repeat(MAX_SYMBOL_LEN + 1), "type": "buy", "quantity": 1, "price": 1
+        }))
+        .unwrap();
+        let issues = record.validate(1, NaiveDate::from_ymd_opt(2024, 6, 1).unwrap());
+        assert_eq!(issues.iter().filter(|i| i.field.as_deref() == Some("symbol")).count(), 1);
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -1,12 +1,29 @@
 pub mod db;
 pub mod calculator;
+pub mod corporate_actions;
+pub mod csv_io;
+pub mod funds;
+pub mod importer;
+pub mod lots;
+pub mod manual_assets;
+pub mod mapped_import;
+pub mod market_calendar;
+pub mod providers;
+pub mod returns;
 pub mod scraper;
 
 use tauri::{State, Manager};
 use sqlx::SqlitePool;
-use crate::calculator::{CalculatorService, PortfolioSummary, Holding};
-use crate::scraper::ScraperService;
-use chrono::Local;
+use crate::calculator::{CalculatorService, PortfolioSummary, Holding, SymbolLots, ClosedTradeReport, DividendReport};
+use crate::corporate_actions::{CorporateAction, CorporateActionService, NewCorporateAction};
+use crate::csv_io::{CsvOptions, CsvService};
+use crate::funds::{FundHistoryPoint, FundPerformance, FundRisk, FundService};
+use crate::importer::{DatabaseImportReport, ImportService, ImportStrategy, TransactionImportReport};
+use crate::lots::{CostBasisMethod, CostBasisSettings, LedgerTransaction, LotLedger, LotSelection, OversellWarning};
+use crate::manual_assets::{ManualAsset, ManualAssetService, ManualValuation, NewManualAsset, MANUAL_ASSET_TYPE};
+use crate::mapped_import::{ImportProfile, MappedImportService, SpreadsheetPreview};
+use crate::returns::{PeriodReturn, ReturnMetrics, ReturnService};
+use crate::scraper::{BackfillResult, ScraperService, UpdateLogEntry, UpdateReport};
 
 struct AppState {
     pool: SqlitePool,
@@ -72,6 +89,13 @@ async fn get_holdings(state: State<'_, AppState>) -> Result<Vec<Holding>, String
     Ok(holdings)
 }
 
+#[tauri::command]
+async fn get_open_lots(state: State<'_, AppState>, symbol: Option<String>) -> Result<Vec<SymbolLots>, String> {
+    CalculatorService::get_open_lots(&state.pool, symbol)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 async fn get_realized_pnl_in_range(state: State<'_, AppState>, start_date: Option<String>, end_date: Option<String>) -> Result<f64, String> {
     CalculatorService::get_realized_pnl_in_range(&state.pool, start_date, end_date)
@@ -79,32 +103,193 @@ async fn get_realized_pnl_in_range(state: State<'_, AppState>, start_date: Optio
         .map_err(|e| e.to_string())
 }
 #[tauri::command]
+async fn get_closed_trades(state: State<'_, AppState>, symbol: Option<String>, asset_type: Option<String>, start_date: Option<String>, end_date: Option<String>) -> Result<ClosedTradeReport, String> {
+    CalculatorService::get_closed_trades(&state.pool, symbol, asset_type, start_date, end_date)
+        .await
+        .map_err(|e| e.to_string())
+}
+#[tauri::command]
+async fn get_dividend_report(state: State<'_, AppState>, year: Option<i32>, symbol: Option<String>) -> Result<DividendReport, String> {
+    CalculatorService::get_dividend_report(&state.pool, year, symbol)
+        .await
+        .map_err(|e| e.to_string())
+}
+#[tauri::command]
 async fn get_range_performance(state: State<'_, AppState>, start_date: Option<String>, end_date: Option<String>) -> Result<(f64, f64), String> {
     CalculatorService::get_range_performance(&state.pool, start_date, end_date)
         .await
         .map_err(|e| e.to_string())
 }
+// Time-weighted and money-weighted (XIRR) returns; symbol = None for the whole portfolio
 #[tauri::command]
-async fn update_market_data(state: State<'_, AppState>, update_type: String, force: bool) -> Result<(), String> {
-    let scraper = ScraperService::new();
-    let pool = &state.pool;
-    
-    match update_type.as_str() {
-        "general" => scraper.update_general_assets(pool, force).await.map_err(|e| e.to_string()),
-        "tefas" => scraper.update_tefas_funds(pool, force).await.map_err(|e| e.to_string()),
-        "all" => {
-            // First general then tefas
-            let _ = scraper.update_general_assets(pool, force).await;
-            scraper.update_tefas_funds(pool, force).await.map_err(|e| e.to_string())
-        },
-        _ => Err(format!("Unknown update type: {}", update_type))
+async fn get_returns(state: State<'_, AppState>, symbol: Option<String>, start_date: Option<String>, end_date: Option<String>) -> Result<Option<ReturnMetrics>, String> {
+    ReturnService::get_returns(&state.pool, symbol, start_date, end_date)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_period_returns(state: State<'_, AppState>, symbol: Option<String>) -> Result<Vec<PeriodReturn>, String> {
+    ReturnService::get_period_returns(&state.pool, symbol)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_cost_basis_settings(state: State<'_, AppState>) -> Result<CostBasisSettings, String> {
+    CostBasisSettings::load(&state.pool)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// asset_type = None sets the portfolio default; method = None clears an asset type override
+#[tauri::command]
+async fn set_cost_basis_method(state: State<'_, AppState>, method: Option<String>, asset_type: Option<String>) -> Result<(), String> {
+    let method = match method {
+        Some(m) => Some(CostBasisMethod::parse(&m).ok_or_else(|| format!("Unknown cost basis method: {}", m))?),
+        None => None,
+    };
+    if method.is_none() && asset_type.is_none() {
+        return Err("Portfolio default cost basis method cannot be removed".to_string());
     }
+    CostBasisSettings::save(&state.pool, asset_type.as_deref(), method)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_lot_selections(state: State<'_, AppState>, sell_transaction_id: i64) -> Result<Vec<LotSelection>, String> {
+    sqlx::query_as::<_, LotSelection>(
+        "SELECT buy_transaction_id, quantity FROM lot_selections WHERE sell_transaction_id = ? ORDER BY id ASC"
+    )
+    .bind(sell_transaction_id)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_lot_selections(state: State<'_, AppState>, sell_transaction_id: i64, selections: Vec<LotSelection>) -> Result<(), String> {
+    LotSelection::save_for_sell(&state.pool, sell_transaction_id, &selections)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_corporate_actions(state: State<'_, AppState>, symbol: Option<String>) -> Result<Vec<CorporateAction>, String> {
+    CorporateActionService::list(&state.pool, symbol)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn add_corporate_action(state: State<'_, AppState>, action: NewCorporateAction) -> Result<String, String> {
+    CorporateActionService::add(&state.pool, action)
+        .await
+        .map(|id| id.to_string())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn delete_corporate_action(state: State<'_, AppState>, id: i64) -> Result<(), String> {
+    CorporateActionService::delete(&state.pool, id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_manual_assets(state: State<'_, AppState>) -> Result<Vec<ManualAsset>, String> {
+    ManualAssetService::list(&state.pool)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn add_manual_asset(state: State<'_, AppState>, asset: NewManualAsset) -> Result<(), String> {
+    ManualAssetService::add(&state.pool, asset)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn delete_manual_asset(state: State<'_, AppState>, symbol: String) -> Result<(), String> {
+    ManualAssetService::delete(&state.pool, &symbol)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_manual_valuations(state: State<'_, AppState>, symbol: String) -> Result<Vec<ManualValuation>, String> {
+    ManualAssetService::get_valuations(&state.pool, &symbol)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_manual_valuation(state: State<'_, AppState>, symbol: String, date: String, price: f64) -> Result<(), String> {
+    ManualAssetService::set_valuation(&state.pool, &symbol, &date, price)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn delete_manual_valuation(state: State<'_, AppState>, symbol: String, date: String) -> Result<(), String> {
+    ManualAssetService::delete_valuation(&state.pool, &symbol, &date)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn update_market_data(state: State<'_, AppState>, update_type: String, force: bool) -> Result<UpdateReport, String> {
+    ScraperService::new()
+        .update_market_data(&state.pool, &update_type, force)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn refresh_symbols(state: State<'_, AppState>, symbols: Vec<String>) -> Result<UpdateReport, String> {
+    ScraperService::new()
+        .refresh_symbols(&state.pool, symbols)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_update_log(state: State<'_, AppState>, limit: Option<i64>) -> Result<Vec<UpdateLogEntry>, String> {
+    ScraperService::get_update_log(&state.pool, limit.unwrap_or(50))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_fund_history(state: State<'_, AppState>, code: String) -> Result<Vec<FundHistoryPoint>, String> {
+    FundService::get_history(&state.pool, &code).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_fund_performance(state: State<'_, AppState>, code: String) -> Result<FundPerformance, String> {
+    FundService::get_performance(&state.pool, &code).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_fund_risk(state: State<'_, AppState>, code: String) -> Result<FundRisk, String> {
+    FundService::get_risk(&state.pool, &code).await.map_err(|e| e.to_string())
+}
+
+// symbols = None backfills every fund currently held
+#[tauri::command]
+async fn backfill_tefas_history(state: State<'_, AppState>, symbols: Option<Vec<String>>) -> Result<Vec<BackfillResult>, String> {
+    ScraperService::new()
+        .backfill_tefas_history(&state.pool, symbols)
+        .await
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 async fn clear_database(state: State<'_, AppState>) -> Result<(), String> {
     let pool = &state.pool;
-    let tables = ["transactions", "assets", "portfolio_snapshots", "asset_price_history", "tefas_daily_tracking"];
+    let tables = ["transactions", "lot_selections", "corporate_actions", "assets", "portfolio_snapshots", "asset_price_history", "price_backfill_progress", "fund_detail_cache", "tefas_daily_tracking", "update_log"];
     for table in tables {
         sqlx::query(&format!("DELETE FROM {}", table))
             .execute(pool)
@@ -114,19 +299,24 @@ async fn clear_database(state: State<'_, AppState>) -> Result<(), String> {
     Ok(())
 }
 
-#[derive(serde::Serialize, serde::Deserialize, sqlx::FromRow)]
-struct TransactionExport {
-    transaction_date: String,
-    asset_type: String,
-    symbol: String,
-    transaction_type: String,
-    quantity: f64,
-    price: f64,
-    total_value: Option<f64>,
-    fees: Option<f64>,
-    currency: Option<String>,
-    broker: Option<String>,
-    notes: Option<String>,
+#[tauri::command]
+async fn migrate_database(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    source_path: String,
+    strategy: ImportStrategy,
+    dry_run: Option<bool>,
+) -> Result<DatabaseImportReport, String> {
+    let backup_dir = app_handle.path().app_data_dir().map_err(|e| e.to_string())?.join("backups");
+    ImportService::migrate_database(
+        &state.pool,
+        std::path::Path::new(&source_path),
+        strategy,
+        dry_run.unwrap_or(false),
+        &backup_dir,
+    )
+    .await
+    .map_err(|e| e.to_string())
 }
 
 // Transaction for frontend display
@@ -137,17 +327,42 @@ struct TransactionForUI {
     symbol: String,
     name: String,
     #[serde(rename = "type")]
-    transaction_type: String, // "buy" or "sell"
+    transaction_type: String, // "buy", "sell" or "dividend"
     quantity: f64,
     price: f64,
     total: f64,
     notes: Option<String>,
+    #[serde(default)]
+    fees: Option<f64>,
+    #[serde(default)]
+    withholding_tax: Option<f64>,
+}
+
+// UI transaction kind -> stored transaction_type
+pub(crate) fn db_transaction_type(ui_type: &str) -> &'static str {
+    match ui_type {
+        "buy" => "BUY",
+        "dividend" => "DIVIDEND",
+        _ => "SELL",
+    }
+}
+
+// Stored transaction_type (incl. legacy Turkish labels) -> UI transaction kind
+pub(crate) fn ui_transaction_type(db_type: &str) -> &'static str {
+    let lower = db_type.to_lowercase();
+    if lower.contains("buy") || lower.contains("alış") {
+        "buy"
+    } else if lower.contains("dividend") || lower.contains("temettü") {
+        "dividend"
+    } else {
+        "sell"
+    }
 }
 
 #[tauri::command]
 async fn get_transactions(state: State<'_, AppState>) -> Result<Vec<TransactionForUI>, String> {
-    let rows = sqlx::query_as::<_, (i64, String, String, String, f64, f64, Option<f64>, Option<String>)>(
-        "SELECT t.id, t.transaction_date, t.symbol, t.transaction_type, t.quantity, t.price, t.total_value, t.notes FROM transactions t ORDER BY t.transaction_date DESC"
+    let rows = sqlx::query_as::<_, (i64, String, String, String, f64, f64, Option<f64>, Option<String>, Option<f64>, Option<f64>)>(
+        "SELECT t.id, t.transaction_date, t.symbol, t.transaction_type, t.quantity, t.price, t.total_value, t.notes, t.fees, t.withholding_tax FROM transactions t ORDER BY t.transaction_date DESC"
     )
     .fetch_all(&state.pool)
     .await
@@ -161,33 +376,89 @@ async fn get_transactions(state: State<'_, AppState>) -> Result<Vec<TransactionF
     
     let asset_map: std::collections::HashMap<String, String> = assets.into_iter().collect();
     
-    let transactions: Vec<TransactionForUI> = rows.into_iter().map(|(id, date, symbol, tx_type, qty, price, total, notes)| {
+    let transactions: Vec<TransactionForUI> = rows.into_iter().map(|(id, date, symbol, tx_type, qty, price, total, notes, fees, withholding_tax)| {
         let name = asset_map.get(&symbol).cloned().unwrap_or_else(|| symbol.clone());
         TransactionForUI {
             id: id.to_string(),
             date,
             symbol,
             name,
-            transaction_type: if tx_type.to_lowercase().contains("buy") || tx_type.to_lowercase().contains("alış") { "buy".to_string() } else { "sell".to_string() },
+            transaction_type: ui_transaction_type(&tx_type).to_string(),
             quantity: qty,
             price,
             total: total.unwrap_or(qty * price),
             notes,
+            fees,
+            withholding_tax,
         }
     }).collect();
     
     Ok(transactions)
 }
 
+// Rejects a change that would sell more than was held as of the sell's date, unless explicitly allowed
+async fn check_oversell(pool: &SqlitePool, candidate: LedgerTransaction, allow_oversell: Option<bool>) -> Result<(), String> {
+    if allow_oversell.unwrap_or(false) {
+        return Ok(());
+    }
+    let warnings = LotLedger::find_new_oversells(pool, candidate)
+        .await
+        .map_err(|e| e.to_string())?;
+    match warnings.first() {
+        Some(w) => Err(format_oversell(w)),
+        None => Ok(()),
+    }
+}
+
+fn format_oversell(w: &OversellWarning) -> String {
+    format!(
+        "Satış miktarı eldeki pozisyonu aşıyor: {} ({}) tarihinde {} adet satılıyor, {} adet eksik",
+        w.symbol, w.sell_date, w.sell_quantity, w.missing_quantity
+    )
+}
+
 #[tauri::command]
-async fn update_transaction(state: State<'_, AppState>, transaction: TransactionForUI) -> Result<(), String> {
-    sqlx::query("UPDATE transactions SET transaction_date = ?, quantity = ?, price = ?, total_value = ?, notes = ?, transaction_type = ? WHERE id = ?")
-        .bind(&transaction.date)
+async fn get_oversell_warnings(state: State<'_, AppState>) -> Result<Vec<OversellWarning>, String> {
+    CalculatorService::get_oversell_warnings(&state.pool)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn update_transaction(state: State<'_, AppState>, transaction: TransactionForUI, allow_oversell: Option<bool>) -> Result<(), String> {
+    let id: i64 = transaction.id.parse().map_err(|_| format!("Invalid transaction id: {}", transaction.id))?;
+    let date = lots::normalize_date(&transaction.date).ok_or_else(|| format!("Invalid transaction date: {}", transaction.date))?;
+    let (asset_type, symbol, fees, withholding_tax) = sqlx::query_as::<_, (String, String, Option<f64>, Option<f64>)>(
+        "SELECT asset_type, symbol, fees, withholding_tax FROM transactions WHERE id = ?"
+    )
+    .bind(id)
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    check_oversell(&state.pool, LedgerTransaction {
+        id,
+        transaction_date: date.clone(),
+        asset_type,
+        symbol,
+        transaction_type: db_transaction_type(&transaction.transaction_type).to_string(),
+        quantity: transaction.quantity,
+        price: transaction.price,
+        fees: transaction.fees.or(fees),
+        is_dividend: Some(transaction.transaction_type == "dividend"),
+        withholding_tax: transaction.withholding_tax.or(withholding_tax),
+    }, allow_oversell).await?;
+
+    sqlx::query("UPDATE transactions SET transaction_date = ?, quantity = ?, price = ?, total_value = ?, notes = ?, transaction_type = ?, is_dividend = ?, fees = COALESCE(?, fees), withholding_tax = COALESCE(?, withholding_tax) WHERE id = ?")
+        .bind(&date)
         .bind(transaction.quantity)
         .bind(transaction.price)
         .bind(transaction.total)
         .bind(&transaction.notes)
-        .bind(if transaction.transaction_type == "buy" { "BUY" } else { "SELL" })
+        .bind(db_transaction_type(&transaction.transaction_type))
+        .bind(transaction.transaction_type == "dividend")
+        .bind(transaction.fees)
+        .bind(transaction.withholding_tax)
         .bind(&transaction.id)
         .execute(&state.pool)
         .await
@@ -202,6 +473,12 @@ async fn delete_transaction(state: State<'_, AppState>, transaction_id: String)
         .execute(&state.pool)
         .await
         .map_err(|e| e.to_string())?;
+    sqlx::query("DELETE FROM lot_selections WHERE sell_transaction_id = ? OR buy_transaction_id = ?")
+        .bind(&transaction_id)
+        .bind(&transaction_id)
+        .execute(&state.pool)
+        .await
+        .map_err(|e| e.to_string())?;
     Ok(())
 }
 
@@ -212,48 +489,90 @@ struct NewTransaction {
     symbol: String,
     name: String,
     asset_type: String,
-    transaction_type: String, // "buy" or "sell"
-    quantity: f64,
-    price: f64,
+    transaction_type: String, // "buy", "sell" or "dividend"
+    quantity: f64, // for dividends: entitled share count
+    price: f64,    // for dividends: gross cash dividend per share
+    fees: Option<f64>,
+    withholding_tax: Option<f64>,
     notes: Option<String>,
+    // Specific-lot identification: BUY lots this SELL closes
+    lot_selections: Option<Vec<LotSelection>>,
 }
 
 #[tauri::command]
-async fn add_transaction(state: State<'_, AppState>, transaction: NewTransaction) -> Result<String, String> {
+async fn add_transaction(state: State<'_, AppState>, transaction: NewTransaction, allow_oversell: Option<bool>) -> Result<String, String> {
+    let is_dividend = transaction.transaction_type == "dividend";
+    let date = lots::normalize_date(&transaction.date).ok_or_else(|| format!("Invalid transaction date: {}", transaction.date))?;
+    // Manual assets are looked up by their upper-case symbol, as `add_manual_asset` stores them
+    let symbol = if transaction.asset_type == MANUAL_ASSET_TYPE {
+        transaction.symbol.trim().to_uppercase()
+    } else {
+        transaction.symbol.clone()
+    };
+    if transaction.transaction_type == "sell" {
+        check_oversell(&state.pool, LedgerTransaction {
+            id: 0,
+            transaction_date: date.clone(),
+            asset_type: transaction.asset_type.clone(),
+            symbol: symbol.clone(),
+            transaction_type: "SELL".to_string(),
+            quantity: transaction.quantity,
+            price: transaction.price,
+            fees: transaction.fees,
+            is_dividend: Some(false),
+            withholding_tax: None,
+        }, allow_oversell).await?;
+    }
+
     // Calculate total
     let total = transaction.quantity * transaction.price;
     
-    // Insert transaction
+    // The SELL and its lot selections are stored together or not at all
+    let mut tx = state.pool.begin().await.map_err(|e| e.to_string())?;
     let result = sqlx::query(
-        "INSERT INTO transactions (transaction_date, asset_type, symbol, transaction_type, quantity, price, total_value, notes, currency, is_dividend) 
-         VALUES (?, ?, ?, ?, ?, ?, ?, ?, 'TRY', 0)"
+        "INSERT INTO transactions (transaction_date, asset_type, symbol, transaction_type, quantity, price, total_value, fees, notes, currency, is_dividend, withholding_tax) 
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, 'TRY', ?, ?)"
     )
-    .bind(&transaction.date)
+    .bind(&date)
     .bind(&transaction.asset_type)
-    .bind(&transaction.symbol)
-    .bind(if transaction.transaction_type == "buy" { "BUY" } else { "SELL" })
+    .bind(&symbol)
+    .bind(db_transaction_type(&transaction.transaction_type))
     .bind(transaction.quantity)
     .bind(transaction.price)
     .bind(total)
+    .bind(transaction.fees.unwrap_or(0.0))
     .bind(&transaction.notes)
-    .execute(&state.pool)
+    .bind(is_dividend)
+    .bind(transaction.withholding_tax.unwrap_or(0.0))
+    .execute(&mut *tx)
     .await
     .map_err(|e| e.to_string())?;
     
-    // Also ensure the asset exists in assets table
+    // Also ensure the asset exists in assets table; a manual asset keeps the name it was registered with
     let _ = sqlx::query(
         "INSERT INTO assets (symbol, name, asset_type, current_price, day_change, last_updated) 
          VALUES (?, ?, ?, ?, 0, datetime('now'))
-         ON CONFLICT(symbol) DO UPDATE SET name = excluded.name"
+         ON CONFLICT(symbol) DO UPDATE SET name = excluded.name WHERE assets.asset_type IS NOT ?"
     )
-    .bind(&transaction.symbol)
+    .bind(&symbol)
     .bind(&transaction.name)
     .bind(&transaction.asset_type)
     .bind(transaction.price)
-    .execute(&state.pool)
+    .bind(MANUAL_ASSET_TYPE)
+    .execute(&mut *tx)
     .await;
     
-    Ok(result.last_insert_rowid().to_string())
+    let id = result.last_insert_rowid();
+    if let Some(selections) = &transaction.lot_selections {
+        if transaction.transaction_type == "sell" && !selections.is_empty() {
+            LotSelection::save_in(&mut tx, id, selections)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    tx.commit().await.map_err(|e| e.to_string())?;
+    
+    Ok(id.to_string())
 }
 
 // Check if symbol exists in portfolio
@@ -272,148 +591,113 @@ async fn check_symbol_in_portfolio(state: State<'_, AppState>, symbol: String) -
 
 #[tauri::command]
 async fn export_database_json(state: State<'_, AppState>) -> Result<String, String> {
-    let rows = sqlx::query_as::<_, TransactionExport>("SELECT transaction_date, asset_type, symbol, transaction_type, quantity, price, total_value, fees, currency, broker, notes FROM transactions")
-        .fetch_all(&state.pool)
+    ImportService::export_json(&state.pool)
         .await
-        .map_err(|e| e.to_string())?;
-    
-    // Get asset names for export too
-    let assets = sqlx::query_as::<_, (String, String, String)>("SELECT symbol, name, asset_type FROM assets")
-        .fetch_all(&state.pool)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn import_database_json(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    json_data: String,
+    strategy: Option<ImportStrategy>,
+    dry_run: Option<bool>,
+    skip_invalid: Option<bool>,
+) -> Result<TransactionImportReport, String> {
+    let backup_dir = app_handle.path().app_data_dir().map_err(|e| e.to_string())?.join("backups");
+    ImportService::import_json(
+        &state.pool,
+        &json_data,
+        strategy.unwrap_or(ImportStrategy::SkipDuplicates),
+        dry_run.unwrap_or(false),
+        skip_invalid.unwrap_or(false),
+        &backup_dir,
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn export_transactions_csv(state: State<'_, AppState>, path: String, options: Option<CsvOptions>) -> Result<usize, String> {
+    CsvService::export_transactions(&state.pool, std::path::Path::new(&path), &options.unwrap_or_default())
         .await
-        .unwrap_or_default();
-    
-    let asset_map: std::collections::HashMap<String, (String, String)> = assets.iter().map(|(s, n, at)| (s.clone(), (n.clone(), at.clone()))).collect();
-    
-    // Create enhanced export with names and types
-    let enhanced: Vec<serde_json::Value> = rows.iter().map(|t| {
-        let (name, asset_type) = asset_map.get(&t.symbol)
-            .cloned()
-            .unwrap_or_else(|| (t.symbol.clone(), t.asset_type.clone()));
-            
-        serde_json::json!({
-            "date": t.transaction_date,
-            "symbol": t.symbol,
-            "name": name,
-            "asset_type": asset_type,
-            "type": if t.transaction_type.to_lowercase().contains("buy") { "buy" } else { "sell" },
-            "quantity": t.quantity,
-            "price": t.price,
-            "total": t.total_value.unwrap_or(t.quantity * t.price),
-            "notes": t.notes,
-            "fees": t.fees.unwrap_or(0.0),
-            "currency": t.currency.as_deref().unwrap_or("TRY"),
-            "broker": t.broker
-        })
-    }).collect();
-    
-    let export_data = serde_json::json!({
-        "transactions": enhanced,
-        "exported_at": format!("{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs())
-    });
-    
-    serde_json::to_string_pretty(&export_data).map_err(|e| e.to_string())
+        .map_err(|e| e.to_string())
 }
 
-#[derive(serde::Deserialize)]
-struct ImportTransaction {
-    date: String,
-    symbol: String,
-    name: Option<String>,
-    asset_type: Option<String>,
-    #[serde(rename = "type")]
-    transaction_type: String,
-    quantity: f64,
-    price: f64,
-    total: Option<f64>,
-    notes: Option<String>,
-    fees: Option<f64>,
-    currency: Option<String>,
-    broker: Option<String>,
+#[tauri::command]
+async fn import_transactions_csv(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+    options: Option<CsvOptions>,
+    strategy: Option<ImportStrategy>,
+    dry_run: Option<bool>,
+    skip_invalid: Option<bool>,
+) -> Result<TransactionImportReport, String> {
+    let backup_dir = app_handle.path().app_data_dir().map_err(|e| e.to_string())?.join("backups");
+    CsvService::import_transactions(
+        &state.pool,
+        std::path::Path::new(&path),
+        &options.unwrap_or_default(),
+        strategy.unwrap_or(ImportStrategy::SkipDuplicates),
+        dry_run.unwrap_or(false),
+        skip_invalid.unwrap_or(false),
+        &backup_dir,
+    )
+    .await
+    .map_err(|e| e.to_string())
 }
 
-#[derive(serde::Deserialize)]
-struct ImportData {
-    transactions: Vec<ImportTransaction>,
+#[tauri::command]
+async fn get_import_profiles(state: State<'_, AppState>) -> Result<Vec<ImportProfile>, String> {
+    MappedImportService::list_profiles(&state.pool)
+        .await
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn import_database_json(state: State<'_, AppState>, json_data: String) -> Result<(), String> {
-    // Try to deserialize either as a list or as the full export object
-    let transactions = if let Ok(data) = serde_json::from_str::<ImportData>(&json_data) {
-        data.transactions
-    } else if let Ok(list) = serde_json::from_str::<Vec<ImportTransaction>>(&json_data) {
-        list
-    } else {
-        // Log the error for better debugging
-        let err = serde_json::from_str::<serde_json::Value>(&json_data).err();
-        return Err(format!("Geçersiz JSON formatı. Yedek dosyası beklenen yapıda değil. Hata: {:?}", err));
-    };
-    
-    let pool = &state.pool;
-    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
-    
-    for t in transactions {
-        let date = if t.date == "-" {
-            // Use current date if date is missing
-            Local::now().format("%Y-%m-%d").to_string()
-        } else {
-            t.date
-        };
-        
-        let tx_type = if t.transaction_type.to_lowercase().contains("buy") || t.transaction_type.to_lowercase().contains("alış") { "BUY" } else { "SELL" };
-        let name = t.name.unwrap_or_else(|| t.symbol.clone());
-        
-        // Infer asset type if missing
-        let asset_type = t.asset_type.unwrap_or_else(|| {
-            let sym = t.symbol.to_uppercase();
-            let n = name.to_lowercase();
-            if sym == "USD" || sym == "EUR" || sym == "GBP" || sym == "CHF" {
-                "doviz".to_string()
-            } else if sym == "GA" || sym == "CE" || sym == "ATA" || sym == "RA5" || sym == "22" || sym == "YRG" || n.contains("altın") || n.contains("bilezik") {
-                "emtia".to_string()
-            } else if sym.ends_with("-C") {
-                "kripto".to_string()
-            } else if sym.len() == 3 || (sym.len() == 4 && sym.chars().any(|c| c.is_numeric())) {
-                "fon".to_string()
-            } else {
-                "hisse".to_string()
-            }
-        });
-
-        sqlx::query("INSERT INTO transactions (transaction_date, asset_type, symbol, transaction_type, quantity, price, total_value, fees, currency, broker, notes) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
-            .bind(&date)
-            .bind(&asset_type)
-            .bind(&t.symbol)
-            .bind(tx_type)
-            .bind(t.quantity)
-            .bind(t.price)
-            .bind(t.total.unwrap_or(t.quantity * t.price))
-            .bind(t.fees.unwrap_or(0.0))
-            .bind(t.currency.unwrap_or_else(|| "TRY".to_string()))
-            .bind(t.broker)
-            .bind(t.notes)
-            .execute(&mut *tx)
-            .await
-            .map_err(|e| e.to_string())?;
-            
-        // Also ensure the asset exists in assets table
-        sqlx::query(
-            "INSERT INTO assets (symbol, name, asset_type, current_price, last_updated) 
-             VALUES (?, ?, ?, ?, datetime('now'))
-             ON CONFLICT(symbol) DO UPDATE SET name = excluded.name, asset_type = excluded.asset_type"
-        )
-        .bind(&t.symbol)
-        .bind(&name)
-        .bind(&asset_type)
-        .bind(t.price)
-        .execute(&mut *tx)
+async fn save_import_profile(state: State<'_, AppState>, profile: ImportProfile) -> Result<(), String> {
+    MappedImportService::save_profile(&state.pool, profile)
         .await
-        .map_err(|e| e.to_string())?;
-    }
-    
-    tx.commit().await.map_err(|e| e.to_string())?;
-    Ok(())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn delete_import_profile(state: State<'_, AppState>, name: String) -> Result<(), String> {
+    MappedImportService::delete_profile(&state.pool, &name)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn preview_spreadsheet(path: String, options: Option<CsvOptions>, sheet: Option<String>) -> Result<SpreadsheetPreview, String> {
+    MappedImportService::preview(std::path::Path::new(&path), &options.unwrap_or_default(), sheet.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn import_mapped_transactions(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+    profile: ImportProfile,
+    strategy: Option<ImportStrategy>,
+    dry_run: Option<bool>,
+    skip_invalid: Option<bool>,
+) -> Result<TransactionImportReport, String> {
+    let backup_dir = app_handle.path().app_data_dir().map_err(|e| e.to_string())?.join("backups");
+    MappedImportService::import(
+        &state.pool,
+        std::path::Path::new(&path),
+        &profile,
+        strategy.unwrap_or(ImportStrategy::SkipDuplicates),
+        dry_run.unwrap_or(false),
+        skip_invalid.unwrap_or(false),
+        &backup_dir,
+    )
+    .await
+    .map_err(|e| e.to_string())
 }
 
 #[derive(serde::Serialize)]
@@ -465,7 +749,8 @@ async fn fix_transaction_types(state: State<'_, AppState>) -> Result<String, Str
 
 #[tauri::command]
 async fn force_set_all_transactions_to_buy(state: State<'_, AppState>) -> Result<String, String> {
-    sqlx::query("UPDATE transactions SET transaction_type = 'BUY'")
+    // Dividends carry no shares; turning them into buys would add phantom lots on the next export/import
+    sqlx::query("UPDATE transactions SET transaction_type = 'BUY' WHERE transaction_type <> 'DIVIDEND' AND COALESCE(is_dividend, 0) = 0")
         .execute(&state.pool)
         .await
         .map_err(|e| e.to_string())?;
@@ -488,6 +773,7 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             get_summary, 
             get_holdings, 
+            get_open_lots,
             get_transactions,
             add_transaction,
             update_transaction,
@@ -498,14 +784,90 @@ pub fn run() {
             fix_transaction_types,
             force_set_all_transactions_to_buy,
             get_realized_pnl_in_range,
+            get_closed_trades,
+            get_oversell_warnings,
+            get_dividend_report,
             get_range_performance,
+            get_returns,
+            get_period_returns,
+            get_cost_basis_settings,
+            set_cost_basis_method,
+            get_lot_selections,
+            set_lot_selections,
+            get_corporate_actions,
+            add_corporate_action,
+            delete_corporate_action,
+            get_manual_assets,
+            add_manual_asset,
+            delete_manual_asset,
+            get_manual_valuations,
+            set_manual_valuation,
+            delete_manual_valuation,
             get_asset_info,
             search_assets,
             update_market_data,
+            refresh_symbols,
+            get_update_log,
+            backfill_tefas_history,
+            get_fund_history,
+            get_fund_performance,
+            get_fund_risk,
             clear_database,
+            migrate_database,
             export_database_json,
-            import_database_json
+            import_database_json,
+            export_transactions_csv,
+            import_transactions_csv,
+            get_import_profiles,
+            save_import_profile,
+            delete_import_profile,
+            preview_spreadsheet,
+            import_mapped_transactions
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    fn sell(date: &str, quantity: f64) -> LedgerTransaction {
+        LedgerTransaction {
+            id: 0,
+            transaction_date: date.to_string(),
+            asset_type: "hisse".to_string(),
+            symbol: "THYAO".to_string(),
+            transaction_type: "SELL".to_string(),
+            quantity,
+            price: 300.0,
+            fees: None,
+            is_dividend: Some(false),
+            withholding_tax: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn check_oversell_blocks_unless_allowed() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        db::create_schema(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO transactions (transaction_date, asset_type, symbol, transaction_type, quantity, price)
+             VALUES ('2024-01-02', 'hisse', 'THYAO', 'BUY', 10, 250)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert_eq!(check_oversell(&pool, sell("2024-02-01", 10.0), None).await, Ok(()));
+        let error = check_oversell(&pool, sell("2024-02-01", 12.0), Some(false)).await.unwrap_err();
+        assert_eq!(error, "Satış miktarı eldeki pozisyonu aşıyor: THYAO (2024-02-01) tarihinde 12 adet satılıyor, 2 adet eksik");
+        assert!(check_oversell(&pool, sell("2024-01-01", 1.0), None).await.is_err());
+        assert_eq!(check_oversell(&pool, sell("2024-02-01", 12.0), Some(true)).await, Ok(()));
+    }
+}
@@ -0,0 +1,819 @@
+use chrono::{Datelike, NaiveDate, NaiveTime};
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::HashMap;
+
+use crate::corporate_actions::CorporateAction;
+
+// Quantities below this are treated as fully consumed (float noise from partial sells)
+pub const QTY_EPSILON: f64 = 1e-9;
+
+// ISO and the day-first layouts Turkish spreadsheets and brokers export. Two-digit years come first
+// because %Y happily reads "24" as year 24.
+const DATE_FORMATS: [&str; 8] = ["%Y-%m-%d", "%Y/%m/%d", "%d.%m.%y", "%d.%m.%Y", "%d/%m/%y", "%d/%m/%Y", "%d-%m-%y", "%d-%m-%Y"];
+
+/// Parses a transaction date in any accepted layout, with an optional time after a space or 'T'
+/// ("2024-01-31", "2024-01-31T10:00:00+03:00", "31.01.2024 10:00", "31/01/24"). The time is ignored.
+pub fn parse_date(value: &str) -> Option<NaiveDate> {
+    let value = value.trim();
+    let (date_part, time_part) = match value.find(['T', ' ']) {
+        Some(i) => (&value[..i], Some(value[i + 1..].trim())),
+        None => (value, None),
+    };
+    if time_part.is_some_and(|t| !is_time(t)) {
+        return None;
+    }
+    DATE_FORMATS
+        .iter()
+        .filter_map(|f| NaiveDate::parse_from_str(date_part, f).ok())
+        .find(|d| d.year() >= 1900)
+}
+
+/// Canonical stored form of a transaction date: ISO `YYYY-MM-DD`.
+pub fn normalize_date(value: &str) -> Option<String> {
+    parse_date(value).map(|d| d.format("%Y-%m-%d").to_string())
+}
+
+// "10:30", "10:30:00", "10:30:00.123Z", "10:30:00+03:00"
+fn is_time(value: &str) -> bool {
+    NaiveTime::parse_and_remainder(value, "%H:%M")
+        .is_ok_and(|(_, rest)| rest.chars().all(|c| c.is_ascii_digit() || matches!(c, ':' | '.' | '+' | '-' | 'Z')))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CostBasisMethod {
+    Fifo,
+    Lifo,
+    WeightedAverage,
+    SpecificLot,
+}
+
+impl CostBasisMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CostBasisMethod::Fifo => "fifo",
+            CostBasisMethod::Lifo => "lifo",
+            CostBasisMethod::WeightedAverage => "weighted_average",
+            CostBasisMethod::SpecificLot => "specific_lot",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "fifo" => Some(CostBasisMethod::Fifo),
+            "lifo" => Some(CostBasisMethod::Lifo),
+            "weighted_average" | "average" | "avco" => Some(CostBasisMethod::WeightedAverage),
+            "specific_lot" | "specific" => Some(CostBasisMethod::SpecificLot),
+            _ => None,
+        }
+    }
+}
+
+/// Portfolio-wide cost basis method with optional per-asset-type overrides.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CostBasisSettings {
+    pub default_method: CostBasisMethod,
+    pub asset_type_methods: HashMap<String, CostBasisMethod>,
+}
+
+impl Default for CostBasisSettings {
+    fn default() -> Self {
+        Self {
+            default_method: CostBasisMethod::Fifo,
+            asset_type_methods: HashMap::new(),
+        }
+    }
+}
+
+impl CostBasisSettings {
+    const KEY: &'static str = "cost_basis_method";
+
+    pub fn method_for(&self, asset_type: &str) -> CostBasisMethod {
+        self.asset_type_methods
+            .get(asset_type)
+            .copied()
+            .unwrap_or(self.default_method)
+    }
+
+    /// Reads `cost_basis_method` and `cost_basis_method:<asset_type>` keys from `settings`.
+    pub async fn load(pool: &SqlitePool) -> Result<Self, sqlx::Error> {
+        let rows = sqlx::query_as::<_, (String, String)>(
+            "SELECT key, value FROM settings WHERE key = ? OR key LIKE ?",
+        )
+        .bind(Self::KEY)
+        .bind(format!("{}:%", Self::KEY))
+        .fetch_all(pool)
+        .await?;
+
+        let mut settings = Self::default();
+        for (key, value) in rows {
+            let Some(method) = CostBasisMethod::parse(&value) else { continue };
+            match key.split_once(':') {
+                Some((_, asset_type)) => {
+                    settings.asset_type_methods.insert(asset_type.to_string(), method);
+                }
+                None => settings.default_method = method,
+            }
+        }
+        Ok(settings)
+    }
+
+    /// Stores the method for an asset type, or the portfolio default when `asset_type` is None.
+    /// Passing `method: None` with an asset type removes the override.
+    pub async fn save(
+        pool: &SqlitePool,
+        asset_type: Option<&str>,
+        method: Option<CostBasisMethod>,
+    ) -> Result<(), sqlx::Error> {
+        let key = match asset_type {
+            Some(t) => format!("{}:{}", Self::KEY, t),
+            None => Self::KEY.to_string(),
+        };
+        match method {
+            Some(m) => {
+                sqlx::query(
+                    "INSERT INTO settings (key, value) VALUES (?, ?)
+                     ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                )
+                .bind(&key)
+                .bind(m.as_str())
+                .execute(pool)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM settings WHERE key = ?")
+                    .bind(&key)
+                    .execute(pool)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A SELL's explicit claim on a BUY lot, used by specific-lot identification.
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct LotSelection {
+    pub buy_transaction_id: i64,
+    pub quantity: f64,
+}
+
+impl LotSelection {
+    /// All stored selections keyed by the SELL transaction id.
+    pub async fn load_all(pool: &SqlitePool) -> Result<HashMap<i64, Vec<LotSelection>>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, (i64, i64, f64)>(
+            "SELECT sell_transaction_id, buy_transaction_id, quantity FROM lot_selections ORDER BY id ASC",
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut map: HashMap<i64, Vec<LotSelection>> = HashMap::new();
+        for (sell_id, buy_transaction_id, quantity) in rows {
+            map.entry(sell_id).or_default().push(LotSelection { buy_transaction_id, quantity });
+        }
+        Ok(map)
+    }
+
+    /// Replaces the selections attached to a SELL transaction.
+    pub async fn save_for_sell(
+        pool: &SqlitePool,
+        sell_transaction_id: i64,
+        selections: &[LotSelection],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        Self::save_in(&mut tx, sell_transaction_id, selections).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// `save_for_sell` inside the caller's transaction, e.g. together with the SELL insert.
+    pub async fn save_in(
+        conn: &mut SqliteConnection,
+        sell_transaction_id: i64,
+        selections: &[LotSelection],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM lot_selections WHERE sell_transaction_id = ?")
+            .bind(sell_transaction_id)
+            .execute(&mut *conn)
+            .await?;
+        for s in selections {
+            sqlx::query(
+                "INSERT INTO lot_selections (sell_transaction_id, buy_transaction_id, quantity) VALUES (?, ?, ?)",
+            )
+            .bind(sell_transaction_id)
+            .bind(s.buy_transaction_id)
+            .bind(s.quantity)
+            .execute(&mut *conn)
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct LedgerTransaction {
+    pub id: i64,
+    pub transaction_date: String,
+    pub asset_type: String,
+    pub symbol: String,
+    pub transaction_type: String,
+    pub quantity: f64,
+    pub price: f64,
+    pub fees: Option<f64>,
+    pub is_dividend: Option<bool>,
+    pub withholding_tax: Option<f64>,
+}
+
+impl LedgerTransaction {
+    pub async fn load_all(pool: &SqlitePool) -> Result<Vec<LedgerTransaction>, sqlx::Error> {
+        sqlx::query_as::<_, LedgerTransaction>(
+            "SELECT id, transaction_date, asset_type, symbol, transaction_type, quantity, price, fees, is_dividend, withholding_tax
+             FROM transactions
+             ORDER BY transaction_date ASC, created_at ASC, id ASC",
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    pub fn is_buy(&self) -> bool {
+        let type_upper = self.transaction_type.to_uppercase();
+        type_upper == "BUY" || type_upper == "ALIM" || type_upper == "ALIŞ" || type_upper == "A" || type_upper == "PURCHASE"
+    }
+
+    /// Cash dividends: `quantity` is the entitled share count and `price` the gross dividend per share.
+    pub fn is_dividend(&self) -> bool {
+        let type_upper = self.transaction_type.to_uppercase();
+        self.is_dividend.unwrap_or(false) || type_upper == "DIVIDEND" || type_upper == "TEMETTÜ" || type_upper == "TEMETTU"
+    }
+
+    fn fees(&self) -> f64 {
+        self.fees.unwrap_or(0.0)
+    }
+
+    // Per-unit cost of a buy, including its commission/BSMV
+    fn unit_cost(&self) -> f64 {
+        if self.quantity > 0.0 { self.price + self.fees() / self.quantity } else { self.price }
+    }
+
+    // Per-unit net proceeds of a sell, after its commission/BSMV
+    fn unit_proceeds(&self) -> f64 {
+        if self.quantity > 0.0 { self.price - self.fees() / self.quantity } else { self.price }
+    }
+}
+
+/// An open (not yet sold) portion of a BUY transaction.
+#[derive(Debug, Clone)]
+pub struct Lot {
+    pub transaction_id: i64, // 0 for lots created by a rights issue
+    pub corporate_action_id: Option<i64>,
+    pub buy_date: String,
+    pub quantity: f64,
+    pub unit_cost: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Position {
+    pub asset_type: String,
+    pub method: CostBasisMethod,
+    pub lots: Vec<Lot>,
+}
+
+impl Position {
+    pub fn quantity(&self) -> f64 {
+        self.lots.iter().map(|l| l.quantity).sum()
+    }
+
+    pub fn cost(&self) -> f64 {
+        self.lots.iter().map(|l| l.quantity * l.unit_cost).sum()
+    }
+
+    fn add_lot(&mut self, lot: Lot) {
+        self.lots.push(lot);
+        if self.method == CostBasisMethod::WeightedAverage {
+            // Every open lot carries the running average so any sell realizes against it
+            let qty = self.quantity();
+            if qty > 0.0 {
+                let avg = self.cost() / qty;
+                for l in &mut self.lots {
+                    l.unit_cost = avg;
+                }
+            }
+        }
+    }
+
+    /// Splits and bonus issues: more shares, same total cost.
+    fn apply_multiplier(&mut self, multiplier: f64) {
+        for l in &mut self.lots {
+            l.quantity *= multiplier;
+            l.unit_cost /= multiplier;
+        }
+    }
+
+    /// Removes `qty` from the open lots according to the position's method and
+    /// returns the consumed portions (lot as it was, quantity taken from it).
+    fn consume(&mut self, mut qty: f64, selections: Option<&[LotSelection]>) -> Vec<(Lot, f64)> {
+        let mut consumed = Vec::new();
+
+        if self.method == CostBasisMethod::SpecificLot {
+            for s in selections.unwrap_or_default() {
+                if qty <= QTY_EPSILON { break; }
+                if let Some(idx) = self.lots.iter().position(|l| l.transaction_id == s.buy_transaction_id) {
+                    let take = s.quantity.min(qty).min(self.lots[idx].quantity);
+                    if take <= 0.0 { continue; }
+                    consumed.push((self.lots[idx].clone(), take));
+                    self.lots[idx].quantity -= take;
+                    qty -= take;
+                }
+            }
+            self.lots.retain(|l| l.quantity > QTY_EPSILON);
+        }
+
+        // Unselected remainder of a specific-lot sell falls back to FIFO
+        let from_back = self.method == CostBasisMethod::Lifo;
+        while qty > QTY_EPSILON && !self.lots.is_empty() {
+            let idx = if from_back { self.lots.len() - 1 } else { 0 };
+            let lot = &mut self.lots[idx];
+            let take = lot.quantity.min(qty);
+            consumed.push((lot.clone(), take));
+            lot.quantity -= take;
+            qty -= take;
+            if lot.quantity <= QTY_EPSILON {
+                self.lots.remove(idx);
+            }
+        }
+
+        consumed
+    }
+}
+
+/// A BUY lot portion matched against a SELL.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ClosedLot {
+    pub sell_transaction_id: i64,
+    pub buy_transaction_id: i64,
+    pub symbol: String,
+    pub asset_type: String,
+    pub buy_date: String,
+    pub sell_date: String,
+    pub quantity: f64,
+    pub buy_price: f64,  // unit cost incl. buy fees
+    pub sell_price: f64, // unit proceeds net of sell fees
+    pub holding_days: Option<i64>,
+    pub realized_pnl: f64,
+}
+
+/// Cash dividend received on a holding.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DividendPayment {
+    pub transaction_id: i64,
+    pub symbol: String,
+    pub asset_type: String,
+    pub payment_date: String,
+    pub quantity: f64,
+    pub per_share: f64,
+    pub gross: f64,
+    pub withholding_tax: f64,
+    pub net: f64,
+}
+
+impl DividendPayment {
+    fn from_transaction(t: &LedgerTransaction, symbol: String) -> Self {
+        let gross = t.quantity * t.price;
+        let withholding_tax = t.withholding_tax.unwrap_or(0.0);
+        DividendPayment {
+            transaction_id: t.id,
+            symbol,
+            asset_type: t.asset_type.clone(),
+            payment_date: t.transaction_date.clone(),
+            quantity: t.quantity,
+            per_share: t.price,
+            gross,
+            withholding_tax,
+            net: gross - withholding_tax - t.fees(),
+        }
+    }
+}
+
+/// A SELL that exceeded the open quantity at its date; the excess has no cost basis and realizes nothing.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OversellWarning {
+    pub symbol: String,
+    pub transaction_id: i64,
+    pub sell_date: String,
+    pub sell_quantity: f64,
+    pub missing_quantity: f64,
+}
+
+/// Result of replaying all transactions through the configured cost basis methods.
+#[derive(Debug, Default)]
+pub struct LotLedger {
+    pub positions: HashMap<String, Position>,
+    pub closed: Vec<ClosedLot>,
+    pub oversells: Vec<OversellWarning>,
+    pub dividends: Vec<DividendPayment>,
+}
+
+impl LotLedger {
+    pub fn build(
+        transactions: &[LedgerTransaction],
+        actions: &[CorporateAction],
+        settings: &CostBasisSettings,
+        selections: &HashMap<i64, Vec<LotSelection>>,
+    ) -> Self {
+        let mut ledger = LotLedger::default();
+        let mut pending_actions = actions.iter().peekable();
+
+        for t in transactions {
+            // Corporate actions take effect before trades dated on their ex-date
+            let trade_date = parse_date(&t.transaction_date);
+            while let Some(action) = pending_actions.next_if(|a| parse_date(&a.ex_date) <= trade_date) {
+                ledger.apply_action(action);
+            }
+
+            let symbol = t.symbol.to_uppercase();
+            let position = ledger.positions.entry(symbol.clone()).or_insert_with(|| Position {
+                asset_type: t.asset_type.clone(),
+                method: settings.method_for(&t.asset_type),
+                lots: Vec::new(),
+            });
+
+            if t.is_dividend() {
+                // Income only; open lots and cost basis are unaffected
+                ledger.dividends.push(DividendPayment::from_transaction(t, symbol));
+            } else if t.is_buy() {
+                position.add_lot(Lot {
+                    transaction_id: t.id,
+                    corporate_action_id: None,
+                    buy_date: t.transaction_date.clone(),
+                    quantity: t.quantity,
+                    unit_cost: t.unit_cost(),
+                });
+            } else {
+                let proceeds = t.unit_proceeds();
+                let consumed = position.consume(t.quantity, selections.get(&t.id).map(|v| v.as_slice()));
+                let matched: f64 = consumed.iter().map(|(_, qty)| qty).sum();
+                if t.quantity - matched > QTY_EPSILON {
+                    ledger.oversells.push(OversellWarning {
+                        symbol: symbol.clone(),
+                        transaction_id: t.id,
+                        sell_date: t.transaction_date.clone(),
+                        sell_quantity: t.quantity,
+                        missing_quantity: t.quantity - matched,
+                    });
+                }
+                let sell_date = parse_date(&t.transaction_date);
+                for (lot, qty) in consumed {
+                    ledger.closed.push(ClosedLot {
+                        sell_transaction_id: t.id,
+                        buy_transaction_id: lot.transaction_id,
+                        symbol: symbol.clone(),
+                        asset_type: position.asset_type.clone(),
+                        holding_days: sell_date.zip(parse_date(&lot.buy_date)).map(|(s, b)| (s - b).num_days()),
+                        buy_date: lot.buy_date,
+                        sell_date: t.transaction_date.clone(),
+                        quantity: qty,
+                        buy_price: lot.unit_cost,
+                        sell_price: proceeds,
+                        realized_pnl: (proceeds - lot.unit_cost) * qty,
+                    });
+                }
+            }
+        }
+
+        for action in pending_actions {
+            ledger.apply_action(action);
+        }
+
+        ledger
+    }
+
+    fn apply_action(&mut self, action: &CorporateAction) {
+        let Some(position) = self.positions.get_mut(&action.symbol.to_uppercase()) else { return };
+        if let Some(multiplier) = action.quantity_multiplier() {
+            position.apply_multiplier(multiplier);
+        } else if action.is_rights() {
+            // Subscribed rights are a paid-in addition at the subscription price
+            let quantity = position.quantity() * action.ratio;
+            if quantity > QTY_EPSILON {
+                position.add_lot(Lot {
+                    transaction_id: 0,
+                    corporate_action_id: Some(action.id),
+                    buy_date: action.ex_date.clone(),
+                    quantity,
+                    unit_cost: action.subscription_price.unwrap_or(0.0),
+                });
+            }
+        }
+    }
+
+    pub async fn load(pool: &SqlitePool) -> Result<Self, sqlx::Error> {
+        let transactions = LedgerTransaction::load_all(pool).await?;
+        let actions = CorporateAction::load_all(pool).await?;
+        let settings = CostBasisSettings::load(pool).await?;
+        let selections = LotSelection::load_all(pool).await?;
+        Ok(Self::build(&transactions, &actions, &settings, &selections))
+    }
+
+    /// Oversells that appear when `candidate` is added (id 0) or replaces the stored row with the same id.
+    pub async fn find_new_oversells(pool: &SqlitePool, candidate: LedgerTransaction) -> Result<Vec<OversellWarning>, sqlx::Error> {
+        let mut transactions = LedgerTransaction::load_all(pool).await?;
+        let actions = CorporateAction::load_all(pool).await?;
+        let settings = CostBasisSettings::load(pool).await?;
+        let selections = LotSelection::load_all(pool).await?;
+        let before = Self::build(&transactions, &actions, &settings, &selections).oversells;
+
+        match transactions.iter().position(|t| t.id == candidate.id) {
+            Some(idx) => transactions[idx] = candidate,
+            None => transactions.push(candidate),
+        }
+        // Stable sort keeps a new candidate after existing rows of the same day, like created_at ordering
+        transactions.sort_by(|a, b| a.transaction_date.cmp(&b.transaction_date));
+        let after = Self::build(&transactions, &actions, &settings, &selections).oversells;
+
+        Ok(after
+            .into_iter()
+            .filter(|w| {
+                !before.iter().any(|b| {
+                    b.transaction_id == w.transaction_id && w.missing_quantity - b.missing_quantity <= QTY_EPSILON
+                })
+            })
+            .collect())
+    }
+
+    pub fn realized_pnl(&self) -> f64 {
+        self.closed.iter().map(|c| c.realized_pnl).sum()
+    }
+
+    pub fn dividend_income(&self) -> f64 {
+        self.dividends.iter().map(|d| d.net).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    fn tx(id: i64, date: &str, kind: &str, quantity: f64, price: f64, fees: f64) -> LedgerTransaction {
+        LedgerTransaction {
+            id,
+            transaction_date: date.to_string(),
+            asset_type: "hisse".to_string(),
+            symbol: "thyao".to_string(),
+            transaction_type: kind.to_string(),
+            quantity,
+            price,
+            fees: Some(fees),
+            is_dividend: None,
+            withholding_tax: None,
+        }
+    }
+
+    // Two buys (unit costs 101 and 120 after fees) and a sell of 15 netting 149 a share
+    fn trades() -> Vec<LedgerTransaction> {
+        vec![
+            tx(1, "2024-01-01", "BUY", 10.0, 100.0, 10.0),
+            tx(2, "2024-02-01", "BUY", 10.0, 120.0, 0.0),
+            tx(3, "2024-03-01", "SELL", 15.0, 150.0, 15.0),
+        ]
+    }
+
+    fn build(transactions: &[LedgerTransaction], method: CostBasisMethod, selections: &HashMap<i64, Vec<LotSelection>>) -> LotLedger {
+        let settings = CostBasisSettings { default_method: method, asset_type_methods: HashMap::new() };
+        LotLedger::build(transactions, &[], &settings, selections)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    fn closed(ledger: &LotLedger) -> Vec<(i64, f64)> {
+        ledger.closed.iter().map(|c| (c.buy_transaction_id, c.quantity)).collect()
+    }
+
+    #[test]
+    fn fifo_sells_the_oldest_lot_first() {
+        let ledger = build(&trades(), CostBasisMethod::Fifo, &HashMap::new());
+        assert_eq!(closed(&ledger), [(1, 10.0), (2, 5.0)]);
+        assert_close(ledger.closed[0].buy_price, 101.0);
+        assert_close(ledger.closed[0].sell_price, 149.0);
+        assert_eq!(ledger.closed[0].holding_days, Some(60));
+        assert_close(ledger.realized_pnl(), 48.0 * 10.0 + 29.0 * 5.0);
+
+        let position = &ledger.positions["THYAO"];
+        assert_close(position.quantity(), 5.0);
+        assert_close(position.cost(), 600.0);
+        assert!(ledger.oversells.is_empty());
+    }
+
+    #[test]
+    fn lifo_sells_the_newest_lot_first() {
+        let ledger = build(&trades(), CostBasisMethod::Lifo, &HashMap::new());
+        assert_eq!(closed(&ledger), [(2, 10.0), (1, 5.0)]);
+        assert_close(ledger.realized_pnl(), 29.0 * 10.0 + 48.0 * 5.0);
+        assert_close(ledger.positions["THYAO"].cost(), 505.0);
+    }
+
+    #[test]
+    fn weighted_average_realizes_against_the_running_average() {
+        let ledger = build(&trades(), CostBasisMethod::WeightedAverage, &HashMap::new());
+        let average = (1010.0 + 1200.0) / 20.0;
+        assert!(ledger.closed.iter().all(|c| (c.buy_price - average).abs() < 1e-9));
+        assert_close(ledger.realized_pnl(), (149.0 - average) * 15.0);
+        assert_close(ledger.positions["THYAO"].cost(), average * 5.0);
+
+        // A later buy re-averages the remaining lots
+        let mut more = trades();
+        more.push(tx(4, "2024-04-01", "BUY", 5.0, 90.0, 0.0));
+        let position = &build(&more, CostBasisMethod::WeightedAverage, &HashMap::new()).positions["THYAO"];
+        assert_close(position.cost(), average * 5.0 + 450.0);
+        assert!(position.lots.iter().all(|l| (l.unit_cost - position.cost() / 10.0).abs() < 1e-9));
+    }
+
+    #[test]
+    fn specific_lot_falls_back_to_fifo_for_the_unselected_rest() {
+        let selections = HashMap::from([(3, vec![LotSelection { buy_transaction_id: 2, quantity: 8.0 }])]);
+        let ledger = build(&trades(), CostBasisMethod::SpecificLot, &selections);
+        assert_eq!(closed(&ledger), [(2, 8.0), (1, 7.0)]);
+        assert_close(ledger.realized_pnl(), 29.0 * 8.0 + 48.0 * 7.0);
+        assert_close(ledger.positions["THYAO"].cost(), 3.0 * 101.0 + 2.0 * 120.0);
+
+        // Without selections a specific-lot sell is plain FIFO
+        let ledger = build(&trades(), CostBasisMethod::SpecificLot, &HashMap::new());
+        assert_eq!(closed(&ledger), [(1, 10.0), (2, 5.0)]);
+
+        // Selections naming a closed or unknown lot, or more than it holds, are capped
+        let selections = HashMap::from([(
+            3,
+            vec![LotSelection { buy_transaction_id: 99, quantity: 5.0 }, LotSelection { buy_transaction_id: 1, quantity: 50.0 }],
+        )]);
+        let ledger = build(&trades(), CostBasisMethod::SpecificLot, &selections);
+        assert_eq!(closed(&ledger), [(1, 10.0), (2, 5.0)]);
+    }
+
+    #[test]
+    fn oversells_and_dividends_leave_lots_alone() {
+        let mut transactions = trades();
+        transactions[2].quantity = 25.0;
+        let mut dividend = tx(4, "2024-04-01", "DIVIDEND", 10.0, 2.0, 0.0);
+        dividend.withholding_tax = Some(3.0);
+        transactions.push(dividend);
+
+        let ledger = build(&transactions, CostBasisMethod::Fifo, &HashMap::new());
+        assert_eq!(ledger.oversells.len(), 1);
+        assert_eq!(ledger.oversells[0].transaction_id, 3);
+        assert_close(ledger.oversells[0].missing_quantity, 5.0);
+        // Only the 20 shares actually held realize a gain
+        assert_close(ledger.closed.iter().map(|c| c.quantity).sum(), 20.0);
+        assert!(ledger.positions["THYAO"].lots.is_empty());
+        assert_eq!(ledger.dividends.len(), 1);
+        assert_close(ledger.dividend_income(), 17.0);
+    }
+
+    fn action(id: i64, action_type: &str, ex_date: &str, ratio: f64, subscription_price: Option<f64>) -> CorporateAction {
+        CorporateAction {
+            id,
+            symbol: "THYAO".to_string(),
+            action_type: action_type.to_string(),
+            ex_date: ex_date.to_string(),
+            ratio,
+            subscription_price,
+            notes: None,
+        }
+    }
+
+    #[test]
+    fn corporate_actions_restate_open_lots() {
+        let settings = CostBasisSettings { default_method: CostBasisMethod::Fifo, asset_type_methods: HashMap::new() };
+        let buys = [tx(1, "2024-01-01", "BUY", 10.0, 100.0, 10.0)];
+
+        // 1:2 split: twice the shares at half the unit cost
+        let split = [action(1, "split", "2024-02-01", 2.0, None)];
+        let position = &LotLedger::build(&buys, &split, &settings, &HashMap::new()).positions["THYAO"];
+        assert_close(position.quantity(), 20.0);
+        assert_close(position.lots[0].unit_cost, 50.5);
+        assert_close(position.cost(), 1010.0);
+
+        // %50 bedelsiz on top: more free shares, same total cost
+        let bonus = [split[0].clone(), action(2, "bonus", "2024-03-01", 0.5, None)];
+        let position = &LotLedger::build(&buys, &bonus, &settings, &HashMap::new()).positions["THYAO"];
+        assert_close(position.quantity(), 30.0);
+        assert_close(position.cost(), 1010.0);
+
+        // %10 bedelli at 10: a new paid-in lot tied to the action
+        let rights = [bonus[0].clone(), bonus[1].clone(), action(3, "rights", "2024-04-01", 0.1, Some(10.0))];
+        let position = &LotLedger::build(&buys, &rights, &settings, &HashMap::new()).positions["THYAO"];
+        assert_close(position.quantity(), 33.0);
+        assert_close(position.cost(), 1040.0);
+        let lot = &position.lots[1];
+        assert_eq!((lot.transaction_id, lot.corporate_action_id, lot.buy_date.as_str()), (0, Some(3), "2024-04-01"));
+        assert_close(lot.quantity, 3.0);
+        assert_close(lot.unit_cost, 10.0);
+    }
+
+    #[test]
+    fn actions_take_effect_before_trades_on_their_ex_date() {
+        let settings = CostBasisSettings { default_method: CostBasisMethod::Fifo, asset_type_methods: HashMap::new() };
+        let split = [action(1, "split", "2024-03-01", 2.0, None)];
+
+        // Selling the post-split 20 shares on the ex-date closes the whole lot
+        for sell_date in ["2024-03-01", "01.03.2024"] {
+            let trades = [tx(1, "2024-01-01", "BUY", 10.0, 100.0, 10.0), tx(2, sell_date, "SELL", 20.0, 60.0, 0.0)];
+            let ledger = LotLedger::build(&trades, &split, &settings, &HashMap::new());
+            assert!(ledger.oversells.is_empty(), "{}", sell_date);
+            assert_eq!(closed(&ledger), [(1, 20.0)]);
+            assert_close(ledger.closed[0].buy_price, 50.5);
+            assert_close(ledger.realized_pnl(), 9.5 * 20.0);
+        }
+
+        // The day before, only the pre-split 10 shares are held
+        let trades = [tx(1, "2024-01-01", "BUY", 10.0, 100.0, 10.0), tx(2, "2024-02-29", "SELL", 20.0, 120.0, 0.0)];
+        let ledger = LotLedger::build(&trades, &split, &settings, &HashMap::new());
+        assert_close(ledger.oversells[0].missing_quantity, 10.0);
+        assert!(ledger.positions["THYAO"].lots.is_empty());
+
+        // Actions after the last trade still apply
+        let ledger = LotLedger::build(&trades[..1], &split, &settings, &HashMap::new());
+        assert_close(ledger.positions["THYAO"].quantity(), 20.0);
+    }
+
+    async fn ledger_pool(transactions: &[LedgerTransaction]) -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::create_schema(&pool).await.unwrap();
+        for t in transactions {
+            sqlx::query(
+                "INSERT INTO transactions (id, transaction_date, asset_type, symbol, transaction_type, quantity, price, fees)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(t.id)
+            .bind(&t.transaction_date)
+            .bind(&t.asset_type)
+            .bind(&t.symbol)
+            .bind(&t.transaction_type)
+            .bind(t.quantity)
+            .bind(t.price)
+            .bind(t.fees)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        pool
+    }
+
+    #[tokio::test]
+    async fn new_oversells_come_from_the_candidate_only() {
+        let pool = ledger_pool(&[tx(1, "2024-01-01", "BUY", 10.0, 100.0, 0.0), tx(2, "2024-02-01", "SELL", 4.0, 110.0, 0.0)]).await;
+
+        // A new sell (id 0) within the remaining 6 shares is fine; one more share is not
+        let fits = LotLedger::find_new_oversells(&pool, tx(0, "2024-03-01", "SELL", 6.0, 120.0, 0.0)).await.unwrap();
+        assert!(fits.is_empty());
+        let over = LotLedger::find_new_oversells(&pool, tx(0, "2024-03-01", "SELL", 7.0, 120.0, 0.0)).await.unwrap();
+        assert_eq!((over[0].transaction_id, over[0].sell_date.as_str()), (0, "2024-03-01"));
+        assert_close(over[0].missing_quantity, 1.0);
+
+        // Held quantity is judged as of the sell date
+        let early = LotLedger::find_new_oversells(&pool, tx(0, "2023-12-01", "SELL", 1.0, 90.0, 0.0)).await.unwrap();
+        assert_close(early[0].missing_quantity, 1.0);
+
+        // Edits replace the stored row: a bigger sell, or a smaller buy under an existing sell
+        let edited_sell = LotLedger::find_new_oversells(&pool, tx(2, "2024-02-01", "SELL", 12.0, 110.0, 0.0)).await.unwrap();
+        assert_eq!(edited_sell[0].transaction_id, 2);
+        assert_close(edited_sell[0].missing_quantity, 2.0);
+        let edited_buy = LotLedger::find_new_oversells(&pool, tx(1, "2024-01-01", "BUY", 3.0, 100.0, 0.0)).await.unwrap();
+        assert_eq!(edited_buy[0].transaction_id, 2);
+        assert_close(edited_buy[0].missing_quantity, 1.0);
+        // Moving the buy after the sell leaves the sell uncovered
+        let moved_buy = LotLedger::find_new_oversells(&pool, tx(1, "2024-03-01", "BUY", 10.0, 100.0, 0.0)).await.unwrap();
+        assert_eq!(moved_buy[0].transaction_id, 2);
+    }
+
+    #[tokio::test]
+    async fn existing_oversells_are_not_reported_again() {
+        let pool = ledger_pool(&[tx(1, "2024-01-01", "BUY", 10.0, 100.0, 0.0), tx(2, "2024-02-01", "SELL", 15.0, 110.0, 0.0)]).await;
+
+        let unrelated = LotLedger::find_new_oversells(&pool, tx(0, "2024-03-01", "BUY", 1.0, 120.0, 0.0)).await.unwrap();
+        assert!(unrelated.is_empty());
+        // Same shortfall after an edit is not new either
+        let same = LotLedger::find_new_oversells(&pool, tx(2, "2024-02-01", "SELL", 15.0, 105.0, 0.0)).await.unwrap();
+        assert!(same.is_empty());
+        // Making it worse is
+        let worse = LotLedger::find_new_oversells(&pool, tx(2, "2024-02-01", "SELL", 18.0, 110.0, 0.0)).await.unwrap();
+        assert_close(worse[0].missing_quantity, 8.0);
+    }
+
+    #[test]
+    fn asset_type_overrides_the_default_method() {
+        let settings = CostBasisSettings {
+            default_method: CostBasisMethod::Fifo,
+            asset_type_methods: HashMap::from([("hisse".to_string(), CostBasisMethod::Lifo)]),
+        };
+        let ledger = LotLedger::build(&trades(), &[], &settings, &HashMap::new());
+        assert_eq!(ledger.positions["THYAO"].method, CostBasisMethod::Lifo);
+        assert_eq!(closed(&ledger), [(2, 10.0), (1, 5.0)]);
+    }
+}
@@ -0,0 +1,307 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::lots;
+
+/// Asset type of holdings no provider can price (private equity, real estate, physical gold,
+/// small bank deposits). Their price is the latest valuation the user recorded.
+pub const MANUAL_ASSET_TYPE: &str = "manuel";
+const MANUAL_PRICE_SOURCE: &str = "manual";
+
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct ManualAsset {
+    pub symbol: String,
+    pub name: String,
+    pub current_price: f64,
+    pub last_valuation_date: Option<String>,
+    pub valuation_count: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewManualAsset {
+    pub symbol: String,
+    pub name: String,
+}
+
+/// Unit value of a manual asset on a date, kept in `asset_price_history`.
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct ManualValuation {
+    pub date: String,
+    pub price: f64,
+}
+
+pub struct ManualAssetService;
+
+impl ManualAssetService {
+    pub async fn list(pool: &SqlitePool) -> Result<Vec<ManualAsset>, Box<dyn std::error::Error>> {
+        Ok(sqlx::query_as::<_, ManualAsset>(
+            "SELECT a.symbol, COALESCE(a.name, a.symbol) AS name, COALESCE(a.current_price, 0) AS current_price,
+                    MAX(h.snapshot_date) AS last_valuation_date, COUNT(h.snapshot_date) AS valuation_count
+             FROM assets a
+             LEFT JOIN asset_price_history h ON h.symbol = a.symbol
+             WHERE a.asset_type = ?
+             GROUP BY a.symbol
+             ORDER BY a.symbol",
+        )
+        .bind(MANUAL_ASSET_TYPE)
+        .fetch_all(pool)
+        .await?)
+    }
+
+    /// Registers a manual asset, or renames it if it already exists.
+    pub async fn add(pool: &SqlitePool, asset: NewManualAsset) -> Result<(), Box<dyn std::error::Error>> {
+        let symbol = asset.symbol.trim().to_uppercase();
+        if symbol.is_empty() {
+            return Err("Manual asset needs a symbol".into());
+        }
+        if let Some(asset_type) = Self::asset_type(pool, &symbol).await? {
+            if asset_type != MANUAL_ASSET_TYPE {
+                return Err(format!("{} is already tracked as {}", symbol, asset_type).into());
+            }
+        }
+        let name = match asset.name.trim() {
+            "" => symbol.clone(),
+            name => name.to_string(),
+        };
+
+        sqlx::query(
+            "INSERT INTO assets (symbol, name, asset_type, current_price, day_change, last_updated, price_source)
+             VALUES (?, ?, ?, 0, 0, ?, ?)
+             ON CONFLICT(symbol) DO UPDATE SET name = excluded.name",
+        )
+        .bind(&symbol)
+        .bind(&name)
+        .bind(MANUAL_ASSET_TYPE)
+        .bind(Local::now().to_rfc3339())
+        .bind(MANUAL_PRICE_SOURCE)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Removes the asset and its valuations; refused while transactions still reference it.
+    pub async fn delete(pool: &SqlitePool, symbol: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let symbol = Self::manual_symbol(pool, symbol).await?;
+        let used: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM transactions WHERE UPPER(symbol) = ?")
+            .bind(&symbol)
+            .fetch_one(pool)
+            .await?;
+        if used > 0 {
+            return Err(format!("{} still has {} transactions", symbol, used).into());
+        }
+
+        let mut tx = pool.begin().await?;
+        sqlx::query("DELETE FROM asset_price_history WHERE symbol = ?")
+            .bind(&symbol)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM assets WHERE symbol = ?")
+            .bind(&symbol)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn get_valuations(pool: &SqlitePool, symbol: &str) -> Result<Vec<ManualValuation>, Box<dyn std::error::Error>> {
+        let symbol = Self::manual_symbol(pool, symbol).await?;
+        Ok(sqlx::query_as::<_, ManualValuation>(
+            "SELECT snapshot_date AS date, price FROM asset_price_history WHERE symbol = ? ORDER BY snapshot_date ASC",
+        )
+        .bind(&symbol)
+        .fetch_all(pool)
+        .await?)
+    }
+
+    /// Records (or replaces) the unit value on `date`. The latest valuation becomes the current price.
+    pub async fn set_valuation(pool: &SqlitePool, symbol: &str, date: &str, price: f64) -> Result<(), Box<dyn std::error::Error>> {
+        let symbol = Self::manual_symbol(pool, symbol).await?;
+        if !price.is_finite() || price < 0.0 {
+            return Err(format!("Invalid valuation: {}", price).into());
+        }
+        let date = lots::parse_date(date)
+            .ok_or_else(|| format!("Invalid valuation date: {}", date))?
+            .format("%Y-%m-%d")
+            .to_string();
+
+        let mut tx = pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO asset_price_history (symbol, price, snapshot_date)
+             VALUES (?, ?, ?)
+             ON CONFLICT(symbol, snapshot_date) DO UPDATE SET price = excluded.price",
+        )
+        .bind(&symbol)
+        .bind(price)
+        .bind(&date)
+        .execute(&mut *tx)
+        .await?;
+        Self::sync_current_price(&mut tx, &symbol).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn delete_valuation(pool: &SqlitePool, symbol: &str, date: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let symbol = Self::manual_symbol(pool, symbol).await?;
+        let date = lots::parse_date(date)
+            .ok_or_else(|| format!("Invalid valuation date: {}", date))?
+            .format("%Y-%m-%d")
+            .to_string();
+
+        let mut tx = pool.begin().await?;
+        sqlx::query("DELETE FROM asset_price_history WHERE symbol = ? AND snapshot_date = ?")
+            .bind(&symbol)
+            .bind(&date)
+            .execute(&mut *tx)
+            .await?;
+        Self::sync_current_price(&mut tx, &symbol).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Points `assets.current_price` at the latest valuation; with none left the last price is kept.
+    async fn sync_current_price(tx: &mut sqlx::SqliteConnection, symbol: &str) -> Result<(), sqlx::Error> {
+        let latest: Option<f64> = sqlx::query_scalar(
+            "SELECT price FROM asset_price_history WHERE symbol = ? ORDER BY snapshot_date DESC LIMIT 1",
+        )
+        .bind(symbol)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(price) = latest else { return Ok(()) };
+
+        sqlx::query("UPDATE assets SET current_price = ?, day_change = 0, last_updated = ?, price_source = ? WHERE symbol = ?")
+            .bind(price)
+            .bind(Local::now().to_rfc3339())
+            .bind(MANUAL_PRICE_SOURCE)
+            .bind(symbol)
+            .execute(&mut *tx)
+            .await?;
+        Ok(())
+    }
+
+    async fn asset_type(pool: &SqlitePool, symbol: &str) -> Result<Option<String>, sqlx::Error> {
+        Ok(sqlx::query_scalar::<_, Option<String>>("SELECT asset_type FROM assets WHERE symbol = ?")
+            .bind(symbol)
+            .fetch_optional(pool)
+            .await?
+            .flatten())
+    }
+
+    /// Normalizes `symbol` and checks it names a manual asset.
+    async fn manual_symbol(pool: &SqlitePool, symbol: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let symbol = symbol.trim().to_uppercase();
+        match Self::asset_type(pool, &symbol).await? {
+            Some(t) if t == MANUAL_ASSET_TYPE => Ok(symbol),
+            Some(t) => Err(format!("{} is priced automatically ({})", symbol, t).into()),
+            None => Err(format!("Unknown manual asset: {}", symbol).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn memory_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::create_schema(&pool).await.unwrap();
+        pool
+    }
+
+    fn new_asset(symbol: &str, name: &str) -> NewManualAsset {
+        NewManualAsset { symbol: symbol.to_string(), name: name.to_string() }
+    }
+
+    async fn current_price(pool: &SqlitePool, symbol: &str) -> (f64, Option<String>) {
+        sqlx::query_as("SELECT current_price, price_source FROM assets WHERE symbol = ?")
+            .bind(symbol)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn creates_and_renames_manual_assets() {
+        let pool = memory_pool().await;
+        ManualAssetService::add(&pool, new_asset(" arsa ", "")).await.unwrap();
+        ManualAssetService::add(&pool, new_asset("köşk", "Kadıköy köşkü")).await.unwrap();
+
+        let assets = ManualAssetService::list(&pool).await.unwrap();
+        let summary: Vec<_> = assets.iter().map(|a| (a.symbol.as_str(), a.name.as_str(), a.valuation_count)).collect();
+        assert_eq!(summary, vec![("ARSA", "ARSA", 0), ("KÖŞK", "Kadıköy köşkü", 0)]);
+        assert_eq!(current_price(&pool, "ARSA").await, (0.0, Some(MANUAL_PRICE_SOURCE.to_string())));
+
+        // Adding again renames; symbols priced by a provider can't be taken over
+        ManualAssetService::add(&pool, new_asset("Arsa", "Bodrum arsası")).await.unwrap();
+        assert_eq!(ManualAssetService::list(&pool).await.unwrap()[0].name, "Bodrum arsası");
+        sqlx::query("INSERT INTO assets (symbol, name, asset_type, current_price) VALUES ('THYAO', 'THY', 'hisse', 300)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(ManualAssetService::add(&pool, new_asset("thyao", "")).await.is_err());
+        assert!(ManualAssetService::add(&pool, new_asset("  ", "Boş")).await.is_err());
+        assert!(ManualAssetService::set_valuation(&pool, "THYAO", "2024-01-01", 1.0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn the_latest_valuation_is_the_current_price() {
+        let pool = memory_pool().await;
+        ManualAssetService::add(&pool, new_asset("ARSA", "")).await.unwrap();
+
+        ManualAssetService::set_valuation(&pool, "arsa", "15.03.2024", 1_200_000.0).await.unwrap();
+        assert_eq!(current_price(&pool, "ARSA").await.0, 1_200_000.0);
+        // An older valuation is history only; the same date is replaced
+        ManualAssetService::set_valuation(&pool, "ARSA", "2023-12-31", 1_000_000.0).await.unwrap();
+        assert_eq!(current_price(&pool, "ARSA").await.0, 1_200_000.0);
+        ManualAssetService::set_valuation(&pool, "ARSA", "2024-03-15", 1_250_000.0).await.unwrap();
+        assert_eq!(current_price(&pool, "ARSA").await.0, 1_250_000.0);
+
+        let valuations: Vec<_> = ManualAssetService::get_valuations(&pool, "ARSA")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|v| (v.date, v.price))
+            .collect();
+        assert_eq!(valuations, vec![("2023-12-31".to_string(), 1_000_000.0), ("2024-03-15".to_string(), 1_250_000.0)]);
+
+        // Removing the latest falls back to the one before; removing the last keeps the price
+        ManualAssetService::delete_valuation(&pool, "ARSA", "2024-03-15").await.unwrap();
+        assert_eq!(current_price(&pool, "ARSA").await.0, 1_000_000.0);
+        ManualAssetService::delete_valuation(&pool, "ARSA", "31/12/2023").await.unwrap();
+        assert_eq!(current_price(&pool, "ARSA").await.0, 1_000_000.0);
+
+        assert!(ManualAssetService::set_valuation(&pool, "ARSA", "2024-01-01", -1.0).await.is_err());
+        assert!(ManualAssetService::set_valuation(&pool, "ARSA", "2024-01-01", f64::NAN).await.is_err());
+        assert!(ManualAssetService::set_valuation(&pool, "ARSA", "yesterday", 1.0).await.is_err());
+        assert!(ManualAssetService::set_valuation(&pool, "TARLA", "2024-01-01", 1.0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn delete_is_refused_while_transactions_use_the_asset() {
+        let pool = memory_pool().await;
+        ManualAssetService::add(&pool, new_asset("ARSA", "")).await.unwrap();
+        ManualAssetService::set_valuation(&pool, "ARSA", "2024-03-15", 1_200_000.0).await.unwrap();
+        sqlx::query(
+            "INSERT INTO transactions (transaction_date, asset_type, symbol, transaction_type, quantity, price)
+             VALUES ('2024-01-10', 'manuel', 'ARSA', 'BUY', 1, 1000000)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert!(ManualAssetService::delete(&pool, "arsa").await.is_err());
+        assert_eq!(ManualAssetService::list(&pool).await.unwrap().len(), 1);
+
+        sqlx::query("DELETE FROM transactions").execute(&pool).await.unwrap();
+        ManualAssetService::delete(&pool, "arsa").await.unwrap();
+        assert!(ManualAssetService::list(&pool).await.unwrap().is_empty());
+        let history: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM asset_price_history").fetch_one(&pool).await.unwrap();
+        assert_eq!(history, 0);
+        assert!(ManualAssetService::delete(&pool, "ARSA").await.is_err());
+    }
+}
@@ -0,0 +1,464 @@
+use calamine::{open_workbook_auto, Data, DataType, Reader};
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::csv_io::{CsvOptions, CsvService};
+use crate::importer::{ImportService, ImportStrategy, TransactionImportReport};
+use crate::providers;
+
+const PREVIEW_ROWS: usize = 5;
+const SIDES: [&str; 3] = ["buy", "sell", "dividend"];
+// Turkish labels the importer already understands, keyed by their folded form so "ALIŞ" maps too
+const DEFAULT_SIDE_LABELS: [(&str, &str); 3] = [("alis", "buy"), ("satis", "sell"), ("temettu", "dividend")];
+const WORKBOOK_EXTENSIONS: [&str; 4] = ["xlsx", "xlsm", "xls", "ods"];
+
+/// Source column (header text) for each transaction field; optional fields may stay unmapped.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ColumnMap {
+    pub date: String,
+    pub symbol: String,
+    pub side: String,
+    pub quantity: String,
+    pub price: String,
+    pub fees: Option<String>,
+    pub currency: Option<String>,
+    pub broker: Option<String>,
+    pub notes: Option<String>,
+}
+
+/// How to read one broker's or bank's export, saved by name and offered again on the next import.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImportProfile {
+    pub name: String,
+    pub columns: ColumnMap,
+    // Side label in the file -> "buy", "sell" or "dividend", e.g. "A" -> "buy". Matched ignoring case
+    // and Turkish letters; labels the importer already knows (Alış, Satış, Temettü) need no entry.
+    #[serde(default)]
+    pub side_values: HashMap<String, String>,
+    #[serde(default)]
+    pub asset_type: Option<String>, // for every row; guessed per symbol when unset
+    #[serde(default)]
+    pub csv: CsvOptions,
+    #[serde(default)]
+    pub sheet: Option<String>, // workbook sheet; the first one when unset
+    #[serde(default)]
+    pub last_used_at: Option<String>,
+}
+
+/// Sheets, header and first rows of a file, for building a column map.
+#[derive(Debug, Serialize, Clone)]
+pub struct SpreadsheetPreview {
+    pub sheets: Vec<String>,
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+/// A cell as read from the file; workbooks may already type numbers and dates.
+enum Cell {
+    Text(String),
+    Number(f64),
+    Date(NaiveDate),
+}
+
+impl Cell {
+    fn text(&self) -> String {
+        match self {
+            Cell::Text(s) => s.clone(),
+            // Whole numbers without ".0" so numeric symbols and codes read as typed
+            Cell::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => format!("{}", *n as i64),
+            Cell::Number(n) => n.to_string(),
+            Cell::Date(d) => d.format("%Y-%m-%d").to_string(),
+        }
+    }
+
+    fn value(&self, numeric: bool) -> Option<serde_json::Value> {
+        let number = match self {
+            Cell::Number(n) => Some(*n),
+            Cell::Text(s) if numeric => providers::parse_tr_float(s),
+            _ => None,
+        };
+        match (numeric, number) {
+            (true, Some(n)) => Some(serde_json::json!(n)),
+            // Unparsable numbers stay strings and are reported by the row validation
+            _ => Some(serde_json::json!(self.text())).filter(|v| v.as_str().is_some_and(|s| !s.is_empty())),
+        }
+    }
+}
+
+struct Table {
+    sheets: Vec<String>,
+    headers: Vec<String>,
+    rows: Vec<(usize, Vec<Cell>)>, // line (spreadsheet row) number, cells
+}
+
+pub struct MappedImportService;
+
+impl MappedImportService {
+    /// Saved profiles, most recently used first.
+    pub async fn list_profiles(pool: &SqlitePool) -> Result<Vec<ImportProfile>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query_as::<_, (String, Option<String>)>(
+            "SELECT profile, last_used_at FROM import_profiles ORDER BY last_used_at IS NULL, last_used_at DESC, name",
+        )
+        .fetch_all(pool)
+        .await?;
+        let mut profiles = Vec::new();
+        for (json, last_used_at) in rows {
+            let mut profile: ImportProfile = serde_json::from_str(&json)?;
+            profile.last_used_at = last_used_at;
+            profiles.push(profile);
+        }
+        Ok(profiles)
+    }
+
+    /// Creates or overwrites the profile with this name.
+    pub async fn save_profile(pool: &SqlitePool, mut profile: ImportProfile) -> Result<(), Box<dyn std::error::Error>> {
+        profile.name = profile.name.trim().to_string();
+        if profile.name.is_empty() {
+            return Err("Profil adı boş olamaz".into());
+        }
+        Self::check_profile(&profile)?;
+        profile.last_used_at = None;
+
+        sqlx::query(
+            "INSERT INTO import_profiles (name, profile, updated_at) VALUES (?, ?, ?)
+             ON CONFLICT(name) DO UPDATE SET profile = excluded.profile, updated_at = excluded.updated_at",
+        )
+        .bind(&profile.name)
+        .bind(serde_json::to_string(&profile)?)
+        .bind(Local::now().to_rfc3339())
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_profile(pool: &SqlitePool, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("DELETE FROM import_profiles WHERE name = ?")
+            .bind(name.trim())
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Reads the header and first rows so the user can map columns. `csv` is ignored for workbooks.
+    pub fn preview(path: &Path, csv: &CsvOptions, sheet: Option<&str>) -> Result<SpreadsheetPreview, Box<dyn std::error::Error>> {
+        let table = Self::read_table(path, csv, sheet)?;
+        Ok(SpreadsheetPreview {
+            sheets: table.sheets,
+            headers: table.headers,
+            rows: table.rows.iter().take(PREVIEW_ROWS).map(|(_, cells)| cells.iter().map(Cell::text).collect()).collect(),
+        })
+    }
+
+    /// Maps every row of a CSV or workbook through `profile` and imports the result like a JSON
+    /// backup (validation, duplicate matching, strategies). A saved profile is marked as used.
+    pub async fn import(
+        pool: &SqlitePool,
+        path: &Path,
+        profile: &ImportProfile,
+        strategy: ImportStrategy,
+        dry_run: bool,
+        skip_invalid: bool,
+        backup_dir: &Path,
+    ) -> Result<TransactionImportReport, Box<dyn std::error::Error>> {
+        Self::check_profile(profile)?;
+        let table = Self::read_table(path, &profile.csv, profile.sheet.as_deref())?;
+
+        let column = |field: &str, header: &str| {
+            table
+                .headers
+                .iter()
+                .position(|h| fold(h) == fold(header))
+                .ok_or_else(|| format!("Dosyada '{}' kolonu yok ({} alanı için)", header, field))
+        };
+        let c = &profile.columns;
+        let mut fields = vec![
+            ("date", column("date", &c.date)?, false),
+            ("symbol", column("symbol", &c.symbol)?, false),
+            ("type", column("side", &c.side)?, false),
+            ("quantity", column("quantity", &c.quantity)?, true),
+            ("price", column("price", &c.price)?, true),
+        ];
+        for (field, header, numeric) in [
+            ("fees", &c.fees, true),
+            ("currency", &c.currency, false),
+            ("broker", &c.broker, false),
+            ("notes", &c.notes, false),
+        ] {
+            if let Some(header) = header.as_deref().filter(|h| !h.trim().is_empty()) {
+                fields.push((field, column(field, header)?, numeric));
+            }
+        }
+        let sides = side_labels(profile);
+
+        let rows = table
+            .rows
+            .iter()
+            .map(|(line, cells)| {
+                let mut row = serde_json::Map::new();
+                for (field, idx, numeric) in &fields {
+                    let Some(mut value) = cells.get(*idx).and_then(|cell| cell.value(*numeric)) else { continue };
+                    if *field == "type" {
+                        if let Some(side) = value.as_str().and_then(|label| sides.get(&fold(label))) {
+                            value = serde_json::json!(side);
+                        }
+                    }
+                    row.insert(field.to_string(), value);
+                }
+                if let Some(asset_type) = &profile.asset_type {
+                    row.insert("asset_type".to_string(), serde_json::json!(asset_type));
+                }
+                (*line, serde_json::Value::Object(row))
+            })
+            .collect();
+
+        let report = ImportService::import_records(pool, rows, strategy, dry_run, skip_invalid, backup_dir).await?;
+        if !dry_run {
+            sqlx::query("UPDATE import_profiles SET last_used_at = ? WHERE name = ?")
+                .bind(Local::now().to_rfc3339())
+                .bind(profile.name.trim())
+                .execute(pool)
+                .await?;
+        }
+        Ok(report)
+    }
+
+    fn check_profile(profile: &ImportProfile) -> Result<(), String> {
+        let c = &profile.columns;
+        for (field, header) in [("date", &c.date), ("symbol", &c.symbol), ("side", &c.side), ("quantity", &c.quantity), ("price", &c.price)] {
+            if header.trim().is_empty() {
+                return Err(format!("'{}' alanı için kolon seçilmedi", field));
+            }
+        }
+        if let Some((label, side)) = profile.side_values.iter().find(|(_, side)| !SIDES.contains(&side.as_str())) {
+            return Err(format!("'{}' etiketi için geçersiz işlem tipi: {} (buy, sell veya dividend)", label, side));
+        }
+        Ok(())
+    }
+
+    fn read_table(path: &Path, csv: &CsvOptions, sheet: Option<&str>) -> Result<Table, Box<dyn std::error::Error>> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        if !WORKBOOK_EXTENSIONS.contains(&extension.as_str()) {
+            let text = CsvService::decode(&std::fs::read(path)?, csv.encoding)?;
+            let (headers, rows) = CsvService::read_rows(&text, csv.delimiter_byte()?)?;
+            let rows = rows.into_iter().map(|(line, cells)| (line, cells.into_iter().map(Cell::Text).collect())).collect();
+            return Ok(Table { sheets: Vec::new(), headers, rows });
+        }
+
+        let mut workbook = open_workbook_auto(path)?;
+        let sheets = workbook.sheet_names();
+        let name = match sheet {
+            Some(name) => name.to_string(),
+            None => sheets.first().cloned().ok_or("Çalışma kitabında sayfa yok")?,
+        };
+        let range = workbook.worksheet_range(&name)?;
+        let first_line = range.start().map_or(0, |(row, _)| row as usize) + 1;
+
+        let mut lines = range.rows().enumerate();
+        let headers = lines.next().map(|(_, row)| row.iter().map(|c| Self::cell(c).text()).collect()).unwrap_or_default();
+        let rows = lines
+            .map(|(i, row)| (first_line + i, row.iter().map(Self::cell).collect::<Vec<_>>()))
+            .filter(|(_, cells)| cells.iter().any(|c| !c.text().is_empty()))
+            .collect();
+        Ok(Table { sheets, headers, rows })
+    }
+
+    fn cell(data: &Data) -> Cell {
+        match data {
+            Data::Int(n) => Cell::Number(*n as f64),
+            Data::Float(n) => Cell::Number(*n),
+            Data::String(s) | Data::DateTimeIso(s) => Cell::Text(s.trim().to_string()),
+            Data::Bool(b) => Cell::Text(b.to_string()),
+            Data::DateTime(_) => data.as_date().map_or(Cell::Text(String::new()), Cell::Date),
+            Data::DurationIso(_) | Data::Error(_) | Data::Empty => Cell::Text(String::new()),
+        }
+    }
+}
+
+// Folded side label -> "buy"/"sell"/"dividend"; the profile's own entries win over the defaults
+fn side_labels(profile: &ImportProfile) -> HashMap<String, &str> {
+    DEFAULT_SIDE_LABELS
+        .iter()
+        .map(|&(label, side)| (label.to_string(), side))
+        .chain(profile.side_values.iter().map(|(label, side)| (fold(label), side.as_str())))
+        .collect()
+}
+
+// Case- and Turkish-letter-insensitive form of a header or label ("ALIŞ", "Alış" and "alis" match)
+fn fold(value: &str) -> String {
+    value
+        .trim()
+        .chars()
+        .map(|c| match c {
+            'İ' | 'I' | 'ı' => 'i',
+            'Ş' | 'ş' => 's',
+            'Ğ' | 'ğ' => 'g',
+            'Ü' | 'ü' => 'u',
+            'Ö' | 'ö' => 'o',
+            'Ç' | 'ç' => 'c',
+            c => c,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::importer::RowStatus;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::path::PathBuf;
+
+    async fn memory_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::create_schema(&pool).await.unwrap();
+        pool
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mapped_import-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn profile() -> ImportProfile {
+        ImportProfile {
+            name: "Banka".to_string(),
+            columns: ColumnMap {
+                date: "İşlem Tarihi".to_string(),
+                symbol: "Kod".to_string(),
+                side: "Yön".to_string(),
+                quantity: "Adet".to_string(),
+                price: "Fiyat".to_string(),
+                ..ColumnMap::default()
+            },
+            side_values: HashMap::new(),
+            asset_type: Some("hisse".to_string()),
+            csv: CsvOptions { delimiter: ';', ..CsvOptions::default() },
+            sheet: None,
+            last_used_at: None,
+        }
+    }
+
+    #[test]
+    fn fold_ignores_case_and_turkish_letters() {
+        for label in ["ALIŞ", "Alış", "alis", "  aLIs "] {
+            assert_eq!(fold(label), "alis", "{}", label);
+        }
+        assert_eq!(fold("İŞLEM TARİHİ"), fold("işlem tarihi"));
+        assert_eq!(fold("Ğ Ü Ö Ç"), "g u o c");
+        assert_ne!(fold("Satış"), fold("Alış"));
+    }
+
+    #[test]
+    fn profile_side_values_override_default_labels() {
+        let mut profile = profile();
+        let defaults = side_labels(&profile);
+        assert_eq!(defaults.len(), DEFAULT_SIDE_LABELS.len());
+        assert_eq!(defaults[&fold("ALIŞ")], "buy");
+        assert_eq!(defaults[&fold("Satış")], "sell");
+        assert_eq!(defaults[&fold("TEMETTÜ")], "dividend");
+
+        profile.side_values.insert("A".to_string(), "buy".to_string());
+        profile.side_values.insert("Alış".to_string(), "sell".to_string());
+        let sides = side_labels(&profile);
+        assert_eq!(sides[&fold("a")], "buy");
+        assert_eq!(sides[&fold("ALIS")], "sell");
+        assert_eq!(sides[&fold("satış")], "sell");
+    }
+
+    #[test]
+    fn profile_from_the_ui_deserializes() {
+        let json = r#"{
+            "name": "Ekstre",
+            "columns": {"date": "Tarih", "symbol": "Kod", "side": "Yön", "quantity": "Adet", "price": "Fiyat",
+                        "fees": null, "currency": null, "broker": "Kurum", "notes": null},
+            "side_values": {"A": "buy"},
+            "asset_type": null,
+            "csv": {"delimiter": ";", "encoding": "windows-1254", "decimal_comma": true},
+            "sheet": null,
+            "last_used_at": null
+        }"#;
+        let profile: ImportProfile = serde_json::from_str(json).unwrap();
+        assert_eq!(profile.csv.encoding, crate::csv_io::CsvEncoding::Windows1254);
+        assert_eq!(profile.columns.broker.as_deref(), Some("Kurum"));
+    }
+
+    #[tokio::test]
+    async fn maps_side_labels_through_the_import() {
+        let pool = memory_pool().await;
+        let dir = scratch_dir("sides");
+        let path = dir.join("sides.csv");
+        std::fs::write(
+            &path,
+            "İşlem Tarihi;Kod;Yön;Adet;Fiyat\n\
+             01.02.2024;THYAO;ALIŞ;1;10\n\
+             02.02.2024;THYAO;a;1;10\n\
+             03.02.2024;THYAO;SATIS;1;10\n\
+             04.02.2024;THYAO;Temettü;1;2\n\
+             05.02.2024;THYAO;BUY;1;10\n\
+             06.02.2024;THYAO;X;1;10\n",
+        )
+        .unwrap();
+        let mut profile = profile();
+        profile.side_values.insert("A".to_string(), "buy".to_string());
+
+        let report = MappedImportService::import(&pool, &path, &profile, ImportStrategy::SkipDuplicates, false, true, &dir)
+            .await
+            .unwrap();
+        let statuses: Vec<RowStatus> = report.rows.iter().map(|r| r.status).collect();
+        assert_eq!(statuses[..5], [RowStatus::New; 5]);
+        assert_eq!(statuses[5], RowStatus::Invalid);
+
+        let types: Vec<String> = sqlx::query_scalar("SELECT transaction_type FROM transactions ORDER BY transaction_date")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(types, ["BUY", "BUY", "SELL", "DIVIDEND", "BUY"]);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn missing_columns_are_reported() {
+        let pool = memory_pool().await;
+        let dir = scratch_dir("columns");
+        let path = dir.join("columns.csv");
+        std::fs::write(&path, "İşlem Tarihi;Kod;Yön;Adet;Fiyat\n01.02.2024;THYAO;Alış;1;10\n").unwrap();
+        let import = |profile: ImportProfile| {
+            let (pool, path, dir) = (&pool, &path, &dir);
+            async move {
+                MappedImportService::import(pool, path, &profile, ImportStrategy::SkipDuplicates, true, false, dir)
+                    .await
+                    .unwrap_err()
+                    .to_string()
+            }
+        };
+
+        let mut unmapped = profile();
+        unmapped.columns.price = " ".to_string();
+        assert_eq!(import(unmapped).await, "'price' alanı için kolon seçilmedi");
+
+        let mut absent = profile();
+        absent.columns.quantity = "Miktar".to_string();
+        assert_eq!(import(absent).await, "Dosyada 'Miktar' kolonu yok (quantity alanı için)");
+
+        let mut optional = profile();
+        optional.columns.fees = Some("Komisyon".to_string());
+        assert_eq!(import(optional).await, "Dosyada 'Komisyon' kolonu yok (fees alanı için)");
+
+        // Headers match regardless of case and Turkish letters
+        let mut folded = profile();
+        folded.columns.date = "ISLEM TARIHI".to_string();
+        let report = MappedImportService::import(&pool, &path, &folded, ImportStrategy::SkipDuplicates, true, false, &dir)
+            .await
+            .unwrap();
+        assert_eq!(report.transactions.new_rows, 1);
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
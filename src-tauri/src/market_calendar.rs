@@ -0,0 +1,265 @@
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use serde::Serialize;
+
+use crate::manual_assets::MANUAL_ASSET_TYPE;
+
+// Turkey has stayed on UTC+3 all year since 2016
+const ISTANBUL_OFFSET_SECS: i32 = 3 * 3600;
+
+// From the opening auction to the end of the closing session; half days end after the morning session
+const BIST_OPEN: (u32, u32) = (9, 40);
+const BIST_CLOSE: (u32, u32) = (18, 10);
+const BIST_HALF_DAY_CLOSE: (u32, u32) = (12, 30);
+// Funds publish the day's price on TEFAS by mid-morning; before that the previous business day is the latest
+const TEFAS_PUBLICATION: (u32, u32) = (10, 0);
+// FX, gold and crypto are quoted around the clock; a day without a refresh means the source dropped it
+const CONTINUOUS_STALE_AFTER_HOURS: i64 = 24;
+
+const FIXED_HOLIDAYS: [(u32, u32, &str); 7] = [
+    (1, 1, "Yılbaşı"),
+    (4, 23, "Ulusal Egemenlik ve Çocuk Bayramı"),
+    (5, 1, "Emek ve Dayanışma Günü"),
+    (5, 19, "Atatürk'ü Anma, Gençlik ve Spor Bayramı"),
+    (7, 15, "Demokrasi ve Milli Birlik Günü"),
+    (8, 30, "Zafer Bayramı"),
+    (10, 29, "Cumhuriyet Bayramı"),
+];
+
+// First day of each bayram per the Diyanet calendar; the day before is a half day (arife)
+const RAMAZAN_BAYRAMI: [(i32, u32, u32); 11] = [
+    (2020, 5, 24), (2021, 5, 13), (2022, 5, 2), (2023, 4, 21), (2024, 4, 10), (2025, 3, 30),
+    (2026, 3, 20), (2027, 3, 9), (2028, 2, 26), (2029, 2, 14), (2030, 2, 4),
+];
+const KURBAN_BAYRAMI: [(i32, u32, u32); 11] = [
+    (2020, 7, 31), (2021, 7, 20), (2022, 7, 9), (2023, 6, 28), (2024, 6, 16), (2025, 6, 6),
+    (2026, 5, 27), (2027, 5, 16), (2028, 5, 5), (2029, 4, 24), (2030, 4, 13),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Holiday {
+    pub name: &'static str,
+    pub half_day: bool, // markets open in the morning only
+}
+
+pub fn istanbul_now() -> DateTime<FixedOffset> {
+    Utc::now().with_timezone(&istanbul())
+}
+
+fn istanbul() -> FixedOffset {
+    FixedOffset::east_opt(ISTANBUL_OFFSET_SECS).unwrap()
+}
+
+fn time((h, m): (u32, u32)) -> NaiveTime {
+    NaiveTime::from_hms_opt(h, m, 0).unwrap()
+}
+
+fn at(date: NaiveDate, t: NaiveTime) -> DateTime<FixedOffset> {
+    istanbul().from_local_datetime(&date.and_time(t)).unwrap()
+}
+
+pub fn holiday(date: NaiveDate) -> Option<Holiday> {
+    if let Some((_, _, name)) = FIXED_HOLIDAYS.iter().find(|(m, d, _)| date.month() == *m && date.day() == *d) {
+        return Some(Holiday { name, half_day: false });
+    }
+
+    let bayrams = [
+        (&RAMAZAN_BAYRAMI[..], 3, "Ramazan Bayramı", "Ramazan Bayramı Arifesi"),
+        (&KURBAN_BAYRAMI[..], 4, "Kurban Bayramı", "Kurban Bayramı Arifesi"),
+    ];
+    for (table, days, name, eve) in bayrams {
+        for &(y, m, d) in table {
+            let Some(first) = NaiveDate::from_ymd_opt(y, m, d) else { continue };
+            let offset = (date - first).num_days();
+            if (0..days).contains(&offset) {
+                return Some(Holiday { name, half_day: false });
+            }
+            if offset == -1 {
+                return Some(Holiday { name: eve, half_day: true });
+            }
+        }
+    }
+
+    (date.month() == 10 && date.day() == 28).then_some(Holiday { name: "Cumhuriyet Bayramı Arifesi", half_day: true })
+}
+
+/// Weekday that isn't a full-day holiday (half days count as business days).
+pub fn is_business_day(date: NaiveDate) -> bool {
+    !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) && holiday(date).is_none_or(|h| h.half_day)
+}
+
+/// Latest business day on or before `date`.
+pub fn last_business_day(date: NaiveDate) -> NaiveDate {
+    let mut day = date;
+    while !is_business_day(day) {
+        day = day.pred_opt().unwrap();
+    }
+    day
+}
+
+/// Latest business day strictly before `date`.
+pub fn previous_business_day(date: NaiveDate) -> NaiveDate {
+    last_business_day(date.pred_opt().unwrap())
+}
+
+/// BIST session bounds on `date` in Istanbul time, or None when the exchange is closed all day.
+pub fn bist_session(date: NaiveDate) -> Option<(NaiveTime, NaiveTime)> {
+    if !is_business_day(date) {
+        return None;
+    }
+    let close = if holiday(date).is_some_and(|h| h.half_day) { BIST_HALF_DAY_CLOSE } else { BIST_CLOSE };
+    Some((time(BIST_OPEN), time(close)))
+}
+
+pub fn is_bist_open(now: DateTime<FixedOffset>) -> bool {
+    let now = now.with_timezone(&istanbul());
+    bist_session(now.date_naive()).is_some_and(|(open, close)| (open..close).contains(&now.time()))
+}
+
+/// Date of the latest BIST close price available at `now` (today once the session has begun).
+pub fn latest_bist_date(now: DateTime<FixedOffset>) -> NaiveDate {
+    let now = now.with_timezone(&istanbul());
+    let today = now.date_naive();
+    match bist_session(today) {
+        Some((open, _)) if now.time() >= open => today,
+        _ => previous_business_day(today),
+    }
+}
+
+/// Date of the latest fund price TEFAS has published at `now`.
+pub fn latest_tefas_date(now: DateTime<FixedOffset>) -> NaiveDate {
+    let now = now.with_timezone(&istanbul());
+    let today = now.date_naive();
+    if is_business_day(today) && now.time() >= time(TEFAS_PUBLICATION) {
+        today
+    } else {
+        previous_business_day(today)
+    }
+}
+
+/// The date a freshly fetched price of `asset_type` belongs to.
+pub fn price_date(asset_type: &str, now: DateTime<FixedOffset>) -> NaiveDate {
+    match asset_type {
+        "hisse" | "endeks" => latest_bist_date(now),
+        "fon" => latest_tefas_date(now),
+        _ => now.with_timezone(&istanbul()).date_naive(),
+    }
+}
+
+/// Whether prices of `asset_type` can have moved between `since` and `now`. BIST assets only move
+/// during sessions and funds once per publication; FX, gold and crypto are treated as always moving
+/// and manual assets only change when the user records a valuation.
+pub fn has_new_prices_since(asset_type: &str, since: DateTime<FixedOffset>, now: DateTime<FixedOffset>) -> bool {
+    match asset_type {
+        "hisse" | "endeks" => {
+            let since = since.with_timezone(&istanbul());
+            let now = now.with_timezone(&istanbul());
+            let mut day = since.date_naive();
+            while day <= now.date_naive() {
+                if let Some((open, close)) = bist_session(day) {
+                    if at(day, open) < now && at(day, close) > since {
+                        return true;
+                    }
+                }
+                day = day.succ_opt().unwrap();
+            }
+            false
+        }
+        "fon" => latest_tefas_date(now) > latest_tefas_date(since),
+        MANUAL_ASSET_TYPE => false,
+        _ => true,
+    }
+}
+
+/// Whether a price fetched at `last_updated` has fallen behind its market: BIST and TEFAS prices
+/// are stale once a whole session or publication has passed without a refresh (the latest one is
+/// allowed to be pending); continuously quoted types once they are a day old.
+pub fn is_stale(asset_type: &str, last_updated: DateTime<FixedOffset>, now: DateTime<FixedOffset>) -> bool {
+    match asset_type {
+        "hisse" | "endeks" => latest_bist_date(last_updated) < previous_business_day(latest_bist_date(now)),
+        "fon" => latest_tefas_date(last_updated) < previous_business_day(latest_tefas_date(now)),
+        MANUAL_ASSET_TYPE => false, // valued by the user, there is no cadence to miss
+        _ => now - last_updated > chrono::Duration::hours(CONTINUOUS_STALE_AFTER_HOURS),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn istanbul_at(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<FixedOffset> {
+        at(date(y, m, d), time((h, min)))
+    }
+
+    #[test]
+    fn bayram_tables_cover_the_current_year() {
+        // Past the last listed year every bayram silently becomes a business day; extend the tables
+        let year = istanbul_now().year();
+        for (name, table) in [("Ramazan", &RAMAZAN_BAYRAMI), ("Kurban", &KURBAN_BAYRAMI)] {
+            let last = table.iter().map(|&(y, _, _)| y).max().unwrap();
+            assert!(last >= year, "{} Bayramı dates end in {}", name, last);
+        }
+    }
+
+    #[test]
+    fn bayram_days_and_arife() {
+        // Ramazan Bayramı 2024: Wed 10 - Fri 12 April, arife on the 9th
+        for day in 10..=12 {
+            assert_eq!(holiday(date(2024, 4, day)), Some(Holiday { name: "Ramazan Bayramı", half_day: false }));
+            assert!(!is_business_day(date(2024, 4, day)));
+            assert_eq!(bist_session(date(2024, 4, day)), None);
+        }
+        assert_eq!(holiday(date(2024, 4, 13)), None);
+
+        let arife = date(2024, 4, 9);
+        assert_eq!(holiday(arife), Some(Holiday { name: "Ramazan Bayramı Arifesi", half_day: true }));
+        assert!(is_business_day(arife));
+        assert_eq!(bist_session(arife), Some((time(BIST_OPEN), time(BIST_HALF_DAY_CLOSE))));
+        assert!(is_bist_open(istanbul_at(2024, 4, 9, 12, 0)));
+        assert!(!is_bist_open(istanbul_at(2024, 4, 9, 14, 0)));
+
+        // Kurban Bayramı lasts four days
+        assert!((16..=19).all(|day| holiday(date(2024, 6, day)).is_some_and(|h| !h.half_day)));
+        assert_eq!(holiday(date(2024, 6, 20)), None);
+
+        // Mon 15 April: back over the weekend and the three bayram days to the arife
+        assert_eq!(previous_business_day(date(2024, 4, 15)), arife);
+        assert_eq!(holiday(date(2024, 10, 28)), Some(Holiday { name: "Cumhuriyet Bayramı Arifesi", half_day: true }));
+        assert!(!is_business_day(date(2024, 10, 29)));
+    }
+
+    #[test]
+    fn tefas_publishes_at_ten() {
+        assert_eq!(latest_tefas_date(istanbul_at(2024, 3, 5, 9, 59)), date(2024, 3, 4));
+        assert_eq!(latest_tefas_date(istanbul_at(2024, 3, 5, 10, 0)), date(2024, 3, 5));
+        // Monday morning still shows Friday's price
+        assert_eq!(latest_tefas_date(istanbul_at(2024, 3, 4, 9, 0)), date(2024, 3, 1));
+        // Saturday shows Friday's
+        assert_eq!(latest_tefas_date(istanbul_at(2024, 3, 2, 15, 0)), date(2024, 3, 1));
+        // The same instant given in UTC
+        let utc = FixedOffset::east_opt(0).unwrap();
+        let morning = utc.from_local_datetime(&date(2024, 3, 5).and_time(time((6, 30)))).unwrap();
+        assert_eq!(latest_tefas_date(morning), date(2024, 3, 4));
+    }
+
+    #[test]
+    fn no_new_prices_over_a_weekend() {
+        let friday_close = istanbul_at(2024, 3, 1, 18, 30);
+        let monday_early = istanbul_at(2024, 3, 4, 9, 0);
+        let monday_open = istanbul_at(2024, 3, 4, 10, 0);
+
+        assert!(!has_new_prices_since("hisse", friday_close, monday_early));
+        assert!(has_new_prices_since("hisse", friday_close, monday_open));
+        assert!(!has_new_prices_since("endeks", friday_close, istanbul_at(2024, 3, 3, 20, 0)));
+
+        let friday_fund = istanbul_at(2024, 3, 1, 11, 0);
+        assert!(!has_new_prices_since("fon", friday_fund, monday_early));
+        assert!(has_new_prices_since("fon", friday_fund, istanbul_at(2024, 3, 4, 10, 30)));
+
+        assert!(has_new_prices_since("doviz", friday_close, monday_early));
+        assert!(!has_new_prices_since(MANUAL_ASSET_TYPE, friday_close, monday_open));
+    }
+}
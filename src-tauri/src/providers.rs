@@ -0,0 +1,1004 @@
+use chrono::NaiveDate;
+use futures::future::{join_all, BoxFuture};
+use futures::FutureExt;
+use scraper::{Html, Selector};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::market_calendar;
+
+pub type ProviderError = Box<dyn std::error::Error + Send + Sync>;
+
+const BORSAPY_DEFAULT_URL: &str = "https://borsapy-api.onrender.com";
+pub const TEFAS_HISTORY_URL: &str = "https://www.tefas.gov.tr/api/DB/BindHistoryInfo";
+pub const TEFAS_FUND_TYPES: [&str; 5] = ["YAT", "EMK", "BYF", "GYF", "GSYF"];
+
+/// One price observation as returned by a provider, before it's written to `assets`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quote {
+    pub symbol: String,
+    pub name: String,
+    pub asset_type: String,
+    pub price: f64,
+    pub day_change: f64,
+    pub price_date: Option<NaiveDate>, // publication date when it isn't today (TEFAS)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HttpCall {
+    pub url: String,
+    pub status: Option<u16>, // None when the request never got a response
+    pub error: Option<String>,
+}
+
+/// What one source did during a fetch: its HTTP calls, rows it returned and the errors it hit.
+#[derive(Debug, Default, Clone)]
+pub struct SourceActivity {
+    pub requests: Vec<HttpCall>,
+    pub rows_parsed: usize,
+    pub errors: Vec<String>,
+}
+
+/// Collects per-source activity while providers run concurrently; turned into update reports.
+#[derive(Debug, Default)]
+pub struct FetchLog {
+    sources: Mutex<HashMap<&'static str, SourceActivity>>,
+}
+
+impl FetchLog {
+    fn with_source(&self, source: &'static str, f: impl FnOnce(&mut SourceActivity)) {
+        let mut sources = self.sources.lock().unwrap();
+        f(sources.entry(source).or_default());
+    }
+
+    pub fn parsed(&self, source: &'static str, rows: usize) {
+        self.with_source(source, |a| a.rows_parsed += rows);
+    }
+
+    pub fn error(&self, source: &'static str, error: String) {
+        self.with_source(source, |a| a.errors.push(error));
+    }
+
+    /// Sends the request and records its URL and HTTP status under `source`.
+    /// Non-success statuses are returned as errors.
+    pub async fn send(&self, source: &'static str, request: reqwest::RequestBuilder) -> Result<reqwest::Response, ProviderError> {
+        let (client, request) = request.build_split();
+        let request = request?;
+        let url = request.url().to_string();
+        match client.execute(request).await {
+            Ok(response) => {
+                let status = response.status();
+                self.with_source(source, |a| a.requests.push(HttpCall {
+                    url,
+                    status: Some(status.as_u16()),
+                    error: None,
+                }));
+                Ok(response.error_for_status()?)
+            }
+            Err(e) => {
+                self.with_source(source, |a| a.requests.push(HttpCall { url, status: None, error: Some(e.to_string()) }));
+                Err(e.into())
+            }
+        }
+    }
+
+    pub fn into_sources(self) -> HashMap<&'static str, SourceActivity> {
+        self.sources.into_inner().unwrap()
+    }
+}
+
+/// A market data source.
+///
+/// `symbols = None` asks for everything the source lists for `asset_type`; providers that can
+/// only quote individual symbols return `supports_listing() == false` and are skipped for those.
+pub trait PriceProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn supported_types(&self) -> &'static [&'static str];
+    /// How long prices from this source stay fresh for `asset_type`.
+    fn ttl(&self, asset_type: &str) -> chrono::Duration;
+    fn supports_listing(&self) -> bool {
+        true
+    }
+    /// True when the source prices a single symbol with its own request, which is cheaper than
+    /// a full listing when only a handful of symbols is wanted.
+    fn supports_symbol_lookup(&self) -> bool {
+        false
+    }
+    /// HTTP calls go through `log.send` so they show up in the update report.
+    fn fetch<'a>(
+        &'a self,
+        asset_type: &'a str,
+        symbols: Option<&'a [String]>,
+        log: &'a FetchLog,
+    ) -> BoxFuture<'a, Result<Vec<Quote>, ProviderError>>;
+}
+
+/// Routes each asset type to an ordered list of providers and falls back down the list.
+#[derive(Default)]
+pub struct ProviderRegistry {
+    providers: Vec<Arc<dyn PriceProvider>>,
+    routes: HashMap<String, Vec<usize>>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// canlidoviz first for the general asset types, TEFAS first for funds, BorsaPy as fallback.
+    pub fn with_defaults(client: reqwest::Client) -> Self {
+        let mut registry = Self::new();
+        registry.register(Arc::new(CanlidovizProvider::new(client.clone())));
+        registry.register(Arc::new(TefasProvider::new(client.clone())));
+        registry.register(Arc::new(BorsaPyProvider::new(BorsaPyClient::from_env(client))));
+        registry
+    }
+
+    /// Appends the provider to the route of every type it supports, after the ones registered earlier.
+    pub fn register(&mut self, provider: Arc<dyn PriceProvider>) {
+        let index = self.providers.len();
+        for asset_type in provider.supported_types() {
+            self.routes.entry(asset_type.to_string()).or_default().push(index);
+        }
+        self.providers.push(provider);
+    }
+
+    /// Replaces the provider order for `asset_type`; unknown names are ignored.
+    pub fn set_route(&mut self, asset_type: &str, provider_names: &[&str]) {
+        let route = provider_names
+            .iter()
+            .filter_map(|name| self.providers.iter().position(|p| p.name() == *name))
+            .collect();
+        self.routes.insert(asset_type.to_string(), route);
+    }
+
+    pub fn providers_for(&self, asset_type: &str) -> Vec<Arc<dyn PriceProvider>> {
+        self.routes
+            .get(asset_type)
+            .map(|route| route.iter().map(|&i| self.providers[i].clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Route for targeted requests: providers with per-symbol lookups first, listings after.
+    pub fn providers_for_symbols(&self, asset_type: &str) -> Vec<Arc<dyn PriceProvider>> {
+        let mut providers = self.providers_for(asset_type);
+        providers.sort_by_key(|p| !p.supports_symbol_lookup());
+        providers
+    }
+
+    /// Freshness window of the primary provider for `asset_type`.
+    pub fn ttl(&self, asset_type: &str) -> Option<chrono::Duration> {
+        self.providers_for(asset_type).first().map(|p| p.ttl(asset_type))
+    }
+
+    /// Asks each provider in route order until one returns quotes. Returns the provider name
+    /// alongside the quotes, or every provider's error when all of them fail.
+    pub async fn fetch(
+        &self,
+        asset_type: &str,
+        symbols: Option<&[String]>,
+        log: &FetchLog,
+    ) -> Result<(&'static str, Vec<Quote>), ProviderError> {
+        self.fetch_with(self.providers_for(asset_type), asset_type, symbols, log).await
+    }
+
+    /// Like `fetch`, but only asks the providers that come after `provider_name` in the route.
+    pub async fn fetch_after(
+        &self,
+        asset_type: &str,
+        symbols: &[String],
+        provider_name: &str,
+        log: &FetchLog,
+    ) -> Result<(&'static str, Vec<Quote>), ProviderError> {
+        let providers = self
+            .providers_for(asset_type)
+            .into_iter()
+            .skip_while(|p| p.name() != provider_name)
+            .skip(1)
+            .collect();
+        self.fetch_with(providers, asset_type, Some(symbols), log).await
+    }
+
+    /// Quotes exactly `symbols` along `providers_for_symbols`; each later provider is only
+    /// asked for the symbols the earlier ones couldn't price. Quotes come back per provider.
+    pub async fn fetch_symbols(
+        &self,
+        asset_type: &str,
+        symbols: &[String],
+        log: &FetchLog,
+    ) -> Vec<(&'static str, Vec<Quote>)> {
+        let mut remaining = symbols.to_vec();
+        let mut fetched = Vec::new();
+        for provider in self.providers_for_symbols(asset_type) {
+            if remaining.is_empty() {
+                break;
+            }
+            match provider.fetch(asset_type, Some(&remaining), log).await {
+                Ok(quotes) if !quotes.is_empty() => {
+                    log.parsed(provider.name(), quotes.len());
+                    remaining.retain(|s| !quotes.iter().any(|q| q.symbol.eq_ignore_ascii_case(s)));
+                    fetched.push((provider.name(), quotes));
+                }
+                Ok(_) => log.error(provider.name(), format!("no {} quotes", asset_type)),
+                Err(e) => log.error(provider.name(), format!("{}: {}", asset_type, e)),
+            }
+        }
+        fetched
+    }
+
+    async fn fetch_with(
+        &self,
+        providers: Vec<Arc<dyn PriceProvider>>,
+        asset_type: &str,
+        symbols: Option<&[String]>,
+        log: &FetchLog,
+    ) -> Result<(&'static str, Vec<Quote>), ProviderError> {
+        let mut errors = Vec::new();
+        for provider in providers {
+            if symbols.is_none() && !provider.supports_listing() {
+                continue;
+            }
+            let error = match provider.fetch(asset_type, symbols, log).await {
+                Ok(quotes) if !quotes.is_empty() => {
+                    log.parsed(provider.name(), quotes.len());
+                    return Ok((provider.name(), quotes));
+                }
+                Ok(_) => format!("no {} quotes", asset_type),
+                Err(e) => format!("{}: {}", asset_type, e),
+            };
+            log.error(provider.name(), error.clone());
+            errors.push(format!("{}: {}", provider.name(), error));
+        }
+        if errors.is_empty() {
+            return Err(format!("No price provider for asset type {}", asset_type).into());
+        }
+        Err(errors.join("; ").into())
+    }
+}
+
+fn wanted(symbols: Option<&[String]>, symbol: &str) -> bool {
+    symbols.is_none_or(|list| list.iter().any(|s| s.eq_ignore_ascii_case(symbol)))
+}
+
+/// Parses numbers in either Turkish (1.234,56) or English (1,234.56) notation.
+pub fn parse_tr_float(val: &str) -> Option<f64> {
+    let clean = val.trim();
+    if clean.contains(',') && clean.contains('.') {
+        if clean.rfind(',') > clean.rfind('.') {
+            clean.replace('.', "").replace(',', ".").parse().ok()
+        } else {
+            clean.replace(',', "").parse().ok()
+        }
+    } else if clean.contains(',') {
+        clean.replace(',', ".").parse().ok()
+    } else {
+        clean.parse().ok()
+    }
+}
+
+/// HTML listing pages on canlidoviz.com, one per asset type.
+pub struct CanlidovizProvider {
+    client: reqwest::Client,
+}
+
+impl CanlidovizProvider {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+
+    fn page_url(asset_type: &str) -> Option<&'static str> {
+        match asset_type {
+            "doviz" => Some("https://canlidoviz.com/doviz-kurlari"),
+            "emtia" => Some("https://canlidoviz.com/altin-fiyatlari"),
+            "hisse" => Some("https://canlidoviz.com/borsa"),
+            "kripto" => Some("https://canlidoviz.com/kripto-paralar"),
+            _ => None,
+        }
+    }
+
+    pub fn parse_page(html: &str, asset_type: &str) -> Vec<Quote> {
+        let document = Html::parse_document(html);
+        let row_selector = Selector::parse("tr.currency-list-row, tr.table-row-md").unwrap();
+        let name_selector =
+            Selector::parse("span[itemprop='name'], span.truncate.text-theme.text-base")
+                .unwrap();
+        let price_selector = Selector::parse("span[dt='bA'], span[dt='amount']").unwrap();
+        let code_selector =
+            Selector::parse("span[itemprop='currency'], span.table-code, span.code").unwrap();
+        let perc_selector = Selector::parse("span[dt='change'], span[dt='perc'], span[dt='p'], span.table-perc, span.currency-change-text").unwrap();
+
+        let mut quotes = Vec::new();
+        for row in document.select(&row_selector) {
+            let name = row
+                .select(&name_selector)
+                .next()
+                .map(|e| e.text().collect::<String>().trim().to_string());
+            let price_text = row
+                .select(&price_selector)
+                .next()
+                .map(|e| e.text().collect::<String>());
+            let mut symbol = row
+                .select(&code_selector)
+                .next()
+                .map(|e| e.text().collect::<String>().trim().to_string());
+            let perc_text = row
+                .select(&perc_selector)
+                .next()
+                .map(|e| e.text().collect::<String>());
+
+            if let (Some(n), Some(p_t)) = (name, price_text) {
+                if symbol.is_none() || symbol.as_ref().unwrap().is_empty() {
+                    // Try to generate symbol from name if missing
+                    let generated = n
+                        .chars()
+                        .filter(|c| c.is_alphanumeric())
+                        .take(5)
+                        .collect::<String>()
+                        .to_uppercase();
+                    symbol = Some(generated);
+                }
+
+                let mut sym = symbol.unwrap();
+                if asset_type == "kripto" {
+                    sym = format!("{}-C", sym); // Only keeping -C for consistency if needed
+                }
+                // Fix: Remove redundant suffix if present
+                if sym.ends_with("-C-C") {
+                    sym = sym.replace("-C-C", "-C");
+                }
+
+                // Optimized price parsing
+                let clean_price = p_t
+                    .chars()
+                    .filter(|c| c.is_ascii_digit() || *c == ',' || *c == '.')
+                    .collect::<String>();
+                let price = parse_tr_float(&clean_price).unwrap_or(0.0);
+
+                let day_change = if let Some(pt) = perc_text {
+                    let clean_perc = pt
+                        .chars()
+                        .filter(|c| {
+                            c.is_ascii_digit() || *c == ',' || *c == '.' || *c == '-' || *c == '+'
+                        })
+                        .collect::<String>();
+                    parse_tr_float(&clean_perc).unwrap_or(0.0)
+                } else {
+                    0.0
+                };
+
+                if price > 0.0 {
+                    quotes.push(Quote {
+                        symbol: sym,
+                        name: n,
+                        asset_type: asset_type.to_string(),
+                        price,
+                        day_change,
+                        price_date: None,
+                    });
+                }
+            }
+        }
+        quotes
+    }
+}
+
+impl PriceProvider for CanlidovizProvider {
+    fn name(&self) -> &'static str {
+        "canlidoviz"
+    }
+
+    fn supported_types(&self) -> &'static [&'static str] {
+        &["doviz", "emtia", "hisse", "kripto"]
+    }
+
+    fn ttl(&self, _asset_type: &str) -> chrono::Duration {
+        chrono::Duration::minutes(15)
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        asset_type: &'a str,
+        symbols: Option<&'a [String]>,
+        log: &'a FetchLog,
+    ) -> BoxFuture<'a, Result<Vec<Quote>, ProviderError>> {
+        async move {
+            let url = Self::page_url(asset_type).ok_or_else(|| format!("canlidoviz has no {} page", asset_type))?;
+            let html = log.send(self.name(), self.client.get(url)).await?.text().await?;
+            Ok(Self::parse_page(&html, asset_type)
+                .into_iter()
+                .filter(|q| wanted(symbols, &q.symbol))
+                .collect())
+        }
+        .boxed()
+    }
+}
+
+/// TEFAS daily fund prices, fetched for the latest publication date across all fund types.
+pub struct TefasProvider {
+    client: reqwest::Client,
+}
+
+impl TefasProvider {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+
+    async fn fetch_day(&self, fund_type: &str, date_str: &str, log: &FetchLog) -> Option<serde_json::Value> {
+        let params = [
+            ("fontip", fund_type),
+            ("bastarih", date_str),
+            ("bittarih", date_str),
+        ];
+        let request = self.client.post(TEFAS_HISTORY_URL).form(&params);
+        match log.send(self.name(), request).await {
+            Ok(res) => res.json::<serde_json::Value>().await.ok(),
+            Err(_) => None,
+        }
+    }
+
+    pub fn parse_day(json: &serde_json::Value, price_date: Option<NaiveDate>) -> Vec<Quote> {
+        let Some(funds) = json["data"].as_array() else { return Vec::new() };
+        funds
+            .iter()
+            .filter_map(|fund| {
+                let symbol = fund["FONKODU"].as_str().unwrap_or("").to_uppercase();
+                let name = fund["FONUNVAN"].as_str().unwrap_or("").to_string();
+                let price = fund["FIYAT"]
+                    .as_f64()
+                    .or_else(|| fund["SONFIYAT"].as_f64())
+                    .or_else(|| fund["BORSABULTENFIYAT"].as_f64())?;
+                let day_change = fund["GUNLUKGETIRI"].as_f64().unwrap_or(0.0);
+                (price > 0.0).then(|| Quote {
+                    symbol,
+                    name,
+                    asset_type: "fon".to_string(),
+                    price,
+                    day_change,
+                    price_date,
+                })
+            })
+            .collect()
+    }
+}
+
+impl PriceProvider for TefasProvider {
+    fn name(&self) -> &'static str {
+        "tefas"
+    }
+
+    fn supported_types(&self) -> &'static [&'static str] {
+        &["fon"]
+    }
+
+    fn ttl(&self, _asset_type: &str) -> chrono::Duration {
+        chrono::Duration::hours(4)
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        _asset_type: &'a str,
+        symbols: Option<&'a [String]>,
+        log: &'a FetchLog,
+    ) -> BoxFuture<'a, Result<Vec<Quote>, ProviderError>> {
+        async move {
+            // Walk back from the latest expected publication date over business days until YAT has data
+            let mut found = None;
+            let mut target = market_calendar::latest_tefas_date(market_calendar::istanbul_now());
+            for _ in 0..5 {
+                let date_str = target.format("%d.%m.%Y").to_string();
+                if let Some(json) = self.fetch_day("YAT", &date_str, log).await {
+                    if json["data"].as_array().is_some_and(|d| !d.is_empty()) {
+                        found = Some((date_str, json));
+                        break;
+                    }
+                }
+                target = market_calendar::previous_business_day(target);
+            }
+            let Some((valid_date_str, yat)) = found else {
+                return Err("TEFAS returned no prices for the last five business days".into());
+            };
+            let price_date = NaiveDate::parse_from_str(&valid_date_str, "%d.%m.%Y").ok();
+
+            // Now fetch other types concurrently for this valid date
+            let others = join_all(
+                TEFAS_FUND_TYPES
+                    .iter()
+                    .filter(|&&t| t != "YAT")
+                    .map(|t| self.fetch_day(t, &valid_date_str, log)),
+            )
+            .await;
+
+            Ok(std::iter::once(yat)
+                .chain(others.into_iter().flatten())
+                .flat_map(|json| Self::parse_day(&json, price_date))
+                .filter(|q| wanted(symbols, &q.symbol))
+                .collect())
+        }
+        .boxed()
+    }
+}
+
+/// `/stocks/{symbol}/info` and `/indices/{symbol}/info`
+#[derive(Debug, Deserialize)]
+pub struct BorsaPyInfo {
+    pub last: Option<f64>,
+    pub change_percent: Option<f64>,
+    pub description: Option<String>,
+}
+
+/// `/crypto/{symbol}/current` and `/fx/{symbol}/current`
+#[derive(Debug, Deserialize)]
+pub struct BorsaPyCurrent {
+    pub current: BorsaPyPrice,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BorsaPyPrice {
+    pub last: Option<f64>,
+    pub open: Option<f64>,
+}
+
+/// `/funds/{code}/info`
+#[derive(Debug, Deserialize)]
+pub struct BorsaPyFundInfo {
+    pub price: Option<f64>,
+    pub daily_return: Option<f64>,
+    pub name: Option<String>,
+}
+
+/// Typed client for the BorsaPy REST API. The base URL comes from `BORSAPY_API_URL`.
+pub struct BorsaPyClient {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl BorsaPyClient {
+    pub fn new(client: reqwest::Client, base_url: &str) -> Self {
+        Self { client, base_url: base_url.trim_end_matches('/').to_string() }
+    }
+
+    pub fn from_env(client: reqwest::Client) -> Self {
+        let base_url = std::env::var("BORSAPY_API_URL").unwrap_or_else(|_| BORSAPY_DEFAULT_URL.to_string());
+        Self::new(client, &base_url)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str, log: &FetchLog) -> Result<T, ProviderError> {
+        let request = self.client.get(format!("{}{}", self.base_url, path));
+        Ok(log.send("borsapy", request).await?.json::<T>().await?)
+    }
+
+    pub async fn stock_info(&self, symbol: &str, log: &FetchLog) -> Result<BorsaPyInfo, ProviderError> {
+        self.get(&format!("/stocks/{}/info", symbol), log).await
+    }
+
+    pub async fn index_info(&self, symbol: &str, log: &FetchLog) -> Result<BorsaPyInfo, ProviderError> {
+        self.get(&format!("/indices/{}/info", symbol), log).await
+    }
+
+    pub async fn crypto_current(&self, symbol: &str, log: &FetchLog) -> Result<BorsaPyCurrent, ProviderError> {
+        self.get(&format!("/crypto/{}/current", symbol), log).await
+    }
+
+    pub async fn fund_info(&self, code: &str, log: &FetchLog) -> Result<BorsaPyFundInfo, ProviderError> {
+        self.get(&format!("/funds/{}/info", code), log).await
+    }
+
+    pub async fn fx_current(&self, symbol: &str, log: &FetchLog) -> Result<BorsaPyCurrent, ProviderError> {
+        self.get(&format!("/fx/{}/current", symbol), log).await
+    }
+
+    /// Quotes one of our symbols. Crypto is stored as `BTC-C` locally but priced as `BTCTRY`.
+    pub async fn quote(&self, asset_type: &str, symbol: &str, log: &FetchLog) -> Result<Quote, ProviderError> {
+        let code = symbol.trim_end_matches("-C");
+        let (price, day_change, name) = match asset_type {
+            "hisse" | "endeks" => {
+                let info = if asset_type == "hisse" { self.stock_info(code, log).await? } else { self.index_info(code, log).await? };
+                (info.last, info.change_percent, info.description)
+            }
+            "fon" => {
+                let info = self.fund_info(code, log).await?;
+                (info.price, info.daily_return, info.name)
+            }
+            "kripto" | "doviz" => {
+                let current = if asset_type == "kripto" {
+                    let pair = if code.ends_with("TRY") { code.to_string() } else { format!("{}TRY", code) };
+                    self.crypto_current(&pair, log).await?.current
+                } else {
+                    self.fx_current(code, log).await?.current
+                };
+                let change = match (current.last, current.open) {
+                    (Some(last), Some(open)) if open > 0.0 => Some((last / open - 1.0) * 100.0),
+                    _ => None,
+                };
+                (current.last, change, None)
+            }
+            _ => return Err(format!("BorsaPy doesn't cover asset type {}", asset_type).into()),
+        };
+
+        let price = price.filter(|p| *p > 0.0).ok_or_else(|| format!("BorsaPy returned no price for {}", symbol))?;
+        Ok(Quote {
+            symbol: symbol.to_string(),
+            name: name.filter(|n| !n.trim().is_empty()).unwrap_or_else(|| symbol.to_string()),
+            asset_type: asset_type.to_string(),
+            price,
+            day_change: day_change.unwrap_or(0.0),
+            price_date: None,
+        })
+    }
+}
+
+/// BorsaPy REST API; quotes one symbol per request, so it only serves explicit symbol lists.
+pub struct BorsaPyProvider {
+    client: BorsaPyClient,
+}
+
+impl BorsaPyProvider {
+    pub fn new(client: BorsaPyClient) -> Self {
+        Self { client }
+    }
+}
+
+impl PriceProvider for BorsaPyProvider {
+    fn name(&self) -> &'static str {
+        "borsapy"
+    }
+
+    fn supported_types(&self) -> &'static [&'static str] {
+        &["hisse", "endeks", "kripto", "fon", "doviz"]
+    }
+
+    fn ttl(&self, asset_type: &str) -> chrono::Duration {
+        match asset_type {
+            "fon" => chrono::Duration::hours(4),
+            _ => chrono::Duration::minutes(15),
+        }
+    }
+
+    fn supports_listing(&self) -> bool {
+        false
+    }
+
+    fn supports_symbol_lookup(&self) -> bool {
+        true
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        asset_type: &'a str,
+        symbols: Option<&'a [String]>,
+        log: &'a FetchLog,
+    ) -> BoxFuture<'a, Result<Vec<Quote>, ProviderError>> {
+        async move {
+            let symbols = symbols.ok_or("BorsaPy needs explicit symbols")?;
+            let results = join_all(symbols.iter().map(|s| self.client.quote(asset_type, s, log))).await;
+            let mut quotes = Vec::new();
+            let mut errors = Vec::new();
+            for result in results {
+                match result {
+                    Ok(q) => quotes.push(q),
+                    Err(e) => errors.push(e),
+                }
+            }
+            // With nothing quoted, the last error becomes the result and the registry reports it
+            let failure = if quotes.is_empty() { errors.pop() } else { None };
+            for e in errors {
+                log.error(self.name(), e.to_string());
+            }
+            match failure {
+                Some(e) => Err(e),
+                None => Ok(quotes),
+            }
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Minimal HTTP/1.1 server answering canned JSON by path; anything else is a 404.
+    async fn mock_borsapy(routes: Vec<(&'static str, &'static str)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else { break };
+                let routes = routes.clone();
+                tokio::spawn(async move {
+                    let mut buf = vec![0u8; 4096];
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buf[..n]);
+                    let path = request.split_whitespace().nth(1).unwrap_or("");
+                    let response = match routes.iter().find(|(p, _)| *p == path) {
+                        Some((_, body)) => format!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                            body.len(),
+                            body
+                        ),
+                        None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                    };
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        format!("http://{}/", addr)
+    }
+
+    fn routes() -> Vec<(&'static str, &'static str)> {
+        vec![
+            ("/stocks/THYAO/info", r#"{"last": 312.5, "change_percent": -1.25, "description": "TURK HAVA YOLLARI"}"#),
+            ("/indices/XU100/info", r#"{"last": 10250.75, "change_percent": 0.4}"#),
+            ("/crypto/BTCTRY/current", r#"{"current": {"last": 2200000.0, "open": 2000000.0}}"#),
+            ("/funds/AAK/info", r#"{"price": 21.402718, "daily_return": 0.12, "name": "ATA PORTFÖY ÇOKLU VARLIK DEĞİŞKEN FON"}"#),
+            ("/fx/USD/current", r#"{"current": {"last": 34.5, "open": null}}"#),
+            ("/stocks/ZERO/info", r#"{"last": 0, "change_percent": 0}"#),
+        ]
+    }
+
+    #[tokio::test]
+    async fn quotes_each_asset_type_from_borsapy() {
+        let base_url = mock_borsapy(routes()).await;
+        let client = BorsaPyClient::new(reqwest::Client::new(), &base_url);
+        let log = FetchLog::default();
+
+        let stock = client.quote("hisse", "THYAO", &log).await.unwrap();
+        assert_eq!(stock.price, 312.5);
+        assert_eq!(stock.day_change, -1.25);
+        assert_eq!(stock.name, "TURK HAVA YOLLARI");
+
+        let index = client.quote("endeks", "XU100", &log).await.unwrap();
+        assert_eq!((index.price, index.name.as_str()), (10250.75, "XU100"));
+
+        // Local crypto symbols carry a -C suffix and are priced against TRY
+        let crypto = client.quote("kripto", "BTC-C", &log).await.unwrap();
+        assert_eq!(crypto.symbol, "BTC-C");
+        assert!((crypto.day_change - 10.0).abs() < 1e-9);
+
+        let fund = client.quote("fon", "AAK", &log).await.unwrap();
+        assert_eq!((fund.price, fund.day_change), (21.402718, 0.12));
+
+        let fx = client.quote("doviz", "USD", &log).await.unwrap();
+        assert_eq!((fx.price, fx.day_change), (34.5, 0.0));
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_and_zero_prices() {
+        let base_url = mock_borsapy(routes()).await;
+        let client = BorsaPyClient::new(reqwest::Client::new(), &base_url);
+        let log = FetchLog::default();
+
+        assert!(client.quote("hisse", "NOPE", &log).await.is_err());
+        assert!(client.quote("hisse", "ZERO", &log).await.is_err());
+        assert!(client.quote("emtia", "GRAM", &log).await.is_err());
+
+        let requests = &log.into_sources()["borsapy"].requests;
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].url.ends_with("/stocks/NOPE/info"));
+        assert_eq!(requests[0].status, Some(404));
+        assert_eq!(requests[1].status, Some(200));
+    }
+
+    #[tokio::test]
+    async fn provider_keeps_partial_results() {
+        let base_url = mock_borsapy(routes()).await;
+        let provider = BorsaPyProvider::new(BorsaPyClient::new(reqwest::Client::new(), &base_url));
+        let log = FetchLog::default();
+
+        let symbols = vec!["THYAO".to_string(), "NOPE".to_string()];
+        let quotes = provider.fetch("hisse", Some(&symbols), &log).await.unwrap();
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].symbol, "THYAO");
+        assert_eq!(log.into_sources()["borsapy"].errors.len(), 1);
+        let log = FetchLog::default();
+
+        assert!(provider.fetch("hisse", Some(&["NOPE".to_string()]), &log).await.is_err());
+        assert!(provider.fetch("hisse", None, &log).await.is_err());
+    }
+
+    /// Canned provider: quotes `prices` for the symbols asked for, or fails every request when
+    /// `prices` is `None`. Records each call's symbol list.
+    struct StubProvider {
+        name: &'static str,
+        types: &'static [&'static str],
+        prices: Option<Vec<(&'static str, f64)>>,
+        listing: bool,
+        lookup: bool,
+        calls: Mutex<Vec<Option<Vec<String>>>>,
+    }
+
+    impl StubProvider {
+        fn new(name: &'static str, types: &'static [&'static str], prices: Option<Vec<(&'static str, f64)>>) -> Self {
+            Self { name, types, prices, listing: true, lookup: false, calls: Mutex::new(Vec::new()) }
+        }
+
+        fn calls(&self) -> Vec<Option<Vec<String>>> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    impl PriceProvider for StubProvider {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn supported_types(&self) -> &'static [&'static str] {
+            self.types
+        }
+
+        fn ttl(&self, _asset_type: &str) -> chrono::Duration {
+            chrono::Duration::minutes(5)
+        }
+
+        fn supports_listing(&self) -> bool {
+            self.listing
+        }
+
+        fn supports_symbol_lookup(&self) -> bool {
+            self.lookup
+        }
+
+        fn fetch<'a>(
+            &'a self,
+            asset_type: &'a str,
+            symbols: Option<&'a [String]>,
+            _log: &'a FetchLog,
+        ) -> BoxFuture<'a, Result<Vec<Quote>, ProviderError>> {
+            self.calls.lock().unwrap().push(symbols.map(|s| s.to_vec()));
+            let result = match &self.prices {
+                Some(prices) => Ok(prices
+                    .iter()
+                    .filter(|(symbol, _)| wanted(symbols, symbol))
+                    .map(|&(symbol, price)| Quote {
+                        symbol: symbol.to_string(),
+                        name: symbol.to_string(),
+                        asset_type: asset_type.to_string(),
+                        price,
+                        day_change: 0.0,
+                        price_date: None,
+                    })
+                    .collect()),
+                None => Err(format!("{} is down", self.name).into()),
+            };
+            async move { result }.boxed()
+        }
+    }
+
+    fn symbols(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn falls_back_when_the_first_provider_errors() {
+        let first = Arc::new(StubProvider::new("first", &["hisse"], None));
+        let second = Arc::new(StubProvider::new("second", &["hisse"], Some(vec![("THYAO", 312.5)])));
+        let mut registry = ProviderRegistry::new();
+        registry.register(first.clone());
+        registry.register(second.clone());
+        let log = FetchLog::default();
+
+        let (source, quotes) = registry.fetch("hisse", None, &log).await.unwrap();
+        assert_eq!(source, "second");
+        assert_eq!(quotes[0].price, 312.5);
+        assert_eq!((first.calls().len(), second.calls().len()), (1, 1));
+
+        let sources = log.into_sources();
+        assert_eq!(sources["first"].errors, vec!["hisse: first is down".to_string()]);
+        assert_eq!(sources["second"].rows_parsed, 1);
+        assert!(sources["second"].errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn surfaces_every_error_when_all_providers_fail() {
+        let mut registry = ProviderRegistry::new();
+        registry.register(Arc::new(StubProvider::new("first", &["hisse"], None)));
+        registry.register(Arc::new(StubProvider::new("second", &["hisse"], Some(vec![]))));
+        let log = FetchLog::default();
+
+        let error = registry.fetch("hisse", None, &log).await.unwrap_err();
+        assert_eq!(error.to_string(), "first: hisse: first is down; second: no hisse quotes");
+        assert_eq!(log.into_sources()["second"].errors, vec!["no hisse quotes".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn skips_unsupported_types_and_lookup_only_providers() {
+        let stocks = Arc::new(StubProvider::new("stocks", &["hisse"], Some(vec![("THYAO", 312.5)])));
+        let mut lookup = StubProvider::new("lookup", &["fon"], Some(vec![("AAK", 21.4)]));
+        lookup.listing = false;
+        let lookup = Arc::new(lookup);
+        let mut registry = ProviderRegistry::new();
+        registry.register(stocks.clone());
+        registry.register(lookup.clone());
+        let log = FetchLog::default();
+
+        let error = registry.fetch("kripto", None, &log).await.unwrap_err();
+        assert_eq!(error.to_string(), "No price provider for asset type kripto");
+        // A full fund listing can't come from a provider that only prices single symbols
+        let error = registry.fetch("fon", None, &log).await.unwrap_err();
+        assert_eq!(error.to_string(), "No price provider for asset type fon");
+        assert!(lookup.calls().is_empty());
+        assert!(stocks.calls().is_empty());
+
+        let (source, quotes) = registry.fetch("fon", Some(&symbols(&["AAK"])), &log).await.unwrap();
+        assert_eq!((source, quotes.len()), ("lookup", 1));
+        assert!(stocks.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn fetch_after_only_asks_later_providers() {
+        let first = Arc::new(StubProvider::new("first", &["hisse"], Some(vec![("THYAO", 312.5)])));
+        let second = Arc::new(StubProvider::new("second", &["hisse"], Some(vec![("THYAO", 313.0)])));
+        let mut registry = ProviderRegistry::new();
+        registry.register(first.clone());
+        registry.register(second.clone());
+        let log = FetchLog::default();
+        let wanted = symbols(&["THYAO"]);
+
+        let (source, quotes) = registry.fetch_after("hisse", &wanted, "first", &log).await.unwrap();
+        assert_eq!((source, quotes[0].price), ("second", 313.0));
+        assert!(first.calls().is_empty());
+        assert_eq!(second.calls(), vec![Some(wanted.clone())]);
+
+        let error = registry.fetch_after("hisse", &wanted, "second", &log).await.unwrap_err();
+        assert_eq!(error.to_string(), "No price provider for asset type hisse");
+    }
+
+    #[tokio::test]
+    async fn fetch_symbols_asks_later_providers_for_the_rest() {
+        let listing = Arc::new(StubProvider::new(
+            "listing",
+            &["hisse"],
+            Some(vec![("THYAO", 312.5), ("GARAN", 120.0), ("ASELS", 60.0)]),
+        ));
+        let mut lookup = StubProvider::new("lookup", &["hisse"], Some(vec![("THYAO", 313.0)]));
+        lookup.lookup = true;
+        let lookup = Arc::new(lookup);
+        let broken = Arc::new(StubProvider::new("broken", &["hisse"], None));
+        let mut registry = ProviderRegistry::new();
+        registry.register(broken.clone());
+        registry.register(listing.clone());
+        registry.register(lookup.clone());
+        let log = FetchLog::default();
+
+        let fetched = registry.fetch_symbols("hisse", &symbols(&["thyao", "GARAN"]), &log).await;
+        let by_source: Vec<_> = fetched
+            .iter()
+            .map(|(source, quotes)| (*source, quotes.iter().map(|q| q.symbol.as_str()).collect::<Vec<_>>()))
+            .collect();
+        // Per-symbol lookups go first, then the route order for whatever is left
+        assert_eq!(by_source, vec![("lookup", vec!["THYAO"]), ("listing", vec!["GARAN"])]);
+        assert_eq!(broken.calls(), vec![Some(symbols(&["GARAN"]))]);
+        assert_eq!(listing.calls(), vec![Some(symbols(&["GARAN"]))]);
+        assert_eq!(log.into_sources()["broken"].errors, vec!["hisse: broken is down".to_string()]);
+    }
+
+    #[test]
+    fn parses_a_saved_canlidoviz_page() {
+        let html = include_str!("../tests/fixtures/canlidoviz_doviz.html");
+        let quotes = CanlidovizProvider::parse_page(html, "doviz");
+
+        let summary: Vec<_> = quotes
+            .iter()
+            .map(|q| (q.symbol.as_str(), q.name.as_str(), q.price, q.day_change))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("USD", "Amerikan Doları", 34.512, 0.25),
+                ("EUR", "Euro", 37.1045, -0.12),
+                // No code on the row, so the symbol comes from the name
+                ("STERL", "Sterlin", 44.88, 0.0),
+                // RUB has no price and is dropped; CHF uses the alternate row layout
+                ("CHF", "İsviçre Frangı", 1039.801, 1.05),
+            ]
+        );
+        assert!(quotes.iter().all(|q| q.asset_type == "doviz" && q.price_date.is_none()));
+
+        let crypto = CanlidovizProvider::parse_page(html, "kripto");
+        assert_eq!(crypto[0].symbol, "USD-C");
+    }
+}
@@ -0,0 +1,415 @@
+use chrono::{Datelike, Local, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::corporate_actions::{self, CorporateAction};
+use crate::lots::{self, LedgerTransaction};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReturnMetrics {
+    pub symbol: Option<String>,
+    pub start_date: String,
+    pub end_date: String,
+    pub start_value: f64,
+    pub end_value: f64,
+    pub net_cash_flow: f64, // money put in (buys) minus money taken out (sells, dividends)
+    pub gain: f64,
+    pub twr_pct: f64,
+    pub xirr_pct: Option<f64>, // annualized; None when the solver finds no root
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PeriodReturn {
+    pub period: String, // 1M, 3M, YTD, 1Y, ALL
+    pub metrics: Option<ReturnMetrics>,
+}
+
+/// Cash movement between the investor and the portfolio on a given day.
+#[derive(Debug, Clone)]
+pub struct CashFlow {
+    pub date: NaiveDate,
+    pub symbol: String,
+    pub quantity_delta: f64,
+    pub amount: f64, // positive = contribution, negative = withdrawal
+}
+
+/// Everything needed to value a set of symbols on any past date.
+#[derive(Debug, Default)]
+pub struct Valuation {
+    flows: Vec<CashFlow>,
+    prices: HashMap<String, BTreeMap<NaiveDate, f64>>,
+    current_prices: HashMap<String, f64>,
+    today: NaiveDate,
+}
+
+impl Valuation {
+    pub fn new(
+        flows: Vec<CashFlow>,
+        prices: HashMap<String, BTreeMap<NaiveDate, f64>>,
+        current_prices: HashMap<String, f64>,
+        today: NaiveDate,
+    ) -> Self {
+        let mut flows = flows;
+        flows.sort_by_key(|f| f.date);
+        Self { flows, prices, current_prices, today }
+    }
+
+    pub fn first_flow_date(&self) -> Option<NaiveDate> {
+        self.flows.first().map(|f| f.date)
+    }
+
+    fn price_on(&self, symbol: &str, date: NaiveDate) -> f64 {
+        if date >= self.today {
+            if let Some(p) = self.current_prices.get(symbol).filter(|p| **p > 0.0) {
+                return *p;
+            }
+        }
+        self.prices
+            .get(symbol)
+            .and_then(|series| series.range(..=date).next_back())
+            .map(|(_, p)| *p)
+            .unwrap_or(0.0)
+    }
+
+    fn value(&self, quantities: &HashMap<String, f64>, date: NaiveDate) -> f64 {
+        quantities
+            .iter()
+            .filter(|(_, q)| **q > 0.0)
+            .map(|(s, q)| q * self.price_on(s, date))
+            .sum()
+    }
+
+    /// TWR chain-linked at every cash flow day, and XIRR over the same flows.
+    /// Values are end-of-day: flows dated `start` are part of the opening value.
+    pub fn compute(&self, start: NaiveDate, end: NaiveDate) -> ReturnMetrics {
+        let mut quantities: HashMap<String, f64> = HashMap::new();
+        let mut idx = 0;
+        while idx < self.flows.len() && self.flows[idx].date <= start {
+            let f = &self.flows[idx];
+            *quantities.entry(f.symbol.clone()).or_default() += f.quantity_delta;
+            idx += 1;
+        }
+
+        let start_value = self.value(&quantities, start);
+        let mut prev_value = start_value;
+        let mut growth = 1.0;
+        let mut net_cash_flow = 0.0;
+        let mut xirr_flows = Vec::new();
+        if start_value > 0.0 {
+            xirr_flows.push((start, -start_value));
+        }
+
+        while idx < self.flows.len() && self.flows[idx].date <= end {
+            let day = self.flows[idx].date;
+            let mut day_flow = 0.0;
+            while idx < self.flows.len() && self.flows[idx].date == day {
+                let f = &self.flows[idx];
+                *quantities.entry(f.symbol.clone()).or_default() += f.quantity_delta;
+                day_flow += f.amount;
+                idx += 1;
+            }
+
+            let value = self.value(&quantities, day);
+            if prev_value > 0.0 {
+                growth *= (value - day_flow) / prev_value;
+            }
+            prev_value = value;
+            net_cash_flow += day_flow;
+            xirr_flows.push((day, -day_flow));
+        }
+
+        let end_value = self.value(&quantities, end);
+        if prev_value > 0.0 {
+            growth *= end_value / prev_value;
+        }
+        xirr_flows.push((end, end_value));
+
+        ReturnMetrics {
+            symbol: None,
+            start_date: start.format("%Y-%m-%d").to_string(),
+            end_date: end.format("%Y-%m-%d").to_string(),
+            start_value,
+            end_value,
+            net_cash_flow,
+            gain: end_value - start_value - net_cash_flow,
+            twr_pct: (growth - 1.0) * 100.0,
+            xirr_pct: xirr(&xirr_flows).map(|r| r * 100.0),
+        }
+    }
+}
+
+/// Annualized internal rate of return for dated cash flows (negative = paid in).
+pub fn xirr(flows: &[(NaiveDate, f64)]) -> Option<f64> {
+    let first = flows.iter().map(|(d, _)| *d).min()?;
+    let has_in = flows.iter().any(|(_, a)| *a < 0.0);
+    let has_out = flows.iter().any(|(_, a)| *a > 0.0);
+    if !has_in || !has_out {
+        return None;
+    }
+
+    let years: Vec<(f64, f64)> = flows
+        .iter()
+        .map(|(d, a)| ((*d - first).num_days() as f64 / 365.0, *a))
+        .collect();
+    let npv = |r: f64| -> f64 { years.iter().map(|(t, a)| a / (1.0 + r).powf(*t)).sum() };
+    let d_npv = |r: f64| -> f64 { years.iter().map(|(t, a)| -t * a / (1.0 + r).powf(t + 1.0)).sum() };
+
+    // Newton-Raphson first, bisection if it wanders off
+    let mut rate = 0.1;
+    for _ in 0..100 {
+        let value = npv(rate);
+        let derivative = d_npv(rate);
+        if derivative.abs() < 1e-12 {
+            break;
+        }
+        let next = rate - value / derivative;
+        if !next.is_finite() || next <= -0.999_999 {
+            break;
+        }
+        if (next - rate).abs() < 1e-10 {
+            return Some(next);
+        }
+        rate = next;
+    }
+
+    let (mut low, mut high) = (-0.999_999, 1e6);
+    let (mut f_low, f_high) = (npv(low), npv(high));
+    if f_low * f_high > 0.0 {
+        return None;
+    }
+    for _ in 0..300 {
+        let mid = (low + high) / 2.0;
+        let f_mid = npv(mid);
+        if f_mid.abs() < 1e-9 || (high - low) < 1e-12 {
+            return Some(mid);
+        }
+        if f_low * f_mid < 0.0 {
+            high = mid;
+        } else {
+            low = mid;
+            f_low = f_mid;
+        }
+    }
+    Some((low + high) / 2.0)
+}
+
+pub struct ReturnService;
+
+impl ReturnService {
+    /// Builds the valuation inputs for the whole portfolio, or a single holding when `symbol` is given.
+    pub async fn load_valuation(pool: &SqlitePool, symbol: Option<&str>) -> Result<Valuation, Box<dyn std::error::Error>> {
+        let symbol = symbol.map(|s| s.to_uppercase());
+        let transactions: Vec<LedgerTransaction> = LedgerTransaction::load_all(pool)
+            .await?
+            .into_iter()
+            .filter(|t| symbol.as_ref().is_none_or(|s| s == &t.symbol.to_uppercase()))
+            .collect();
+        let actions: Vec<CorporateAction> = CorporateAction::load_all(pool)
+            .await?
+            .into_iter()
+            .filter(|a| symbol.as_ref().is_none_or(|s| s == &a.symbol))
+            .collect();
+
+        let mut flows = Vec::new();
+        let mut prices: HashMap<String, BTreeMap<NaiveDate, f64>> = HashMap::new();
+        for t in &transactions {
+            let Some(date) = lots::parse_date(&t.transaction_date) else { continue };
+            let sym = t.symbol.to_uppercase();
+            let gross = t.quantity * t.price;
+            let fees = t.fees.unwrap_or(0.0);
+            if t.is_dividend() {
+                // Dividends are cash paid out to the investor; their price is per-share income, not a quote
+                let net = gross - t.withholding_tax.unwrap_or(0.0) - fees;
+                flows.push(CashFlow { date, symbol: sym, quantity_delta: 0.0, amount: -net });
+                continue;
+            }
+            // Quantities and trade prices are restated in post-split shares, like the stored price history
+            let factor = corporate_actions::adjustment_factor(&actions, &sym, &t.transaction_date);
+            let (quantity_delta, amount) = if t.is_buy() {
+                (t.quantity * factor, gross + fees)
+            } else {
+                (-t.quantity * factor, -(gross - fees))
+            };
+            flows.push(CashFlow { date, symbol: sym.clone(), quantity_delta, amount });
+            // Trade prices fill gaps where no stored price history exists
+            prices.entry(sym).or_default().entry(date).or_insert(t.price / factor);
+        }
+
+        // Rights issues bring new shares in exchange for the subscription payment
+        for a in actions.iter().filter(|a| a.is_rights()) {
+            let Some(date) = lots::parse_date(&a.ex_date) else { continue };
+            let held: f64 = flows.iter()
+                .filter(|f| f.symbol == a.symbol && f.date < date)
+                .map(|f| f.quantity_delta)
+                .sum();
+            if held <= 0.0 { continue; }
+            let new_shares = held * a.ratio;
+            let factor = corporate_actions::adjustment_factor(&actions, &a.symbol, &a.ex_date);
+            let amount = new_shares / factor * a.subscription_price.unwrap_or(0.0);
+            flows.push(CashFlow { date, symbol: a.symbol.clone(), quantity_delta: new_shares, amount });
+        }
+
+        let history = sqlx::query_as::<_, (String, f64, String)>(
+            "SELECT symbol, price, snapshot_date FROM asset_price_history WHERE price > 0"
+        )
+        .fetch_all(pool)
+        .await?;
+        for (sym, price, date) in history {
+            let sym = sym.to_uppercase();
+            if !prices.contains_key(&sym) { continue; }
+            if let Some(date) = lots::parse_date(&date) {
+                prices.entry(sym).or_default().insert(date, price);
+            }
+        }
+
+        let current_prices: HashMap<String, f64> = sqlx::query_as::<_, (String, Option<f64>)>(
+            "SELECT symbol, current_price FROM assets"
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .filter_map(|(s, p)| p.map(|p| (s.to_uppercase(), p)))
+        .collect();
+
+        Ok(Valuation::new(flows, prices, current_prices, Local::now().date_naive()))
+    }
+
+    pub async fn get_returns(
+        pool: &SqlitePool,
+        symbol: Option<String>,
+        start_date: Option<String>,
+        end_date: Option<String>,
+    ) -> Result<Option<ReturnMetrics>, Box<dyn std::error::Error>> {
+        let valuation = Self::load_valuation(pool, symbol.as_deref()).await?;
+        let Some(inception) = valuation.first_flow_date() else { return Ok(None) };
+
+        let start = match &start_date {
+            Some(s) => lots::parse_date(s).ok_or_else(|| format!("Invalid start date: {}", s))?,
+            None => inception.pred_opt().unwrap_or(inception),
+        };
+        let end = match &end_date {
+            Some(e) => lots::parse_date(e).ok_or_else(|| format!("Invalid end date: {}", e))?,
+            None => valuation.today,
+        };
+        if end < start {
+            return Err("End date is before start date".into());
+        }
+
+        let mut metrics = valuation.compute(start, end);
+        metrics.symbol = symbol.map(|s| s.to_uppercase());
+        Ok(Some(metrics))
+    }
+
+    /// Returns for 1M, 3M, YTD, 1Y and since inception, all ending today.
+    pub async fn get_period_returns(pool: &SqlitePool, symbol: Option<String>) -> Result<Vec<PeriodReturn>, Box<dyn std::error::Error>> {
+        let valuation = Self::load_valuation(pool, symbol.as_deref()).await?;
+        let today = valuation.today;
+        let inception = valuation.first_flow_date().and_then(|d| d.pred_opt());
+
+        let periods = [
+            ("1M", today.checked_sub_months(Months::new(1))),
+            ("3M", today.checked_sub_months(Months::new(3))),
+            ("YTD", NaiveDate::from_ymd_opt(today.year() - 1, 12, 31)),
+            ("1Y", today.checked_sub_months(Months::new(12))),
+            ("ALL", inception),
+        ];
+
+        Ok(periods
+            .into_iter()
+            .map(|(period, start)| PeriodReturn {
+                period: period.to_string(),
+                metrics: start.filter(|_| inception.is_some()).map(|start| {
+                    // A period reaching back before the first trade is measured since inception
+                    let start = inception.map_or(start, |i| start.max(i));
+                    let mut m = valuation.compute(start, today);
+                    m.symbol = symbol.as_ref().map(|s| s.to_uppercase());
+                    m
+                }),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn flow(d: &str, quantity_delta: f64, amount: f64) -> CashFlow {
+        CashFlow { date: date(d), symbol: "AAA".to_string(), quantity_delta, amount }
+    }
+
+    #[test]
+    fn xirr_of_a_single_flow_pair() {
+        // 1000 in, 1100 out exactly 365 days later
+        let rate = xirr(&[(date("2023-01-01"), -1000.0), (date("2024-01-01"), 1100.0)]).unwrap();
+        assert!((rate - 0.10).abs() < 1e-9, "{}", rate);
+
+        // A lone flow has no rate
+        assert_eq!(xirr(&[(date("2023-01-01"), -1000.0)]), None);
+        assert_eq!(xirr(&[]), None);
+    }
+
+    #[test]
+    fn xirr_of_multiple_flows() {
+        // The spreadsheet XIRR reference example, also on a 365-day year
+        let flows = [
+            (date("2008-01-01"), -10000.0),
+            (date("2008-03-01"), 2750.0),
+            (date("2008-10-30"), 4250.0),
+            (date("2009-02-15"), 3250.0),
+            (date("2009-04-01"), 2750.0),
+        ];
+        let rate = xirr(&flows).unwrap();
+        assert!((rate - 0.373_362_535).abs() < 1e-6, "{}", rate);
+    }
+
+    #[test]
+    fn xirr_falls_back_to_bisection_when_newton_overshoots() {
+        // From 0.1 the first Newton step lands near -120, below -100%, so bisection has to find -99%
+        let flows = [(date("2023-01-01"), -100.0), (date("2024-01-01"), 1.0)];
+        let rate = xirr(&flows).unwrap();
+        assert!((rate + 0.99).abs() < 1e-6, "{}", rate);
+    }
+
+    #[test]
+    fn twr_chain_links_around_cash_flows() {
+        let prices = HashMap::from([(
+            "AAA".to_string(),
+            BTreeMap::from([(date("2024-01-01"), 100.0), (date("2024-02-01"), 110.0), (date("2024-03-01"), 99.0)]),
+        )]);
+        // Buy 10 @ 100, then double the position @ 110 right before a 10% drop
+        let valuation = Valuation::new(
+            vec![flow("2024-02-01", 10.0, 1100.0), flow("2024-01-01", 10.0, 1000.0)],
+            prices,
+            HashMap::new(),
+            date("2025-01-01"),
+        );
+        assert_eq!(valuation.first_flow_date(), Some(date("2024-01-01")));
+
+        let m = valuation.compute(date("2024-01-01"), date("2024-03-01"));
+        assert_eq!(m.start_value, 1000.0);
+        assert!((m.end_value - 1980.0).abs() < 1e-9);
+        assert!((m.net_cash_flow - 1100.0).abs() < 1e-9);
+        assert!((m.gain + 120.0).abs() < 1e-9);
+        // +10% then -10%, whatever the amounts: 1.1 * 0.9 - 1
+        assert!((m.twr_pct + 1.0).abs() < 1e-9, "{}", m.twr_pct);
+        // Money-weighted is worse since most money went in before the drop
+        assert!(m.xirr_pct.unwrap() < m.twr_pct);
+    }
+
+    #[test]
+    fn twr_uses_current_price_from_today() {
+        let prices = HashMap::from([("AAA".to_string(), BTreeMap::from([(date("2024-01-01"), 100.0)]))]);
+        let current = HashMap::from([("AAA".to_string(), 125.0)]);
+        let valuation = Valuation::new(vec![flow("2024-01-01", 10.0, 1000.0)], prices, current, date("2024-06-01"));
+
+        let m = valuation.compute(date("2024-01-01"), date("2024-06-01"));
+        assert!((m.end_value - 1250.0).abs() < 1e-9);
+        assert!((m.twr_pct - 25.0).abs() < 1e-9);
+    }
+}
@@ -1,10 +1,86 @@
-use chrono::Local;
+use chrono::{Local, NaiveDate, TimeZone};
 use futures::future::join_all;
-use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
+use std::collections::HashMap;
+
+use crate::corporate_actions::{self, CorporateAction};
+use crate::lots::{self, LotLedger};
+use crate::manual_assets::MANUAL_ASSET_TYPE;
+use crate::market_calendar;
+use crate::providers::{FetchLog, HttpCall, ProviderRegistry, Quote, TEFAS_FUND_TYPES, TEFAS_HISTORY_URL};
+
+// TEFAS rejects long date ranges; 60 days per request stays well inside its limit
+const TEFAS_HISTORY_CHUNK_DAYS: i64 = 60;
+// Types refreshed by the "general" update; endeks has no listing source and is quoted per held symbol
+const GENERAL_ASSET_TYPES: [&str; 5] = ["doviz", "emtia", "hisse", "kripto", "endeks"];
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BackfillResult {
+    pub symbol: String,
+    pub from_date: String,
+    pub to_date: String,
+    pub chunks_fetched: usize,
+    pub rows_stored: usize,
+    pub completed: bool,
+    pub error: Option<String>,
+}
+
+/// Per-source outcome of one market data update.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SourceReport {
+    pub source: String,
+    pub requests: Vec<HttpCall>,
+    pub rows_parsed: usize,
+    pub rows_upserted: usize,
+    pub changed_symbols: Vec<String>,
+    pub skipped_cached: bool,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct UpdateReport {
+    pub update_type: String,
+    pub started_at: String,
+    pub duration_ms: i64,
+    pub sources: Vec<SourceReport>,
+}
+
+/// One `update_log` row, i.e. one source of a past update.
+#[derive(Debug, Serialize, Clone)]
+pub struct UpdateLogEntry {
+    pub id: i64,
+    pub update_type: String,
+    pub started_at: String,
+    pub duration_ms: i64,
+    #[serde(flatten)]
+    pub report: SourceReport,
+}
+
+#[derive(sqlx::FromRow)]
+struct UpdateLogRow {
+    id: i64,
+    update_type: String,
+    source: String,
+    started_at: String,
+    duration_ms: i64,
+    skipped_cached: bool,
+    rows_parsed: i64,
+    rows_upserted: i64,
+    changed_symbols: String,
+    requests: String,
+    errors: String,
+}
 
 pub struct ScraperService {
     client: reqwest::Client,
+    registry: ProviderRegistry,
+}
+
+impl Default for ScraperService {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ScraperService {
@@ -14,339 +90,1075 @@ impl ScraperService {
             h.insert("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36".parse().unwrap());
             h
         };
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()
+            .unwrap();
         Self {
-            client: reqwest::Client::builder()
-                .default_headers(headers)
-                .build()
-                .unwrap(),
+            registry: ProviderRegistry::with_defaults(client.clone()),
+            client,
         }
     }
 
-    pub async fn update_general_assets(
+    /// Runs a "general", "tefas", "all" or "held" update and records one `update_log` row per source.
+    /// "held" only quotes symbols with an open position whose price is older than its TTL.
+    pub async fn update_market_data(
         &self,
         pool: &SqlitePool,
+        update_type: &str,
         force: bool,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        if !force {
-            if let Ok(Some(last_str)) = sqlx::query_scalar::<_, Option<String>>(
-                "SELECT MAX(last_updated) FROM assets WHERE asset_type IN ('doviz', 'emtia', 'hisse', 'kripto')"
-            ).fetch_optional(pool).await {
-                if let Some(last_updated) = last_str {
-                    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(&last_updated) {
-                         // 15 mins cache for general assets
-                        if (Local::now() - dt.with_timezone(&Local)).num_minutes() < 15 {
-                             return Ok(());
-                        }
-                    }
-                }
+    ) -> Result<UpdateReport, Box<dyn std::error::Error + Send + Sync>> {
+        let started = Local::now();
+        let sources = match update_type {
+            "general" => self.update_general_assets(pool, force).await?,
+            "tefas" => self.update_tefas_funds(pool, force).await?,
+            "held" => self.refresh_held(pool, force).await?,
+            "all" => {
+                // Source failures land in their own report, so one can't hide the other
+                let mut sources = self.update_general_assets(pool, force).await?;
+                sources.extend(self.update_tefas_funds(pool, force).await?);
+                sources
+            }
+            _ => return Err(format!("Unknown update type: {}", update_type).into()),
+        };
+
+        Self::finish_report(pool, update_type, started, sources).await
+    }
+
+    /// Refreshes exactly these symbols, ignoring the cache. Every symbol must be known from
+    /// `assets` or `transactions` so its asset type (and therefore its providers) is known.
+    pub async fn refresh_symbols(
+        &self,
+        pool: &SqlitePool,
+        symbols: Vec<String>,
+    ) -> Result<UpdateReport, Box<dyn std::error::Error + Send + Sync>> {
+        let started = Local::now();
+        let known: HashMap<String, String> = sqlx::query_as::<_, (String, String)>(
+            "SELECT symbol, asset_type FROM transactions UNION SELECT symbol, asset_type FROM assets",
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|(symbol, asset_type)| (symbol.to_uppercase(), asset_type))
+        .collect();
+
+        let mut grouped: HashMap<String, Vec<String>> = HashMap::new();
+        let mut unknown = Vec::new();
+        let mut manual = Vec::new();
+        for symbol in symbols {
+            let symbol = symbol.trim().to_uppercase();
+            match known.get(&symbol) {
+                Some(asset_type) if asset_type == MANUAL_ASSET_TYPE => manual.push(symbol),
+                Some(asset_type) => grouped.entry(asset_type.clone()).or_default().push(symbol),
+                None => unknown.push(symbol),
             }
         }
+        if !unknown.is_empty() {
+            return Err(format!("Unknown symbols: {}", unknown.join(", ")).into());
+        }
+        if !manual.is_empty() {
+            return Err(format!("Manually priced symbols can't be refreshed: {}", manual.join(", ")).into());
+        }
 
-        let urls = vec![
-            ("https://canlidoviz.com/doviz-kurlari", "doviz"),
-            ("https://canlidoviz.com/altin-fiyatlari", "emtia"),
-            ("https://canlidoviz.com/borsa", "hisse"),
-            ("https://canlidoviz.com/kripto-paralar", "kripto"),
-        ];
+        let sources = self.refresh_grouped(pool, grouped, true).await?;
+        Self::finish_report(pool, "symbols", started, sources).await
+    }
 
-        // Fetch concurrently
-        let futures = urls.into_iter().map(|(url, asset_type)| {
-            let client = self.client.clone();
-            async move {
-                match client.get(url).send().await {
-                    Ok(resp) => match resp.text().await {
-                        Ok(text) => Some((text, asset_type)),
-                        Err(_) => None,
-                    },
-                    Err(_) => None,
+    async fn refresh_held(
+        &self,
+        pool: &SqlitePool,
+        force: bool,
+    ) -> Result<Vec<SourceReport>, Box<dyn std::error::Error + Send + Sync>> {
+        let held = Self::held_symbols(pool).await?;
+        self.refresh_grouped(pool, held, force).await
+    }
+
+    /// Quotes symbols grouped by asset type through per-symbol providers. Unless `force`,
+    /// symbols priced within their TTL are left alone.
+    async fn refresh_grouped(
+        &self,
+        pool: &SqlitePool,
+        grouped: HashMap<String, Vec<String>>,
+        force: bool,
+    ) -> Result<Vec<SourceReport>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut wanted: Vec<(String, Vec<String>)> = Vec::new();
+        let mut skipped: Vec<&'static str> = Vec::new();
+        for (asset_type, symbols) in grouped {
+            let symbols = if force { symbols } else { self.stale_symbols(pool, &asset_type, symbols).await? };
+            if symbols.is_empty() {
+                if let Some(provider) = self.registry.providers_for_symbols(&asset_type).first() {
+                    skipped.push(provider.name());
                 }
+                continue;
             }
-        });
+            wanted.push((asset_type, symbols));
+        }
+        if wanted.is_empty() {
+            return Ok(Self::skipped_reports(skipped));
+        }
 
-        let results = join_all(futures).await;
-        let mut all_assets = Vec::new();
-
-        for result in results.into_iter().flatten() {
-            let (res, asset_type) = result;
-
-            // Parsing Logic (same as before but optimized extraction)
-            let document = Html::parse_document(&res);
-            let row_selector = Selector::parse("tr.currency-list-row, tr.table-row-md").unwrap();
-            let name_selector =
-                Selector::parse("span[itemprop='name'], span.truncate.text-theme.text-base")
-                    .unwrap();
-            let price_selector = Selector::parse("span[dt='bA'], span[dt='amount']").unwrap();
-            let code_selector =
-                Selector::parse("span[itemprop='currency'], span.table-code, span.code").unwrap();
-            let perc_selector = Selector::parse("span[dt='change'], span[dt='perc'], span[dt='p'], span.table-perc, span.currency-change-text").unwrap();
-
-            for row in document.select(&row_selector) {
-                let name = row
-                    .select(&name_selector)
-                    .next()
-                    .map(|e| e.text().collect::<String>().trim().to_string());
-                let price_text = row
-                    .select(&price_selector)
-                    .next()
-                    .map(|e| e.text().collect::<String>());
-                let mut symbol = row
-                    .select(&code_selector)
-                    .next()
-                    .map(|e| e.text().collect::<String>().trim().to_string());
-                let perc_text = row
-                    .select(&perc_selector)
-                    .next()
-                    .map(|e| e.text().collect::<String>());
-
-                if let (Some(n), Some(p_t)) = (name, price_text) {
-                    if symbol.is_none() || symbol.as_ref().unwrap().is_empty() {
-                        // Try to generate symbol from name if missing
-                        let generated = n
-                            .chars()
-                            .filter(|c| c.is_alphanumeric())
-                            .take(5)
-                            .collect::<String>()
-                            .to_uppercase();
-                        symbol = Some(generated);
-                    }
+        let log = FetchLog::default();
+        let fetched: Vec<(&'static str, Vec<Quote>)> = join_all(
+            wanted
+                .iter()
+                .map(|(asset_type, symbols)| self.registry.fetch_symbols(asset_type, symbols, &log)),
+        )
+        .await
+        .into_iter()
+        .flatten()
+        .collect();
+        self.store_fetched(pool, fetched, log).await
+    }
 
-                    let mut sym = symbol.unwrap();
-                    if asset_type == "kripto" {
-                        sym = format!("{}-C", sym); // Only keeping -C for consistency if needed
-                    }
-                    // Fix: Remove redundant suffix if present
-                    if sym.ends_with("-C-C") {
-                        sym = sym.replace("-C-C", "-C");
-                    }
+    /// Drops symbols whose `assets.last_updated` is still within the primary provider's TTL, or
+    /// whose market hasn't produced a new price since.
+    async fn stale_symbols(
+        &self,
+        pool: &SqlitePool,
+        asset_type: &str,
+        symbols: Vec<String>,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let Some(ttl) = self.registry.ttl(asset_type) else { return Ok(symbols) };
+        let updated: HashMap<String, String> = sqlx::query_as::<_, (String, Option<String>)>(
+            "SELECT symbol, last_updated FROM assets WHERE asset_type = ?",
+        )
+        .bind(asset_type)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .filter_map(|(symbol, last)| last.map(|l| (symbol.to_uppercase(), l)))
+        .collect();
+
+        Ok(symbols
+            .into_iter()
+            .filter(|s| {
+                !updated
+                    .get(&s.to_uppercase())
+                    .is_some_and(|last| is_fresh(&[asset_type], last, ttl))
+            })
+            .collect())
+    }
+
+    async fn finish_report(
+        pool: &SqlitePool,
+        update_type: &str,
+        started: chrono::DateTime<Local>,
+        sources: Vec<SourceReport>,
+    ) -> Result<UpdateReport, Box<dyn std::error::Error + Send + Sync>> {
+        let report = UpdateReport {
+            update_type: update_type.to_string(),
+            started_at: started.to_rfc3339(),
+            duration_ms: (Local::now() - started).num_milliseconds(),
+            sources,
+        };
+        Self::save_report(pool, &report).await?;
+        Ok(report)
+    }
 
-                    // Optimized price parsing
-                    let clean_price = p_t
-                        .chars()
-                        .filter(|c| c.is_digit(10) || *c == ',' || *c == '.')
-                        .collect::<String>();
-                    let price = self.parse_tr_float(&clean_price).unwrap_or(0.0);
-
-                    let day_change = if let Some(pt) = perc_text {
-                        let clean_perc = pt
-                            .chars()
-                            .filter(|c| {
-                                c.is_digit(10) || *c == ',' || *c == '.' || *c == '-' || *c == '+'
-                            })
-                            .collect::<String>();
-                        self.parse_tr_float(&clean_perc).unwrap_or(0.0)
-                    } else {
-                        0.0
-                    };
-
-                    if price > 0.0 {
-                        all_assets.push((sym, n, asset_type.to_string(), price, day_change));
+    fn skipped_reports(sources: Vec<&'static str>) -> Vec<SourceReport> {
+        let mut reports: Vec<SourceReport> = Vec::new();
+        for source in sources {
+            if !reports.iter().any(|r| r.source == source) {
+                reports.push(SourceReport { source: source.to_string(), skipped_cached: true, ..Default::default() });
+            }
+        }
+        reports
+    }
+
+    pub async fn update_general_assets(
+        &self,
+        pool: &SqlitePool,
+        force: bool,
+    ) -> Result<Vec<SourceReport>, Box<dyn std::error::Error + Send + Sync>> {
+        self.update_types(pool, &GENERAL_ASSET_TYPES, force).await
+    }
+
+    pub async fn update_tefas_funds(
+        &self,
+        pool: &SqlitePool,
+        force: bool,
+    ) -> Result<Vec<SourceReport>, Box<dyn std::error::Error + Send + Sync>> {
+        self.update_types(pool, &["fon"], force).await
+    }
+
+    async fn update_types(
+        &self,
+        pool: &SqlitePool,
+        asset_types: &[&str],
+        force: bool,
+    ) -> Result<Vec<SourceReport>, Box<dyn std::error::Error + Send + Sync>> {
+        if !force && self.is_cached(pool, asset_types).await {
+            let primaries = asset_types
+                .iter()
+                .filter_map(|t| self.registry.providers_for(t).first().map(|p| p.name()))
+                .collect();
+            return Ok(Self::skipped_reports(primaries));
+        }
+
+        let log = FetchLog::default();
+        let fetched = self.fetch_types(pool, asset_types, &log).await?;
+        self.store_fetched(pool, fetched, log).await
+    }
+
+    /// Stores fetched quotes and turns the fetch log into one report per source that was asked.
+    async fn store_fetched(
+        &self,
+        pool: &SqlitePool,
+        fetched: Vec<(&'static str, Vec<Quote>)>,
+        log: FetchLog,
+    ) -> Result<Vec<SourceReport>, Box<dyn std::error::Error + Send + Sync>> {
+        let activity = log.into_sources();
+
+        let mut sources: Vec<&'static str> = activity.keys().copied().collect();
+        sources.sort();
+        let mut reports = Vec::new();
+        for source in sources {
+            let quotes: Vec<Quote> = fetched
+                .iter()
+                .filter(|(s, _)| *s == source)
+                .flat_map(|(_, quotes)| quotes.iter().cloned())
+                .collect();
+            let (rows_upserted, changed_symbols) = self.store_quotes(pool, source, &quotes).await?;
+            let activity = &activity[source];
+            reports.push(SourceReport {
+                source: source.to_string(),
+                requests: activity.requests.clone(),
+                rows_parsed: activity.rows_parsed,
+                rows_upserted,
+                changed_symbols,
+                skipped_cached: false,
+                errors: activity.errors.clone(),
+            });
+        }
+        Ok(reports)
+    }
+
+    async fn save_report(pool: &SqlitePool, report: &UpdateReport) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut tx = pool.begin().await?;
+        for source in &report.sources {
+            sqlx::query(
+                "INSERT INTO update_log (update_type, source, started_at, duration_ms, skipped_cached,
+                    rows_parsed, rows_upserted, changed_symbols, requests, errors)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&report.update_type)
+            .bind(&source.source)
+            .bind(&report.started_at)
+            .bind(report.duration_ms)
+            .bind(source.skipped_cached)
+            .bind(source.rows_parsed as i64)
+            .bind(source.rows_upserted as i64)
+            .bind(serde_json::to_string(&source.changed_symbols)?)
+            .bind(serde_json::to_string(&source.requests)?)
+            .bind(serde_json::to_string(&source.errors)?)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Most recent `update_log` rows first.
+    pub async fn get_update_log(pool: &SqlitePool, limit: i64) -> Result<Vec<UpdateLogEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        let rows = sqlx::query_as::<_, UpdateLogRow>(
+            "SELECT id, update_type, source, started_at, duration_ms, skipped_cached, rows_parsed, rows_upserted,
+                    changed_symbols, requests, errors
+             FROM update_log
+             ORDER BY id DESC
+             LIMIT ?",
+        )
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(UpdateLogEntry {
+                    id: row.id,
+                    update_type: row.update_type,
+                    started_at: row.started_at,
+                    duration_ms: row.duration_ms,
+                    report: SourceReport {
+                        source: row.source,
+                        requests: serde_json::from_str(&row.requests)?,
+                        rows_parsed: row.rows_parsed as usize,
+                        rows_upserted: row.rows_upserted as usize,
+                        changed_symbols: serde_json::from_str(&row.changed_symbols)?,
+                        skipped_cached: row.skipped_cached,
+                        errors: serde_json::from_str(&row.errors)?,
+                    },
+                })
+            })
+            .collect()
+    }
+
+    /// True when the newest price among `asset_types` is younger than the primary provider's TTL,
+    /// or none of their markets can have moved since it was fetched (weekends, holidays, after hours).
+    async fn is_cached(&self, pool: &SqlitePool, asset_types: &[&str]) -> bool {
+        let Some(ttl) = asset_types.iter().filter_map(|t| self.registry.ttl(t)).min() else {
+            return false;
+        };
+        let sql = format!(
+            "SELECT MAX(last_updated) FROM assets WHERE asset_type IN ({})",
+            vec!["?"; asset_types.len()].join(", ")
+        );
+        let mut query = sqlx::query_scalar::<_, Option<String>>(&sql);
+        for asset_type in asset_types {
+            query = query.bind(*asset_type);
+        }
+        match query.fetch_one(pool).await {
+            Ok(Some(last_updated)) => is_fresh(asset_types, &last_updated, ttl),
+            _ => false,
+        }
+    }
+
+    /// Lists every type concurrently through the registry. Held symbols missing from a listing are
+    /// quoted one by one from the next providers (BorsaPy); a type whose listing fails on all
+    /// providers falls back to quoting just its held symbols. Quotes come back grouped by source.
+    async fn fetch_types(
+        &self,
+        pool: &SqlitePool,
+        asset_types: &[&str],
+        log: &FetchLog,
+    ) -> Result<Vec<(&'static str, Vec<Quote>)>, Box<dyn std::error::Error + Send + Sync>> {
+        let held = Self::held_symbols(pool).await?;
+        let futures = asset_types.iter().map(|asset_type| {
+            let held = held.get(*asset_type);
+            async move {
+                let mut fetched = Vec::new();
+                match self.registry.fetch(asset_type, None, log).await {
+                    Ok((provider, quotes)) => {
+                        let missing: Vec<String> = held
+                            .into_iter()
+                            .flatten()
+                            .filter(|s| !quotes.iter().any(|q| q.symbol.eq_ignore_ascii_case(s)))
+                            .cloned()
+                            .collect();
+                        fetched.push((provider, quotes));
+                        if !missing.is_empty() {
+                            if let Ok(extra) = self.registry.fetch_after(asset_type, &missing, provider, log).await {
+                                fetched.push(extra);
+                            }
+                        }
+                    }
+                    Err(_) => {
+                        if let Some(symbols) = held {
+                            fetched.extend(self.registry.fetch_symbols(asset_type, symbols, log).await);
+                        }
                     }
                 }
+                fetched
+            }
+        });
+        Ok(join_all(futures).await.into_iter().flatten().collect())
+    }
+
+    /// Symbols with an open position, grouped by asset type.
+    async fn held_symbols(pool: &SqlitePool) -> Result<HashMap<String, Vec<String>>, sqlx::Error> {
+        let ledger = LotLedger::load(pool).await?;
+        let mut held: HashMap<String, Vec<String>> = HashMap::new();
+        for (symbol, position) in ledger.positions {
+            if position.quantity() > lots::QTY_EPSILON && position.asset_type != MANUAL_ASSET_TYPE {
+                held.entry(position.asset_type.clone()).or_default().push(symbol);
             }
         }
+        for symbols in held.values_mut() {
+            symbols.sort();
+        }
+        Ok(held)
+    }
 
-        if all_assets.is_empty() {
-            return Ok(());
+    /// Upserts the quotes from `source`; returns how many rows were written and which symbols changed price.
+    async fn store_quotes(
+        &self,
+        pool: &SqlitePool,
+        source: &str,
+        quotes: &[Quote],
+    ) -> Result<(usize, Vec<String>), Box<dyn std::error::Error + Send + Sync>> {
+        if quotes.is_empty() {
+            return Ok((0, Vec::new()));
         }
 
         // Batch Insert with Transaction
         let mut tx = pool.begin().await?;
         let last_updated = Local::now().to_rfc3339();
+        let now = market_calendar::istanbul_now();
+
+        let mut changed = Vec::new();
+        let mut upserted = 0;
+        for quote in quotes {
+            let existing = sqlx::query_as::<_, (Option<f64>, Option<String>)>("SELECT current_price, asset_type FROM assets WHERE symbol = ?")
+                .bind(&quote.symbol)
+                .fetch_optional(&mut *tx)
+                .await?;
+            let (previous, asset_type) = existing.unwrap_or((None, None));
+            // Manually valued assets keep the user's valuations even if a listing carries the same symbol
+            if asset_type.as_deref() == Some(MANUAL_ASSET_TYPE) {
+                continue;
+            }
+            if previous.is_none_or(|p| (p - quote.price).abs() > 1e-9) {
+                changed.push(quote.symbol.clone());
+            }
+
+            // Daily close: the last price seen for the day wins. Prices belong to their publication or
+            // session date, so a weekend fetch of a BIST stock lands on Friday rather than Saturday
+            let snapshot_date = quote
+                .price_date
+                .unwrap_or_else(|| market_calendar::price_date(&quote.asset_type, now))
+                .format("%Y-%m-%d")
+                .to_string();
+            sqlx::query(
+                "INSERT INTO asset_price_history (symbol, price, snapshot_date)
+                    VALUES (?, ?, ?)
+                    ON CONFLICT(symbol, snapshot_date) DO UPDATE SET price = excluded.price"
+            )
+            .bind(&quote.symbol)
+            .bind(quote.price)
+            .bind(&snapshot_date)
+            .execute(&mut *tx)
+            .await?;
 
-        for (symbol, name, a_type, price, day_change) in all_assets {
             sqlx::query(
-                "INSERT INTO assets (symbol, name, asset_type, current_price, day_change, last_updated)
-                    VALUES (?, ?, ?, ?, ?, ?)
+                "INSERT INTO assets (symbol, name, asset_type, current_price, day_change, last_updated, price_source)
+                    VALUES (?, ?, ?, ?, ?, ?, ?)
                     ON CONFLICT(symbol) DO UPDATE SET
                     current_price = excluded.current_price,
                     day_change = excluded.day_change,
-                    last_updated = excluded.last_updated"
+                    last_updated = excluded.last_updated,
+                    price_source = excluded.price_source"
             )
-            .bind(symbol)
-            .bind(name)
-            .bind(a_type)
-            .bind(price)
-            .bind(day_change)
+            .bind(&quote.symbol)
+            .bind(&quote.name)
+            .bind(&quote.asset_type)
+            .bind(quote.price)
+            .bind(quote.day_change)
             .bind(&last_updated)
+            .bind(source)
             .execute(&mut *tx)
             .await?;
+            upserted += 1;
         }
 
         tx.commit().await?;
 
-        Ok(())
+        Ok((upserted, changed))
     }
 
-    pub async fn update_tefas_funds(
+    /// Fills `asset_price_history` for held funds (or `symbols`) from their first transaction date.
+    /// Progress is saved after every chunk, so an interrupted run resumes where it stopped.
+    pub async fn backfill_tefas_history(
         &self,
         pool: &SqlitePool,
-        force: bool,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        if !force {
-            if let Ok(Some(last_str)) = sqlx::query_scalar::<_, Option<String>>(
-                "SELECT MAX(last_updated) FROM assets WHERE asset_type = 'fon'",
-            )
-            .fetch_optional(pool)
-            .await
-            {
-                if let Some(last_updated) = last_str {
-                    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(&last_updated) {
-                        // 4 hours cache for funds
-                        if (Local::now() - dt.with_timezone(&Local)).num_hours() < 4 {
-                            return Ok(());
-                        }
-                    }
-                }
+        symbols: Option<Vec<String>>,
+    ) -> Result<Vec<BackfillResult>, Box<dyn std::error::Error + Send + Sync>> {
+        // Nothing newer than the latest published TEFAS price exists yet, so chunks stop there
+        let latest = market_calendar::latest_tefas_date(market_calendar::istanbul_now());
+        let first_dates = Self::first_transaction_dates(pool).await?;
+        let symbols = match symbols {
+            Some(list) => list.into_iter().map(|s| s.trim().to_uppercase()).collect(),
+            None => {
+                let ledger = LotLedger::load(pool).await?;
+                let mut held: Vec<String> = ledger
+                    .positions
+                    .into_iter()
+                    .filter(|(_, p)| p.asset_type == "fon" && p.quantity() > 0.0)
+                    .map(|(s, _)| s)
+                    .collect();
+                held.sort();
+                held
             }
-        }
+        };
+        let actions = CorporateAction::load_all(pool).await?;
 
-        let base_url = "https://www.tefas.gov.tr/api/DB/BindHistoryInfo";
-        let fund_types = vec!["YAT", "EMK", "BYF", "GYF", "GSYF"];
+        let mut results = Vec::new();
+        for symbol in symbols {
+            let requested_start = first_dates
+                .get(&symbol)
+                .copied()
+                .unwrap_or_else(|| latest - chrono::Duration::days(365));
+            let (from, fund_type) = backfill_resume_point(pool, &symbol, requested_start).await?;
+            results.push(self.fill_range(pool, &symbol, requested_start, (from, latest), fund_type, &actions).await?);
+        }
 
-        // Find valid date concurrently? No, date dependency exists.
-        // We need to find the latest valid date first.
-        // But checking 5 days is fast if we do it right.
-        // Let's assume today or yesterday is valid and try to fetch in parallel.
+        Ok(results)
+    }
 
-        // Strategy: fetch all fund types for calculated date
-        // Try today, if empty try yesterday, etc.
+    /// Backfill for callers that wait on it (the fund detail page): only the closes after the
+    /// newest stored one, at most a year back, and the fund type is probed once up front so each
+    /// chunk costs a single request.
+    pub async fn backfill_recent_tefas_history(
+        &self,
+        pool: &SqlitePool,
+        code: &str,
+    ) -> Result<BackfillResult, Box<dyn std::error::Error + Send + Sync>> {
+        let symbol = code.trim().to_uppercase();
+        let latest = market_calendar::latest_tefas_date(market_calendar::istanbul_now());
+        let newest_stored: Option<String> = sqlx::query_scalar(
+            "SELECT MAX(snapshot_date) FROM asset_price_history WHERE symbol = ? AND price > 0",
+        )
+        .bind(&symbol)
+        .fetch_one(pool)
+        .await?;
+        let year_ago = latest - chrono::Duration::days(365);
+        let requested_start = newest_stored
+            .as_deref()
+            .and_then(lots::parse_date)
+            .map_or(year_ago, |d| (d + chrono::Duration::days(1)).max(year_ago));
+        let (from, fund_type) = backfill_resume_point(pool, &symbol, requested_start).await?;
+        let from = from.max(requested_start);
 
-        let mut valid_date_str = String::new();
-        let mut funds_data = Vec::new();
+        let fund_type = match fund_type {
+            Some(known) => Some(known),
+            // Nothing missing: don't spend requests finding the fund type
+            None if from > latest => None,
+            None => match self.probe_fund_type(&symbol, latest).await {
+                Ok(Some(found)) => Some(found),
+                outcome => {
+                    return Ok(BackfillResult {
+                        from_date: from.format("%Y-%m-%d").to_string(),
+                        to_date: latest.format("%Y-%m-%d").to_string(),
+                        chunks_fetched: 0,
+                        rows_stored: 0,
+                        completed: false,
+                        error: Some(outcome.err().unwrap_or_else(|| format!("TEFAS has no recent prices for {}", symbol))),
+                        symbol,
+                    })
+                }
+            },
+        };
+        let actions = CorporateAction::load_all(pool).await?;
+        self.fill_range(pool, &symbol, requested_start, (from, latest), fund_type, &actions).await
+    }
 
-        for i in 0..5 {
-            let target_dt = Local::now() - chrono::Duration::days(i);
-            // Skip weekends: Sat=6, Sun=7. format %u
-            if target_dt.format("%u").to_string().parse::<u8>().unwrap() > 5 {
-                continue;
+    /// Finds the fund type that publishes `symbol` from the last two weeks of TEFAS history.
+    /// `Err` only when no type answered and at least one request failed.
+    async fn probe_fund_type(&self, symbol: &str, latest: NaiveDate) -> Result<Option<String>, String> {
+        let mut fetch_error = None;
+        for f_type in TEFAS_FUND_TYPES {
+            tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+            match self.fetch_tefas_history(symbol, f_type, latest - chrono::Duration::days(14), latest).await {
+                Ok(json) if parse_tefas_history(&json).iter().any(|(s, _, _)| s == symbol) => {
+                    return Ok(Some(f_type.to_string()))
+                }
+                Ok(_) => {}
+                Err(e) => fetch_error = Some(e.to_string()),
             }
+        }
+        fetch_error.map_or(Ok(None), Err)
+    }
+
+    /// Fetches and stores `range` chunk by chunk, stopping at the first chunk that can't be fetched.
+    async fn fill_range(
+        &self,
+        pool: &SqlitePool,
+        symbol: &str,
+        requested_start: NaiveDate,
+        (from, latest): (NaiveDate, NaiveDate),
+        mut fund_type: Option<String>,
+        actions: &[CorporateAction],
+    ) -> Result<BackfillResult, Box<dyn std::error::Error + Send + Sync>> {
+        let mut result = BackfillResult {
+            symbol: symbol.to_string(),
+            from_date: from.format("%Y-%m-%d").to_string(),
+            to_date: latest.format("%Y-%m-%d").to_string(),
+            chunks_fetched: 0,
+            rows_stored: 0,
+            completed: false,
+            error: None,
+        };
 
-            let date_str = target_dt.format("%d.%m.%Y").to_string();
-
-            // Check just one type to verify date validity quickly
-            let params = [
-                ("fontip", "YAT"),
-                ("bastarih", &date_str),
-                ("bittarih", &date_str),
-            ];
-            if let Ok(res) = self.client.post(base_url).form(&params).send().await {
-                if let Ok(json) = res.json::<serde_json::Value>().await {
-                    if let Some(data) = json["data"].as_array() {
-                        if !data.is_empty() {
-                            valid_date_str = date_str;
-                            // We found data for YAT, add it
-                            funds_data.push(json);
+        for (chunk_start, chunk_end) in backfill_chunks(from, latest, TEFAS_HISTORY_CHUNK_DAYS) {
+            // Until a chunk returns rows the fund type is unknown, so every type is tried
+            let candidates: Vec<String> = match &fund_type {
+                Some(known) => vec![known.clone()],
+                None => TEFAS_FUND_TYPES.iter().map(|t| t.to_string()).collect(),
+            };
+
+            let mut rows = Vec::new();
+            let mut fetch_error = None;
+            for f_type in candidates {
+                // Be polite to TEFAS; it throttles rapid consecutive history requests
+                tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+                match self.fetch_tefas_history(symbol, &f_type, chunk_start, chunk_end).await {
+                    Ok(json) => {
+                        rows = parse_tefas_history(&json)
+                            .into_iter()
+                            .filter(|(s, _, _)| s == symbol)
+                            .collect();
+                        if !rows.is_empty() {
+                            fund_type = Some(f_type);
                             break;
                         }
                     }
+                    // One failing type doesn't rule out the others
+                    Err(e) => fetch_error = Some(e.to_string()),
+                }
+            }
+            // An empty chunk only counts as done when every type actually answered
+            if rows.is_empty() {
+                if let Some(e) = fetch_error {
+                    result.error = Some(e);
+                    break;
                 }
             }
-        }
 
-        if valid_date_str.is_empty() {
-            return Ok(());
+            result.rows_stored +=
+                store_history_chunk(pool, symbol, &rows, requested_start, chunk_end, fund_type.as_deref(), actions).await?;
+            result.chunks_fetched += 1;
         }
 
-        // Now fetch other types concurrently for this valid date
-        let remaining_types = fund_types
-            .into_iter()
-            .filter(|&t| t != "YAT")
-            .collect::<Vec<_>>();
+        result.completed = result.error.is_none();
+        Ok(result)
+    }
 
-        let futures = remaining_types.into_iter().map(|f_type| {
-            let client = self.client.clone();
-            let d_str = valid_date_str.clone();
-            async move {
-                let params = [
-                    ("fontip", f_type),
-                    ("bastarih", &d_str),
-                    ("bittarih", &d_str),
-                ];
-                match client
-                    .post("https://www.tefas.gov.tr/api/DB/BindHistoryInfo")
-                    .form(&params)
-                    .send()
-                    .await
-                {
-                    Ok(res) => res.json::<serde_json::Value>().await.ok(),
-                    Err(_) => None,
-                }
-            }
-        });
+    async fn fetch_tefas_history(
+        &self,
+        code: &str,
+        fund_type: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<serde_json::Value, reqwest::Error> {
+        let start = start.format("%d.%m.%Y").to_string();
+        let end = end.format("%d.%m.%Y").to_string();
+        let params = [
+            ("fontip", fund_type),
+            ("bastarih", &start),
+            ("bittarih", &end),
+            ("fonkod", code),
+        ];
+        self.client
+            .post(TEFAS_HISTORY_URL)
+            .form(&params)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<serde_json::Value>()
+            .await
+    }
 
-        let results = join_all(futures).await;
-        for res in results.into_iter().flatten() {
-            funds_data.push(res);
+    async fn first_transaction_dates(pool: &SqlitePool) -> Result<HashMap<String, NaiveDate>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, (String, String)>("SELECT symbol, transaction_date FROM transactions")
+            .fetch_all(pool)
+            .await?;
+        let mut first: HashMap<String, NaiveDate> = HashMap::new();
+        for (symbol, date) in rows {
+            let Some(date) = lots::parse_date(&date) else { continue };
+            first
+                .entry(symbol.to_uppercase())
+                .and_modify(|d| *d = (*d).min(date))
+                .or_insert(date);
         }
+        Ok(first)
+    }
+}
+
+/// A stored price is fresh while it's younger than `ttl` or no market among `asset_types` has
+/// produced a newer price since it was fetched.
+fn is_fresh(asset_types: &[&str], last_updated: &str, ttl: chrono::Duration) -> bool {
+    let Ok(fetched) = chrono::DateTime::parse_from_rfc3339(last_updated) else { return false };
+    let now = market_calendar::istanbul_now();
+    now - fetched < ttl || !asset_types.iter().any(|t| market_calendar::has_new_prices_since(t, fetched, now))
+}
 
-        // Process all funds
-        let mut all_funds = Vec::new();
+/// Extracts (fund code, price date, price) rows from a `BindHistoryInfo` response.
+/// `TARIH` is epoch milliseconds at Istanbul midnight, sent as a string or a number.
+pub fn parse_tefas_history(json: &serde_json::Value) -> Vec<(String, NaiveDate, f64)> {
+    let istanbul = chrono::FixedOffset::east_opt(3 * 3600).unwrap();
+    let Some(data) = json["data"].as_array() else { return Vec::new() };
 
-        for json in funds_data {
-            if let Some(funds) = json["data"].as_array() {
-                for fund in funds {
-                    let symbol = fund["FONKODU"].as_str().unwrap_or("").to_uppercase();
-                    let name = fund["FONUNVAN"].as_str().unwrap_or("").to_string();
-                    let price_raw = fund["FIYAT"]
-                        .as_f64()
-                        .or_else(|| fund["SONFIYAT"].as_f64())
-                        .or_else(|| fund["BORSABULTENFIYAT"].as_f64());
+    data.iter()
+        .filter_map(|row| {
+            let symbol = row["FONKODU"].as_str()?.trim().to_uppercase();
+            let millis = row["TARIH"]
+                .as_i64()
+                .or_else(|| row["TARIH"].as_str().and_then(|s| s.trim().parse().ok()))?;
+            let date = istanbul.timestamp_millis_opt(millis).single()?.date_naive();
+            let price = row["FIYAT"]
+                .as_f64()
+                .or_else(|| row["FIYAT"].as_str().and_then(|s| s.replace(',', ".").parse().ok()))?;
+            (price > 0.0).then_some((symbol, date, price))
+        })
+        .collect()
+}
 
-                    let day_change = fund["GUNLUKGETIRI"].as_f64().unwrap_or(0.0);
+/// Splits `[start, end]` into consecutive inclusive ranges of at most `days` days.
+pub fn backfill_chunks(start: NaiveDate, end: NaiveDate, days: i64) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut chunks = Vec::new();
+    let mut chunk_start = start;
+    while chunk_start <= end {
+        let chunk_end = (chunk_start + chrono::Duration::days(days - 1)).min(end);
+        chunks.push((chunk_start, chunk_end));
+        chunk_start = chunk_end + chrono::Duration::days(1);
+    }
+    chunks
+}
 
-                    if let Some(price) = price_raw {
-                        if price > 0.0 {
-                            all_funds.push((symbol, name, price, day_change));
-                        }
-                    }
-                }
-            }
+/// Where a backfill should continue: the day after the last completed chunk, unless the
+/// requested start moved earlier (e.g. an older transaction was added), and the known fund type.
+async fn backfill_resume_point(
+    pool: &SqlitePool,
+    symbol: &str,
+    requested_start: NaiveDate,
+) -> Result<(NaiveDate, Option<String>), sqlx::Error> {
+    let progress = sqlx::query_as::<_, (String, Option<String>, Option<String>)>(
+        "SELECT start_date, last_completed_date, fund_type FROM price_backfill_progress WHERE symbol = ? AND source = 'tefas'",
+    )
+    .bind(symbol)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some((start_date, last_completed, fund_type)) = progress else {
+        return Ok((requested_start, None));
+    };
+    let stored_start = lots::parse_date(&start_date).unwrap_or(requested_start);
+    match last_completed.as_deref().and_then(lots::parse_date) {
+        Some(last) if stored_start <= requested_start => Ok((last + chrono::Duration::days(1), fund_type)),
+        _ => Ok((requested_start, fund_type)),
+    }
+}
+
+/// Upserts one chunk of closes and advances the resume point in the same transaction.
+async fn store_history_chunk(
+    pool: &SqlitePool,
+    symbol: &str,
+    rows: &[(String, NaiveDate, f64)],
+    requested_start: NaiveDate,
+    chunk_end: NaiveDate,
+    fund_type: Option<&str>,
+    actions: &[CorporateAction],
+) -> Result<usize, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for (_, date, price) in rows {
+        let date = date.format("%Y-%m-%d").to_string();
+        // Raw TEFAS prices are pre-action; restate them like the rest of the stored history
+        let factor = corporate_actions::adjustment_factor(actions, symbol, &date);
+        sqlx::query(
+            "INSERT INTO asset_price_history (symbol, price, snapshot_date)
+                VALUES (?, ?, ?)
+                ON CONFLICT(symbol, snapshot_date) DO UPDATE SET price = excluded.price",
+        )
+        .bind(symbol)
+        .bind(price / factor)
+        .bind(&date)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    sqlx::query(
+        "INSERT INTO price_backfill_progress (symbol, source, start_date, last_completed_date, fund_type, updated_at)
+            VALUES (?, 'tefas', ?, ?, ?, CURRENT_TIMESTAMP)
+            ON CONFLICT(symbol, source) DO UPDATE SET
+            start_date = MIN(price_backfill_progress.start_date, excluded.start_date),
+            last_completed_date = excluded.last_completed_date,
+            fund_type = COALESCE(excluded.fund_type, price_backfill_progress.fund_type),
+            updated_at = CURRENT_TIMESTAMP",
+    )
+    .bind(symbol)
+    .bind(requested_start.format("%Y-%m-%d").to_string())
+    .bind(chunk_end.format("%Y-%m-%d").to_string())
+    .bind(fund_type)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    async fn memory_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::create_schema(&pool).await.unwrap();
+        pool
+    }
+
+    fn fixture(name: &str) -> serde_json::Value {
+        let raw = match name {
+            "aak" => include_str!("../tests/fixtures/tefas_history_aak.json"),
+            _ => include_str!("../tests/fixtures/tefas_history_empty.json"),
+        };
+        serde_json::from_str(raw).unwrap()
+    }
+
+    #[test]
+    fn parses_recorded_history_response() {
+        let rows = parse_tefas_history(&fixture("aak"));
+        assert_eq!(rows.len(), 5);
+        assert!(rows.iter().all(|(s, _, _)| s == "AAK"));
+
+        let first = rows.iter().min_by_key(|(_, d, _)| *d).unwrap();
+        assert_eq!(first.1, date("2024-01-02"));
+        assert!((first.2 - 21.254310).abs() < 1e-9);
+        assert_eq!(rows.iter().max_by_key(|(_, d, _)| *d).unwrap().1, date("2024-01-08"));
+
+        assert!(parse_tefas_history(&fixture("empty")).is_empty());
+    }
+
+    #[test]
+    fn splits_range_into_chunks() {
+        let chunks = backfill_chunks(date("2024-01-01"), date("2024-03-15"), 60);
+        assert_eq!(
+            chunks,
+            vec![
+                (date("2024-01-01"), date("2024-02-29")),
+                (date("2024-03-01"), date("2024-03-15")),
+            ]
+        );
+        assert!(backfill_chunks(date("2024-02-01"), date("2024-01-01"), 60).is_empty());
+    }
+
+    #[tokio::test]
+    async fn stores_chunks_and_resumes_after_last_one() {
+        let pool = memory_pool().await;
+        let start = date("2024-01-01");
+
+        let (from, fund_type) = backfill_resume_point(&pool, "AAK", start).await.unwrap();
+        assert_eq!((from, fund_type), (start, None));
+
+        let rows = parse_tefas_history(&fixture("aak"));
+        let stored = store_history_chunk(&pool, "AAK", &rows, start, date("2024-01-10"), Some("YAT"), &[])
+            .await
+            .unwrap();
+        assert_eq!(stored, 5);
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM asset_price_history WHERE symbol = 'AAK'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 5);
+
+        let (from, fund_type) = backfill_resume_point(&pool, "AAK", start).await.unwrap();
+        assert_eq!(from, date("2024-01-11"));
+        assert_eq!(fund_type.as_deref(), Some("YAT"));
+
+        // An empty chunk (fund not trading yet, holiday week) still advances the resume point
+        store_history_chunk(&pool, "AAK", &parse_tefas_history(&fixture("empty")), start, date("2024-03-10"), None, &[])
+            .await
+            .unwrap();
+        let (from, fund_type) = backfill_resume_point(&pool, "AAK", start).await.unwrap();
+        assert_eq!(from, date("2024-03-11"));
+        assert_eq!(fund_type.as_deref(), Some("YAT"));
+
+        // An earlier first transaction restarts from the new start date
+        let (from, _) = backfill_resume_point(&pool, "AAK", date("2023-06-01")).await.unwrap();
+        assert_eq!(from, date("2023-06-01"));
+    }
+
+    #[tokio::test]
+    async fn restates_backfilled_prices_for_later_splits() {
+        let pool = memory_pool().await;
+        let split = CorporateAction {
+            id: 1,
+            symbol: "AAK".to_string(),
+            action_type: CorporateAction::SPLIT.to_string(),
+            ex_date: "2024-01-05".to_string(),
+            ratio: 2.0,
+            subscription_price: None,
+            notes: None,
+        };
+        let rows = vec![
+            ("AAK".to_string(), date("2024-01-04"), 20.0),
+            ("AAK".to_string(), date("2024-01-05"), 10.5),
+        ];
+        store_history_chunk(&pool, "AAK", &rows, date("2024-01-01"), date("2024-01-05"), None, &[split])
+            .await
+            .unwrap();
+
+        let prices: Vec<f64> = sqlx::query_scalar("SELECT price FROM asset_price_history ORDER BY snapshot_date")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(prices, vec![10.0, 10.5]);
+    }
+
+    #[tokio::test]
+    async fn recent_backfill_skips_tefas_when_history_is_current() {
+        let pool = memory_pool().await;
+        let latest = market_calendar::latest_tefas_date(market_calendar::istanbul_now());
+        sqlx::query("INSERT INTO asset_price_history (symbol, price, snapshot_date) VALUES ('AAK', 21.4, ?)")
+            .bind(latest.format("%Y-%m-%d").to_string())
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        // No stored fund type and nothing missing: finishes without probing or fetching
+        let result = ScraperService::new().backfill_recent_tefas_history(&pool, " aak").await.unwrap();
+        assert_eq!(result.symbol, "AAK");
+        assert!(result.completed);
+        assert_eq!((result.chunks_fetched, result.rows_stored, result.error), (0, 0, None));
+    }
+
+    #[tokio::test]
+    async fn update_log_round_trips_each_source() {
+        let pool = memory_pool().await;
+        let fetched = SourceReport {
+            source: "canlidoviz".to_string(),
+            requests: vec![
+                HttpCall { url: "https://canlidoviz.com/borsa".to_string(), status: Some(200), error: None },
+                HttpCall { url: "https://canlidoviz.com/kripto-paralar".to_string(), status: None, error: Some("timeout".to_string()) },
+            ],
+            rows_parsed: 12,
+            rows_upserted: 10,
+            changed_symbols: vec!["THYAO".to_string(), "ÇEYRE".to_string()],
+            skipped_cached: false,
+            errors: vec!["kripto: timeout".to_string()],
+        };
+        let skipped = SourceReport { source: "tefas".to_string(), skipped_cached: true, ..Default::default() };
+        let first = UpdateReport {
+            update_type: "all".to_string(),
+            started_at: "2024-06-12T10:00:00+03:00".to_string(),
+            duration_ms: 1500,
+            sources: vec![fetched.clone(), skipped.clone()],
+        };
+        ScraperService::save_report(&pool, &first).await.unwrap();
+        let second = ScraperService::finish_report(&pool, "held", Local::now(), vec![skipped]).await.unwrap();
+
+        // Newest first, one row per source, every field as saved
+        let log = ScraperService::get_update_log(&pool, 10).await.unwrap();
+        let rows: Vec<_> = log.iter().map(|e| (e.update_type.as_str(), e.report.source.as_str())).collect();
+        assert_eq!(rows, vec![("held", "tefas"), ("all", "tefas"), ("all", "canlidoviz")]);
+        assert_eq!(log[0].started_at, second.started_at);
+        assert!(log[0].report.skipped_cached);
+
+        let entry = &log[2];
+        assert_eq!((entry.started_at.as_str(), entry.duration_ms), ("2024-06-12T10:00:00+03:00", 1500));
+        let report = &entry.report;
+        assert_eq!((report.rows_parsed, report.rows_upserted, report.skipped_cached), (12, 10, false));
+        assert_eq!(report.changed_symbols, fetched.changed_symbols);
+        assert_eq!(report.errors, fetched.errors);
+        let requests: Vec<_> = report.requests.iter().map(|r| (r.url.as_str(), r.status, r.error.as_deref())).collect();
+        assert_eq!(
+            requests,
+            vec![("https://canlidoviz.com/borsa", Some(200), None), ("https://canlidoviz.com/kripto-paralar", None, Some("timeout"))]
+        );
+
+        assert_eq!(ScraperService::get_update_log(&pool, 1).await.unwrap().len(), 1);
+        assert!(log.windows(2).all(|w| w[0].id > w[1].id));
+    }
+
+    /// Quotes every requested symbol at a fixed price and records what it was asked for.
+    struct FixedPriceProvider {
+        price: f64,
+        calls: std::sync::Mutex<Vec<Vec<String>>>,
+    }
+
+    impl crate::providers::PriceProvider for FixedPriceProvider {
+        fn name(&self) -> &'static str {
+            "fixed"
         }
 
-        if all_funds.is_empty() {
-            return Ok(());
+        fn supported_types(&self) -> &'static [&'static str] {
+            &["hisse"]
         }
 
-        // Bulk Insert with Transaction
-        let mut tx = pool.begin().await?;
-        let last_updated = Local::now().to_rfc3339();
+        fn ttl(&self, _asset_type: &str) -> chrono::Duration {
+            chrono::Duration::minutes(15)
+        }
 
-        for (symbol, name, price, day_change) in all_funds {
-            sqlx::query(
-                "INSERT INTO assets (symbol, name, asset_type, current_price, day_change, last_updated)
-                    VALUES (?, ?, ?, ?, ?, ?)
-                    ON CONFLICT(symbol) DO UPDATE SET
-                    current_price = excluded.current_price,
-                    day_change = excluded.day_change,
-                    last_updated = excluded.last_updated"
-            )
-            .bind(symbol)
-            .bind(name)
-            .bind("fon")
-            .bind(price)
-            .bind(day_change)
-            .bind(&last_updated)
-            .execute(&mut *tx)
-            .await?;
+        fn supports_symbol_lookup(&self) -> bool {
+            true
         }
 
-        tx.commit().await?;
+        fn fetch<'a>(
+            &'a self,
+            asset_type: &'a str,
+            symbols: Option<&'a [String]>,
+            _log: &'a FetchLog,
+        ) -> futures::future::BoxFuture<'a, Result<Vec<Quote>, crate::providers::ProviderError>> {
+            let symbols = symbols.unwrap_or_default().to_vec();
+            self.calls.lock().unwrap().push(symbols.clone());
+            let quotes = symbols
+                .into_iter()
+                .map(|symbol| Quote {
+                    name: symbol.clone(),
+                    symbol,
+                    asset_type: asset_type.to_string(),
+                    price: self.price,
+                    day_change: 0.0,
+                    price_date: None,
+                })
+                .collect();
+            Box::pin(async move { Ok(quotes) })
+        }
+    }
 
-        Ok(())
+    fn fixed_price_service(price: f64) -> (ScraperService, std::sync::Arc<FixedPriceProvider>) {
+        let provider = std::sync::Arc::new(FixedPriceProvider { price, calls: Default::default() });
+        let mut registry = ProviderRegistry::new();
+        registry.register(provider.clone());
+        (ScraperService { client: reqwest::Client::new(), registry }, provider)
     }
 
-    fn parse_tr_float(&self, val: &str) -> Option<f64> {
-        let clean = val.trim();
-        if clean.contains(',') && clean.contains('.') {
-            if clean.rfind(',') > clean.rfind('.') {
-                clean.replace('.', "").replace(',', ".").parse().ok()
-            } else {
-                clean.replace(',', "").parse().ok()
-            }
-        } else if clean.contains(',') {
-            clean.replace(',', ".").parse().ok()
-        } else {
-            clean.parse().ok()
+    // THYAO was just priced, GARAN years ago, ASELS never stored
+    async fn priced_pool() -> SqlitePool {
+        let pool = memory_pool().await;
+        for (symbol, price, last_updated) in [
+            ("THYAO", 300.0, Local::now().to_rfc3339()),
+            ("GARAN", 100.0, "2020-01-02T10:00:00+03:00".to_string()),
+        ] {
+            sqlx::query("INSERT INTO assets (symbol, name, asset_type, current_price, last_updated) VALUES (?, ?, 'hisse', ?, ?)")
+                .bind(symbol)
+                .bind(symbol)
+                .bind(price)
+                .bind(last_updated)
+                .execute(&pool)
+                .await
+                .unwrap();
         }
+        pool
+    }
+
+    fn symbols(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn stale_symbols_drops_fresh_prices() {
+        let pool = priced_pool().await;
+        let (service, _) = fixed_price_service(1.0);
+
+        let stale = service.stale_symbols(&pool, "hisse", symbols(&["thyao", "garan", "ASELS"])).await.unwrap();
+        assert_eq!(stale, symbols(&["garan", "ASELS"]));
+        // Without a provider there is no TTL to go by, so everything is refreshed
+        let stale = service.stale_symbols(&pool, "fon", symbols(&["AAK"])).await.unwrap();
+        assert_eq!(stale, symbols(&["AAK"]));
+    }
+
+    #[tokio::test]
+    async fn refresh_grouped_quotes_only_what_is_stale_unless_forced() {
+        let pool = priced_pool().await;
+        let (service, provider) = fixed_price_service(120.0);
+
+        let grouped = HashMap::from([("hisse".to_string(), symbols(&["THYAO", "GARAN"]))]);
+        let reports = service.refresh_grouped(&pool, grouped.clone(), false).await.unwrap();
+        assert_eq!(provider.calls.lock().unwrap().clone(), vec![symbols(&["GARAN"])]);
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!((report.source.as_str(), report.rows_parsed, report.rows_upserted), ("fixed", 1, 1));
+        assert_eq!(report.changed_symbols, symbols(&["GARAN"]));
+        let price: f64 = sqlx::query_scalar("SELECT current_price FROM assets WHERE symbol = 'GARAN'").fetch_one(&pool).await.unwrap();
+        assert_eq!(price, 120.0);
+
+        // Both are fresh now: nothing is fetched and the primary provider reports a cache skip
+        let reports = service.refresh_grouped(&pool, grouped.clone(), false).await.unwrap();
+        assert_eq!(provider.calls.lock().unwrap().len(), 1);
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].skipped_cached);
+        assert_eq!(reports[0].source, "fixed");
+
+        let reports = service.refresh_grouped(&pool, grouped, true).await.unwrap();
+        assert_eq!(provider.calls.lock().unwrap().last(), Some(&symbols(&["THYAO", "GARAN"])));
+        assert_eq!(reports[0].changed_symbols, symbols(&["THYAO"]));
     }
 }
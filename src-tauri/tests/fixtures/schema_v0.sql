@@ -0,0 +1,75 @@
+-- Database as created by the app before schema versioning (no schema_version table)
+CREATE TABLE transactions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    transaction_date DATE NOT NULL,
+    asset_type TEXT NOT NULL,
+    symbol TEXT NOT NULL,
+    transaction_type TEXT NOT NULL,
+    quantity REAL NOT NULL,
+    price REAL NOT NULL,
+    total_value REAL,
+    fees REAL DEFAULT 0,
+    currency TEXT DEFAULT 'TRY',
+    broker TEXT,
+    notes TEXT,
+    is_dividend BOOLEAN DEFAULT 0
+);
+
+CREATE TABLE assets (
+    symbol TEXT PRIMARY KEY,
+    name TEXT,
+    asset_type TEXT,
+    current_price REAL,
+    day_change REAL DEFAULT 0,
+    last_updated TIMESTAMP,
+    market TEXT,
+    sector TEXT
+);
+
+CREATE TABLE portfolio_snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    snapshot_date DATE UNIQUE NOT NULL,
+    total_value_tl REAL NOT NULL,
+    total_value_usd REAL NOT NULL,
+    total_cost_basis REAL DEFAULT 0,
+    realized_pnl REAL DEFAULT 0,
+    unrealized_pnl REAL DEFAULT 0,
+    cash_balance REAL DEFAULT 0,
+    total_return_pct REAL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE asset_price_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    symbol TEXT,
+    price REAL,
+    snapshot_date DATE,
+    UNIQUE(symbol, snapshot_date)
+);
+
+CREATE TABLE tefas_daily_tracking (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    symbol TEXT,
+    price REAL,
+    day_change REAL,
+    snapshot_date DATE,
+    UNIQUE(symbol, snapshot_date)
+);
+
+INSERT INTO transactions (transaction_date, asset_type, symbol, transaction_type, quantity, price, total_value, fees, is_dividend)
+VALUES
+    ('2024-01-15', 'hisse', 'THYAO', 'BUY', 100, 250.5, 25050, 12.5, 0),
+    ('2024-03-01', 'fon', 'AAK', 'BUY', 1000, 1.25, 1250, 0, 0),
+    ('2024-06-10', 'hisse', 'THYAO', 'SELL', 40, 310, 12400, 6.2, 0);
+
+INSERT INTO assets (symbol, name, asset_type, current_price, day_change, last_updated)
+VALUES
+    ('THYAO', 'Türk Hava Yolları', 'hisse', 298.75, -1.2, '2024-06-12 15:30:00'),
+    ('AAK', 'Ata Portföy Çoklu Varlık', 'fon', 1.41, 0.08, '2024-06-12 11:00:00');
+
+INSERT INTO portfolio_snapshots (snapshot_date, total_value_tl, total_value_usd)
+VALUES ('2024-06-12', 19335, 600.4);
+
+INSERT INTO asset_price_history (symbol, price, snapshot_date)
+VALUES ('AAK', 1.40, '2024-06-11'), ('AAK', 1.41, '2024-06-12');
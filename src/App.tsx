@@ -59,12 +59,12 @@ function App() {
     initializeData();
   }, [fetchData]);
 
-  // Auto-refresh every 15 minutes
+  // Auto-refresh every 15 minutes (only held symbols; full listings load on startup)
   useEffect(() => {
     const interval = setInterval(async () => {
       console.log('[APP] Auto-refreshing market data...');
       try {
-        await invoke('update_market_data', { updateType: 'all', force: false });
+        await invoke('update_market_data', { updateType: 'held', force: false });
         await fetchData();
       } catch (err) {
         console.error('[APP] Auto-refresh failed:', err);
@@ -33,6 +33,7 @@ const AddTransactionModal = memo<AddTransactionModalProps>(({ onClose, onSave, i
         transaction_type: initialType || 'buy',
         quantity: 0,
         price: 0,
+        fees: 0,
         notes: '',
     });
 
@@ -96,6 +97,7 @@ const AddTransactionModal = memo<AddTransactionModalProps>(({ onClose, onSave, i
                     transaction_type: formData.transaction_type,
                     quantity: formData.quantity,
                     price: formData.price,
+                    fees: formData.fees,
                     notes: formData.notes || null,
                 }
             });
@@ -186,6 +188,7 @@ const AddTransactionModal = memo<AddTransactionModalProps>(({ onClose, onSave, i
                             <option value="altin">Altın / Kıymetli Maden</option>
                             <option value="doviz">Döviz</option>
                             <option value="kripto">Kripto Para</option>
+                            <option value="manuel">Manuel Değerlenen (Gayrimenkul, Girişim...)</option>
                             <option value="diger">Diğer</option>
                         </select>
                     </div>
@@ -276,6 +279,17 @@ const AddTransactionModal = memo<AddTransactionModalProps>(({ onClose, onSave, i
                             </div>
                         </div>
 
+                        <div className="space-y-2">
+                            <label className="text-sm font-medium text-[var(--color-text-secondary)]">Komisyon</label>
+                            <Input
+                                type="number"
+                                min="0"
+                                step="0.01"
+                                value={formData.fees}
+                                onChange={(e) => setFormData(prev => ({ ...prev, fees: parseFloat(e.target.value) || 0 }))}
+                            />
+                        </div>
+
                         <div className="p-4 bg-[var(--color-bg-tertiary)] rounded-xl flex justify-between items-center">
                             <span className="text-sm font-medium text-[var(--color-text-secondary)]">Toplam Tutar</span>
                             <span className="text-lg font-bold text-[var(--color-text-primary)]">
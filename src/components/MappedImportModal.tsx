@@ -0,0 +1,385 @@
+import { memo, useState, useEffect } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { FolderOpen, Save, Trash2, Upload } from 'lucide-react';
+import Modal from './ui/Modal';
+import Button from './ui/Button';
+import type { ColumnMap, CsvOptions, ImportProfile, SpreadsheetPreview, TransactionSide } from '../store/useStore';
+
+interface MappedImportModalProps {
+    isOpen: boolean;
+    onClose: () => void;
+    csvOptions: CsvOptions;
+    // Runs the dry run / confirm / import flow; resolves once the user is done with the file
+    onImport: (path: string, profile: ImportProfile) => Promise<void>;
+}
+
+const FIELDS: { key: keyof ColumnMap; label: string; required: boolean }[] = [
+    { key: 'date', label: 'Tarih', required: true },
+    { key: 'symbol', label: 'Sembol / Kod', required: true },
+    { key: 'side', label: 'İşlem Yönü', required: true },
+    { key: 'quantity', label: 'Adet', required: true },
+    { key: 'price', label: 'Fiyat', required: true },
+    { key: 'fees', label: 'Komisyon', required: false },
+    { key: 'currency', label: 'Para Birimi', required: false },
+    { key: 'broker', label: 'Aracı Kurum', required: false },
+    { key: 'notes', label: 'Not', required: false },
+];
+
+const SIDES: { value: TransactionSide; label: string }[] = [
+    { value: 'buy', label: 'Alış' },
+    { value: 'sell', label: 'Satış' },
+    { value: 'dividend', label: 'Temettü' },
+];
+
+const WORKBOOK_EXTENSIONS = ['xlsx', 'xlsm', 'xls', 'ods'];
+
+const selectClass =
+    'w-full px-2 py-1.5 rounded-xl border border-[var(--color-border)] bg-[var(--color-bg-primary)] text-xs text-[var(--color-text-primary)]';
+
+const newProfile = (csv: CsvOptions): ImportProfile => ({
+    name: '',
+    columns: {
+        date: '',
+        symbol: '',
+        side: '',
+        quantity: '',
+        price: '',
+        fees: null,
+        currency: null,
+        broker: null,
+        notes: null,
+    },
+    side_values: {},
+    asset_type: null,
+    csv,
+    sheet: null,
+    last_used_at: null,
+});
+
+const isWorkbook = (path: string) => WORKBOOK_EXTENSIONS.includes(path.split('.').pop()?.toLowerCase() ?? '');
+
+const MappedImportModal = memo<MappedImportModalProps>(({ isOpen, onClose, csvOptions, onImport }) => {
+    const [profiles, setProfiles] = useState<ImportProfile[]>([]);
+    const [profile, setProfile] = useState<ImportProfile>(() => newProfile(csvOptions));
+    const [path, setPath] = useState<string | null>(null);
+    const [preview, setPreview] = useState<SpreadsheetPreview | null>(null);
+    const [newLabel, setNewLabel] = useState('');
+    const [busy, setBusy] = useState(false);
+
+    const loadProfiles = async () => {
+        try {
+            const list = await invoke<ImportProfile[]>('get_import_profiles');
+            setProfiles(list);
+            return list;
+        } catch (error) {
+            console.error('Failed to load import profiles:', error);
+            return [];
+        }
+    };
+
+    // Start from the most recently used profile
+    useEffect(() => {
+        if (!isOpen) return;
+        loadProfiles().then((list) => setProfile(list[0] ?? newProfile(csvOptions)));
+        // eslint-disable-next-line react-hooks/exhaustive-deps
+    }, [isOpen]);
+
+    // Re-read the file whenever the sheet or the CSV settings change
+    const { delimiter, encoding, decimal_comma } = profile.csv;
+    useEffect(() => {
+        if (!path) return;
+        invoke<SpreadsheetPreview>('preview_spreadsheet', {
+            path,
+            options: { delimiter, encoding, decimal_comma },
+            sheet: profile.sheet,
+        })
+            .then(setPreview)
+            .catch((error) => {
+                setPreview(null);
+                alert('Dosya okunamadı: ' + error);
+            });
+    }, [path, profile.sheet, delimiter, encoding, decimal_comma]);
+
+    const pickFile = async () => {
+        const { open } = await import('@tauri-apps/plugin-dialog');
+        const selected = await open({
+            multiple: false,
+            filters: [{ name: 'Tablo', extensions: [...WORKBOOK_EXTENSIONS, 'csv', 'txt'] }]
+        });
+        if (selected) setPath(selected as string);
+    };
+
+    const setColumn = (key: keyof ColumnMap, header: string) => {
+        setProfile((prev) => ({
+            ...prev,
+            columns: { ...prev.columns, [key]: header || (FIELDS.find((f) => f.key === key)?.required ? '' : null) },
+        }));
+    };
+
+    const setSide = (label: string, side: TransactionSide | '') => {
+        setProfile((prev) => {
+            const side_values = { ...prev.side_values };
+            if (side) side_values[label] = side;
+            else delete side_values[label];
+            return { ...prev, side_values };
+        });
+    };
+
+    // Labels seen in the previewed side column plus the ones the profile already maps
+    const sideIndex = preview?.headers.indexOf(profile.columns.side) ?? -1;
+    const sideLabels = Array.from(
+        new Set([
+            ...(sideIndex >= 0 ? preview!.rows.map((row) => row[sideIndex]?.trim()).filter(Boolean) : []),
+            ...Object.keys(profile.side_values),
+        ])
+    );
+
+    const headers = preview?.headers ?? [];
+    const ready = path !== null && FIELDS.every((f) => !f.required || headers.includes(profile.columns[f.key] ?? ''));
+
+    const handleSave = async () => {
+        const name = profile.name.trim();
+        if (!name) {
+            alert('Profil adı girin.');
+            return;
+        }
+        try {
+            await invoke('save_import_profile', { profile: { ...profile, name } });
+            await loadProfiles();
+            setProfile((prev) => ({ ...prev, name }));
+            alert(`'${name}' profili kaydedildi.`);
+        } catch (error) {
+            alert('Profil kaydedilemedi: ' + error);
+        }
+    };
+
+    const handleDelete = async () => {
+        if (!profiles.some((p) => p.name === profile.name)) return;
+        if (!window.confirm(`'${profile.name}' profili silinsin mi?`)) return;
+        try {
+            await invoke('delete_import_profile', { name: profile.name });
+            await loadProfiles();
+            setProfile(newProfile(csvOptions));
+        } catch (error) {
+            alert('Profil silinemedi: ' + error);
+        }
+    };
+
+    const handleImport = async () => {
+        if (!path) return;
+        setBusy(true);
+        try {
+            await onImport(path, profile);
+            await loadProfiles();
+        } finally {
+            setBusy(false);
+        }
+    };
+
+    return (
+        <Modal
+            isOpen={isOpen}
+            onClose={onClose}
+            size="xl"
+            title="Aracı Kurum / Banka Dosyası İçe Aktar"
+            description="Excel veya CSV dosyanızın sütunlarını işlem alanlarıyla eşleştirin; eşleştirmeyi profil olarak kaydedip tekrar kullanabilirsiniz."
+            footer={
+                <Button onClick={handleImport} disabled={!ready || busy} leftIcon={<Upload size={16} />}>
+                    İçe Aktar
+                </Button>
+            }
+        >
+            <div className="space-y-4 max-h-[65vh] overflow-y-auto pr-1">
+                {/* Profile */}
+                <div className="flex gap-2 items-center">
+                    <select
+                        value={profiles.some((p) => p.name === profile.name) ? profile.name : ''}
+                        onChange={(e) =>
+                            setProfile(profiles.find((p) => p.name === e.target.value) ?? newProfile(csvOptions))
+                        }
+                        className={selectClass}
+                    >
+                        <option value="">Yeni profil</option>
+                        {profiles.map((p) => (
+                            <option key={p.name} value={p.name}>{p.name}</option>
+                        ))}
+                    </select>
+                    <input
+                        value={profile.name}
+                        onChange={(e) => setProfile((prev) => ({ ...prev, name: e.target.value }))}
+                        placeholder="Profil adı (ör. Banka Ekstresi)"
+                        className={selectClass}
+                    />
+                    <Button variant="secondary" size="sm" onClick={handleSave} leftIcon={<Save size={14} />}>
+                        Kaydet
+                    </Button>
+                    <Button
+                        variant="ghost"
+                        size="sm"
+                        onClick={handleDelete}
+                        className="text-rose-500 hover:bg-rose-500/10"
+                        aria-label="Profili sil"
+                    >
+                        <Trash2 size={14} />
+                    </Button>
+                </div>
+
+                {/* File */}
+                <div className="flex gap-2 items-center">
+                    <Button variant="secondary" size="sm" onClick={pickFile} leftIcon={<FolderOpen size={14} />}>
+                        Dosya Seç
+                    </Button>
+                    <p className="flex-1 text-xs text-[var(--color-text-secondary)] truncate">{path ?? 'Dosya seçilmedi'}</p>
+                    {path && isWorkbook(path) && preview && preview.sheets.length > 1 && (
+                        <select
+                            value={profile.sheet ?? preview.sheets[0]}
+                            onChange={(e) => setProfile((prev) => ({ ...prev, sheet: e.target.value }))}
+                            className={selectClass + ' max-w-[12rem]'}
+                        >
+                            {preview.sheets.map((s) => (
+                                <option key={s} value={s}>{s}</option>
+                            ))}
+                        </select>
+                    )}
+                    {path && !isWorkbook(path) && (
+                        <>
+                            <select
+                                value={profile.csv.delimiter}
+                                onChange={(e) => {
+                                    const delimiter = e.target.value as CsvOptions['delimiter'];
+                                    setProfile((prev) => ({ ...prev, csv: { ...prev.csv, delimiter, decimal_comma: delimiter !== ',' } }));
+                                }}
+                                className={selectClass + ' max-w-[10rem]'}
+                            >
+                                <option value=";">Noktalı virgül (;)</option>
+                                <option value=",">Virgül (,)</option>
+                                <option value={'\t'}>Sekme</option>
+                            </select>
+                            <select
+                                value={profile.csv.encoding}
+                                onChange={(e) => {
+                                    const encoding = e.target.value as CsvOptions['encoding'];
+                                    setProfile((prev) => ({ ...prev, csv: { ...prev.csv, encoding } }));
+                                }}
+                                className={selectClass + ' max-w-[10rem]'}
+                            >
+                                <option value="utf-8">UTF-8</option>
+                                <option value="windows-1254">Windows-1254</option>
+                            </select>
+                        </>
+                    )}
+                </div>
+
+                {/* Preview */}
+                {preview && (
+                    <div className="overflow-x-auto rounded-xl border border-[var(--color-border)]">
+                        <table className="w-full text-[11px] text-[var(--color-text-primary)]">
+                            <thead className="bg-[var(--color-bg-tertiary)]">
+                                <tr>
+                                    {preview.headers.map((h, i) => (
+                                        <th key={i} className="px-2 py-1 text-left font-semibold whitespace-nowrap">{h}</th>
+                                    ))}
+                                </tr>
+                            </thead>
+                            <tbody>
+                                {preview.rows.map((row, i) => (
+                                    <tr key={i} className="border-t border-[var(--color-border)]">
+                                        {preview.headers.map((_, j) => (
+                                            <td key={j} className="px-2 py-1 whitespace-nowrap">{row[j]}</td>
+                                        ))}
+                                    </tr>
+                                ))}
+                            </tbody>
+                        </table>
+                    </div>
+                )}
+
+                {/* Column mapping */}
+                <div className="grid grid-cols-2 md:grid-cols-3 gap-3">
+                    {FIELDS.map((field) => (
+                        <div key={field.key} className="space-y-1">
+                            <label className="text-[11px] font-medium text-[var(--color-text-secondary)]">
+                                {field.label}{field.required && ' *'}
+                            </label>
+                            <select
+                                value={profile.columns[field.key] ?? ''}
+                                onChange={(e) => setColumn(field.key, e.target.value)}
+                                className={selectClass}
+                            >
+                                <option value="">{field.required ? 'Sütun seçin' : 'Yok'}</option>
+                                {headers.map((h, i) => (
+                                    <option key={i} value={h}>{h}</option>
+                                ))}
+                                {/* Keep a saved mapping visible even before a file is picked */}
+                                {profile.columns[field.key] && !headers.includes(profile.columns[field.key]!) && (
+                                    <option value={profile.columns[field.key]!}>{profile.columns[field.key]}</option>
+                                )}
+                            </select>
+                        </div>
+                    ))}
+                    <div className="space-y-1">
+                        <label className="text-[11px] font-medium text-[var(--color-text-secondary)]">Varlık Tipi</label>
+                        <select
+                            value={profile.asset_type ?? ''}
+                            onChange={(e) => setProfile((prev) => ({ ...prev, asset_type: e.target.value || null }))}
+                            className={selectClass}
+                        >
+                            <option value="">Sembolden tahmin et</option>
+                            <option value="fon">Yatırım Fonu (TEFAS)</option>
+                            <option value="hisse">Hisse Senedi</option>
+                            <option value="emtia">Altın / Emtia</option>
+                            <option value="doviz">Döviz</option>
+                            <option value="kripto">Kripto Para</option>
+                            <option value="endeks">Endeks</option>
+                            <option value="manuel">Manuel Değerlenen</option>
+                        </select>
+                    </div>
+                </div>
+
+                {/* Side labels */}
+                <div className="p-3 rounded-2xl bg-[var(--color-bg-tertiary)] border border-[var(--color-border)] space-y-2">
+                    <p className="text-[11px] text-[var(--color-text-secondary)]">
+                        İşlem yönü sütunundaki değerler. Alış, Satış ve Temettü ayrıca eşleştirilmeden tanınır.
+                    </p>
+                    {sideLabels.map((label) => (
+                        <div key={label} className="flex gap-2 items-center">
+                            <span className="flex-1 text-xs font-medium text-[var(--color-text-primary)]">{label}</span>
+                            <select
+                                value={profile.side_values[label] ?? ''}
+                                onChange={(e) => setSide(label, e.target.value as TransactionSide | '')}
+                                className={selectClass + ' max-w-[10rem]'}
+                            >
+                                <option value="">Otomatik</option>
+                                {SIDES.map((s) => (
+                                    <option key={s.value} value={s.value}>{s.label}</option>
+                                ))}
+                            </select>
+                        </div>
+                    ))}
+                    <div className="flex gap-2">
+                        <input
+                            value={newLabel}
+                            onChange={(e) => setNewLabel(e.target.value)}
+                            placeholder="Başka bir değer (ör. A, S)"
+                            className={selectClass}
+                        />
+                        <Button
+                            variant="secondary"
+                            size="sm"
+                            onClick={() => {
+                                if (newLabel.trim()) setSide(newLabel.trim(), 'buy');
+                                setNewLabel('');
+                            }}
+                        >
+                            Ekle
+                        </Button>
+                    </div>
+                </div>
+            </div>
+        </Modal>
+    );
+});
+
+MappedImportModal.displayName = 'MappedImportModal';
+
+export default MappedImportModal;
@@ -17,12 +17,15 @@ import {
     Activity,
     Clock,
     Target,
-    Shield
+    Shield,
+    Trash2,
+    Save
 } from 'lucide-react';
 import { Card, CardHeader, CardContent } from '../components/ui/Card';
 import Button from '../components/ui/Button';
 import { cn, formatCurrency, formatPercentage } from '../lib/utils';
-import type { Holding } from '../store/useStore';
+import { useStore } from '../store/useStore';
+import type { Holding, ManualValuation } from '../store/useStore';
 
 const API_BASE = 'https://borsapy-api.onrender.com';
 
@@ -56,8 +59,6 @@ interface FundRisk {
     sharpe_ratio?: number;
     sortino_ratio?: number;
     max_drawdown?: number;
-    beta?: number;
-    alpha?: number;
 }
 
 interface StockInfo {
@@ -165,6 +166,9 @@ const AssetDetailPage = memo<AssetDetailPageProps>(({ holding, onBack }) => {
     const [riskData, setRiskData] = useState<FundRisk | null>(null);
     const [stockInfo, setStockInfo] = useState<StockInfo | null>(null);
     const [error, setError] = useState<string | null>(null);
+    const [valuations, setValuations] = useState<ManualValuation[]>([]);
+    const [valuationDate, setValuationDate] = useState(new Date().toISOString().split('T')[0]);
+    const [valuationPrice, setValuationPrice] = useState('');
 
     const normalizedType = holding.asset_type.toLowerCase().trim();
     const isStock = normalizedType === 'hisse' || normalizedType === 'stock';
@@ -172,6 +176,7 @@ const AssetDetailPage = memo<AssetDetailPageProps>(({ holding, onBack }) => {
     const isFx = normalizedType === 'doviz' || normalizedType === 'emtia' || normalizedType === 'fx' || normalizedType === 'gold';
     const isCrypto = normalizedType === 'kripto' || normalizedType === 'crypto' || normalizedType === 'coin';
     const isIndex = normalizedType === 'endeks' || normalizedType === 'index';
+    const isManual = normalizedType === 'manuel';
 
     // Clean symbol (remove validation chars if any, though usually trusted)
     const cleanSymbol = holding.symbol.trim().toUpperCase();
@@ -217,6 +222,13 @@ const AssetDetailPage = memo<AssetDetailPageProps>(({ holding, onBack }) => {
                     }
                 }
 
+                // --- MANUAL ASSETS (valuations entered by the user) ---
+                else if (isManual) {
+                    const list = await invoke<ManualValuation[]>('get_manual_valuations', { symbol: cleanSymbol });
+                    setValuations(list);
+                    setHistoryData(list.map((v) => ({ date: v.date, close: v.price })));
+                }
+
                 // --- OTHER ASSETS (Use Direct Fetch for now) ---
                 else {
                     let historyUrl = '';
@@ -281,7 +293,41 @@ const AssetDetailPage = memo<AssetDetailPageProps>(({ holding, onBack }) => {
         };
 
         fetchData();
-    }, [holding.symbol, holding.asset_type, isStock, isFund, isFx, isCrypto, isIndex, cleanSymbol]);
+    }, [holding.symbol, holding.asset_type, isStock, isFund, isFx, isCrypto, isIndex, isManual, cleanSymbol]);
+
+    const reloadValuations = async () => {
+        const list = await invoke<ManualValuation[]>('get_manual_valuations', { symbol: cleanSymbol });
+        setValuations(list);
+        setHistoryData(list.map((v) => ({ date: v.date, close: v.price })));
+        // The latest valuation is the holding's current price
+        await useStore.getState().fetchData();
+        await useStore.getState().triggerAutoBackup();
+    };
+
+    const handleSaveValuation = async () => {
+        const price = parseFloat(valuationPrice.replace(',', '.'));
+        if (!valuationDate || !Number.isFinite(price) || price < 0) {
+            alert('Geçerli bir tarih ve birim değer girin.');
+            return;
+        }
+        try {
+            await invoke('set_manual_valuation', { symbol: cleanSymbol, date: valuationDate, price });
+            setValuationPrice('');
+            await reloadValuations();
+        } catch (error) {
+            alert('Değerleme kaydedilemedi: ' + error);
+        }
+    };
+
+    const handleDeleteValuation = async (date: string) => {
+        if (!window.confirm(`${date} tarihli değerleme silinsin mi?`)) return;
+        try {
+            await invoke('delete_manual_valuation', { symbol: cleanSymbol, date });
+            await reloadValuations();
+        } catch (error) {
+            alert('Değerleme silinemedi: ' + error);
+        }
+    };
 
     // Calculate price change from history
     const priceChanges = React.useMemo(() => {
@@ -524,6 +570,59 @@ const AssetDetailPage = memo<AssetDetailPageProps>(({ holding, onBack }) => {
                         </Card>
                     </motion.div>
 
+                    {/* Manual valuations */}
+                    {isManual && (
+                        <motion.div variants={itemVariants}>
+                            <Card variant="glass">
+                                <CardHeader title="🏷️ Değerlemeler" subtitle="Birim değeri elle girin; en son tarihli değerleme güncel fiyat olur" />
+                                <CardContent>
+                                    <div className="flex flex-col md:flex-row gap-2 mb-4">
+                                        <input
+                                            type="date"
+                                            value={valuationDate}
+                                            onChange={(e) => setValuationDate(e.target.value)}
+                                            className="px-3 py-2 rounded-xl border border-[var(--color-border)] bg-[var(--color-bg-primary)] text-sm text-[var(--color-text-primary)]"
+                                        />
+                                        <input
+                                            type="text"
+                                            inputMode="decimal"
+                                            value={valuationPrice}
+                                            onChange={(e) => setValuationPrice(e.target.value)}
+                                            placeholder="Birim değer (TL)"
+                                            className="flex-1 px-3 py-2 rounded-xl border border-[var(--color-border)] bg-[var(--color-bg-primary)] text-sm text-[var(--color-text-primary)]"
+                                        />
+                                        <Button onClick={handleSaveValuation} leftIcon={<Save size={16} />}>
+                                            Kaydet
+                                        </Button>
+                                    </div>
+                                    {valuations.length > 0 ? (
+                                        <div className="divide-y divide-[var(--color-border)]">
+                                            {[...valuations].reverse().map((v) => (
+                                                <div key={v.date} className="flex items-center justify-between py-2">
+                                                    <span className="text-sm text-[var(--color-text-secondary)]">{v.date}</span>
+                                                    <div className="flex items-center gap-3">
+                                                        <span className="text-sm font-semibold text-[var(--color-text-primary)]">{formatCurrency(v.price)}</span>
+                                                        <button
+                                                            onClick={() => handleDeleteValuation(v.date)}
+                                                            className="p-1.5 rounded-lg text-rose-500 hover:bg-rose-500/10 transition-all"
+                                                            aria-label="Değerlemeyi sil"
+                                                        >
+                                                            <Trash2 size={14} />
+                                                        </button>
+                                                    </div>
+                                                </div>
+                                            ))}
+                                        </div>
+                                    ) : (
+                                        <p className="text-center text-sm text-[var(--color-text-secondary)] py-4">
+                                            Henüz değerleme yok; fiyat alış fiyatında kalır
+                                        </p>
+                                    )}
+                                </CardContent>
+                            </Card>
+                        </motion.div>
+                    )}
+
                     {/* Stock-specific info */}
                     {isStock && stockInfo && (
                         <motion.div variants={itemVariants}>
@@ -600,13 +699,6 @@ const AssetDetailPage = memo<AssetDetailPageProps>(({ holding, onBack }) => {
                                                 variant="negative"
                                             />
                                         )}
-                                        {riskData.beta !== undefined && (
-                                            <StatCard
-                                                label="Beta"
-                                                value={riskData.beta.toFixed(2)}
-                                                icon={BarChart3}
-                                            />
-                                        )}
                                     </div>
                                 </CardContent>
                             </Card>
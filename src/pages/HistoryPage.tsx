@@ -130,6 +130,20 @@ const EditTransactionModal = memo<{
                         </div>
                     </div>
 
+                    <div>
+                        <label className="text-xs font-medium text-[var(--color-text-secondary)] uppercase tracking-wider">
+                            Komisyon
+                        </label>
+                        <input
+                            type="number"
+                            step="0.01"
+                            min="0"
+                            value={formData.fees ?? 0}
+                            onChange={e => setFormData({ ...formData, fees: parseFloat(e.target.value) || 0 })}
+                            className="w-full mt-2 p-3 rounded-xl border border-[var(--color-border)] bg-[var(--color-bg-primary)] text-[var(--color-text-primary)] focus:outline-none focus:ring-2 focus:ring-sky-500/50"
+                        />
+                    </div>
+
                     <div>
                         <label className="text-xs font-medium text-[var(--color-text-secondary)] uppercase tracking-wider">
                             İşlem Tipi
@@ -545,6 +545,7 @@ const PortfolioPage = memo<PortfolioPageProps>(({ holdings, summary, lastUpdates
         'kripto': 'Kripto',
         'doviz': 'Döviz',
         'emtia': 'Emtia',
+        'manuel': 'Manuel',
     };
 
     const assetTypeColors: Record<string, { bg: string; text: string }> = {
@@ -553,6 +554,7 @@ const PortfolioPage = memo<PortfolioPageProps>(({ holdings, summary, lastUpdates
         'kripto': { bg: 'bg-amber-500/10', text: 'text-amber-400' },
         'doviz': { bg: 'bg-violet-500/10', text: 'text-violet-400' },
         'emtia': { bg: 'bg-rose-500/10', text: 'text-rose-400' },
+        'manuel': { bg: 'bg-slate-500/10', text: 'text-slate-400' },
     };
 
     const isPnLPositive = (summary?.unrealized_pnl || 0) >= 0;
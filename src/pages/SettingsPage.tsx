@@ -22,6 +22,8 @@ import Button from '../components/ui/Button';
 import { cn } from '../lib/utils';
 import { useTheme } from '../hooks/useTheme';
 import { useStore } from '../store/useStore';
+import type { CsvOptions, ImportProfile, TransactionImportReport } from '../store/useStore';
+import MappedImportModal from '../components/MappedImportModal';
 
 export interface SettingsPageProps {
     onClearData?: () => void;
@@ -32,6 +34,40 @@ export interface SettingsPageProps {
 type Theme = 'light' | 'dark' | 'system';
 type ExportFormat = 'json';
 
+// Asks the user to go ahead with a dry-run report; returns whether to skip invalid rows, or null to cancel
+const confirmImport = (preview: TransactionImportReport): boolean | null => {
+    const stats = preview.transactions;
+    const describe = (severity: 'error' | 'warning') =>
+        preview.issues
+            .filter((i) => i.severity === severity)
+            .slice(0, 5)
+            .map((i) => `Satır ${i.row}: ${i.message_tr}`)
+            .join('\n');
+    let skipInvalid = false;
+    if (stats.invalid > 0) {
+        if (stats.new_rows === 0) {
+            alert(`${stats.invalid} satır geçersiz, içe aktarılacak işlem yok.\n${describe('error')}`);
+            return null;
+        }
+        const message =
+            `${stats.invalid} satır geçersiz:\n${describe('error')}\n\n` +
+            `Yalnızca geçerli satırlar içe aktarılsın mı?`;
+        if (!window.confirm(message)) return null;
+        skipInvalid = true;
+    }
+    if (stats.new_rows === 0) {
+        alert(`Yeni işlem yok (${stats.duplicates} mükerrer, ${stats.conflicts} çakışan).`);
+        return null;
+    }
+    const warnings = describe('warning');
+    const message =
+        `${stats.new_rows} yeni işlem eklenecek.\n` +
+        `${stats.duplicates} mükerrer ve ${stats.conflicts} çakışan işlem atlanacak.\n` +
+        (warnings ? `\nUyarılar:\n${warnings}\n` : '') +
+        `Devam edilsin mi?`;
+    return window.confirm(message) ? skipInvalid : null;
+};
+
 const containerVariants = {
     hidden: { opacity: 0 },
     visible: {
@@ -53,6 +89,9 @@ const SettingsPage = memo<SettingsPageProps>(function SettingsPage() {
     const { theme, setTheme } = useTheme();
     // Removed auto-update state and logic
     const { fetchData, fetchLastUpdates, backupPath, setBackupPath, triggerAutoBackup } = useStore();
+    const [csvDelimiter, setCsvDelimiter] = useState<CsvOptions['delimiter']>(';');
+    const [csvEncoding, setCsvEncoding] = useState<CsvOptions['encoding']>('utf-8');
+    const [mappedImportOpen, setMappedImportOpen] = useState(false);
 
     // Initial fetch of update times - can be kept if needed for other features in future
     useEffect(() => {
@@ -113,9 +152,21 @@ const SettingsPage = memo<SettingsPageProps>(function SettingsPage() {
         reader.onload = async (event) => {
             try {
                 const json = event.target?.result as string;
-                await invoke('import_database_json', { jsonData: json });
+                const preview = await invoke<TransactionImportReport>('import_database_json', {
+                    jsonData: json,
+                    strategy: 'skip_duplicates',
+                    dryRun: true,
+                });
+                const skipInvalid = confirmImport(preview);
+                if (skipInvalid === null) return;
+
+                const result = await invoke<TransactionImportReport>('import_database_json', {
+                    jsonData: json,
+                    strategy: 'skip_duplicates',
+                    skipInvalid,
+                });
                 await useStore.getState().triggerAutoBackup();
-                alert('Veriler başarıyla içe aktarıldı!');
+                alert(`${result.transactions.written} işlem başarıyla içe aktarıldı!`);
                 fetchData();
             } catch (error) {
                 console.error('Import failed:', error);
@@ -126,6 +177,91 @@ const SettingsPage = memo<SettingsPageProps>(function SettingsPage() {
         e.target.value = ''; // Reset input
     };
 
+    // Semicolon-separated files are what Turkish-locale Excel expects, with decimal commas
+    const csvOptions = (): CsvOptions => ({
+        delimiter: csvDelimiter,
+        encoding: csvEncoding,
+        decimal_comma: csvDelimiter !== ',',
+    });
+
+    const handleCsvExport = async () => {
+        try {
+            const { save } = await import('@tauri-apps/plugin-dialog');
+            const dateStr = new Date().toISOString().split('T')[0];
+            const filePath = await save({
+                defaultPath: `islemler_${dateStr}.csv`,
+                filters: [{ name: 'CSV', extensions: ['csv'] }]
+            });
+            if (!filePath) return;
+
+            const count = await invoke<number>('export_transactions_csv', { path: filePath, options: csvOptions() });
+            alert(`${count} işlem kaydedildi: ${filePath}`);
+        } catch (error) {
+            console.error('CSV export failed:', error);
+            alert('Dışa aktarma başarısız: ' + String(error));
+        }
+    };
+
+    const handleCsvImport = async () => {
+        try {
+            const { open } = await import('@tauri-apps/plugin-dialog');
+            const filePath = await open({
+                multiple: false,
+                filters: [{ name: 'CSV', extensions: ['csv', 'txt'] }]
+            });
+            if (!filePath) return;
+
+            const preview = await invoke<TransactionImportReport>('import_transactions_csv', {
+                path: filePath,
+                options: csvOptions(),
+                strategy: 'skip_duplicates',
+                dryRun: true,
+            });
+            const skipInvalid = confirmImport(preview);
+            if (skipInvalid === null) return;
+
+            const result = await invoke<TransactionImportReport>('import_transactions_csv', {
+                path: filePath,
+                options: csvOptions(),
+                strategy: 'skip_duplicates',
+                skipInvalid,
+            });
+            await useStore.getState().triggerAutoBackup();
+            alert(`${result.transactions.written} işlem başarıyla içe aktarıldı!`);
+            fetchData();
+        } catch (error) {
+            console.error('CSV import failed:', error);
+            alert('İçe aktarma hatası: ' + error);
+        }
+    };
+
+    const handleMappedImport = async (path: string, profile: ImportProfile) => {
+        try {
+            const preview = await invoke<TransactionImportReport>('import_mapped_transactions', {
+                path,
+                profile,
+                strategy: 'skip_duplicates',
+                dryRun: true,
+            });
+            const skipInvalid = confirmImport(preview);
+            if (skipInvalid === null) return;
+
+            const result = await invoke<TransactionImportReport>('import_mapped_transactions', {
+                path,
+                profile,
+                strategy: 'skip_duplicates',
+                skipInvalid,
+            });
+            await useStore.getState().triggerAutoBackup();
+            alert(`${result.transactions.written} işlem başarıyla içe aktarıldı!`);
+            setMappedImportOpen(false);
+            fetchData();
+        } catch (error) {
+            console.error('Mapped import failed:', error);
+            alert('İçe aktarma hatası: ' + error);
+        }
+    };
+
     const handleClear = async () => {
         if (window.confirm('Tüm veriler silinecek! Bu işlem geri alınamaz. Emin misiniz?')) {
             try {
@@ -224,7 +360,7 @@ const SettingsPage = memo<SettingsPageProps>(function SettingsPage() {
                                     </div>
                                     <div>
                                         <p className="text-sm font-bold text-[var(--color-text-primary)]">Veritabanını Yedekle</p>
-                                        <p className="text-[11px] text-[var(--color-text-secondary)]">Tüm verilerinizi JSON formatında dışa aktarın</p>
+                                        <p className="text-[11px] text-[var(--color-text-secondary)]">Tüm verilerinizi JSON, işlemlerinizi CSV formatında dışa aktarın</p>
                                     </div>
                                 </div>
                                 <Button
@@ -235,6 +371,33 @@ const SettingsPage = memo<SettingsPageProps>(function SettingsPage() {
                                 >
                                     JSON Olarak Kaydet
                                 </Button>
+                                <Button
+                                    onClick={handleCsvExport}
+                                    variant="secondary"
+                                    className="w-full"
+                                    leftIcon={<Download size={16} />}
+                                >
+                                    İşlemleri CSV Olarak Kaydet
+                                </Button>
+                                <div className="flex gap-2">
+                                    <select
+                                        value={csvDelimiter}
+                                        onChange={(e) => setCsvDelimiter(e.target.value as CsvOptions['delimiter'])}
+                                        className="flex-1 px-2 py-1.5 rounded-xl border border-[var(--color-border)] bg-[var(--color-bg-primary)] text-xs text-[var(--color-text-primary)]"
+                                    >
+                                        <option value=";">Noktalı virgül (;)</option>
+                                        <option value=",">Virgül (,)</option>
+                                        <option value={'\t'}>Sekme</option>
+                                    </select>
+                                    <select
+                                        value={csvEncoding}
+                                        onChange={(e) => setCsvEncoding(e.target.value as CsvOptions['encoding'])}
+                                        className="flex-1 px-2 py-1.5 rounded-xl border border-[var(--color-border)] bg-[var(--color-bg-primary)] text-xs text-[var(--color-text-primary)]"
+                                    >
+                                        <option value="utf-8">UTF-8</option>
+                                        <option value="windows-1254">Windows-1254</option>
+                                    </select>
+                                </div>
                             </div>
 
                             {/* Restore & Reset Section */}
@@ -245,7 +408,7 @@ const SettingsPage = memo<SettingsPageProps>(function SettingsPage() {
                                     </div>
                                     <div>
                                         <p className="text-sm font-bold text-[var(--color-text-primary)]">Verileri Geri Yükle</p>
-                                        <p className="text-[11px] text-[var(--color-text-secondary)]">JSON yedeğinden tüm işlemlerinizi içe aktarın</p>
+                                        <p className="text-[11px] text-[var(--color-text-secondary)]">JSON yedeğinden, CSV dosyasından veya aracı kurum ekstresinden işlemlerinizi içe aktarın</p>
                                     </div>
                                 </div>
                                 <div className="flex gap-2">
@@ -257,9 +420,21 @@ const SettingsPage = memo<SettingsPageProps>(function SettingsPage() {
                                             onChange={handleImport}
                                         />
                                         <div className="flex items-center justify-center gap-2 px-3 py-2 rounded-xl border border-[var(--color-border)] hover:bg-[var(--color-bg-primary)] transition-all cursor-pointer text-xs font-medium text-[var(--color-text-primary)]">
-                                            İçe Aktar
+                                            JSON İçe Aktar
                                         </div>
                                     </label>
+                                    <button
+                                        onClick={handleCsvImport}
+                                        className="flex-1 px-3 py-2 rounded-xl border border-[var(--color-border)] hover:bg-[var(--color-bg-primary)] transition-all text-xs font-medium text-[var(--color-text-primary)]"
+                                    >
+                                        CSV İçe Aktar
+                                    </button>
+                                    <button
+                                        onClick={() => setMappedImportOpen(true)}
+                                        className="flex-1 px-3 py-2 rounded-xl border border-[var(--color-border)] hover:bg-[var(--color-bg-primary)] transition-all text-xs font-medium text-[var(--color-text-primary)]"
+                                    >
+                                        Excel / Banka Dosyası
+                                    </button>
                                     <button
                                         onClick={handleClear}
                                         className="px-3 py-2 rounded-xl border border-rose-500/30 hover:bg-rose-500/5 transition-all text-rose-500 text-xs font-medium"
@@ -357,10 +532,12 @@ const SettingsPage = memo<SettingsPageProps>(function SettingsPage() {
                 </Card>
             </motion.div>
 
-
-
-
-
+            <MappedImportModal
+                isOpen={mappedImportOpen}
+                onClose={() => setMappedImportOpen(false)}
+                csvOptions={csvOptions()}
+                onImport={handleMappedImport}
+            />
         </motion.div>
     );
 });
@@ -1,17 +1,30 @@
 import { create } from 'zustand';
 import { invoke } from '@tauri-apps/api/core';
 
+export interface OversellWarning {
+    symbol: string;
+    transaction_id: number;
+    sell_date: string;
+    sell_quantity: number;
+    missing_quantity: number;
+}
+
 export interface PortfolioSummary {
     total_value: number;
     total_value_usd: number;
     unrealized_pnl: number;
     realized_pnl: number;
+    dividend_income: number;
     total_return: number;
     roi_pct: number;
     holdings_count: number;
     top_performer: string;
     worst_performer: string;
     last_updated?: string;
+    total_fees: number;
+    oversell_warnings: OversellWarning[];
+    stale_value: number;
+    stale_value_pct: number;
     daily_change: number;
     daily_change_pct: number;
     weekly_change: number;
@@ -30,6 +43,11 @@ export interface Holding {
     value: number;
     pnl: number;
     pnl_pct: number;
+    dividend_income: number;
+    yield_on_cost_pct: number;
+    last_updated?: string;
+    price_source?: string;
+    stale: boolean;
 }
 
 export interface Transaction {
@@ -37,11 +55,19 @@ export interface Transaction {
     date: string;
     symbol: string;
     name: string;
-    type: 'buy' | 'sell';
+    type: 'buy' | 'sell' | 'dividend';
     quantity: number;
     price: number;
     total: number;
     notes?: string;
+    fees?: number;
+    withholding_tax?: number;
+}
+
+// Unit value of a manually valued asset on a date
+export interface ManualValuation {
+    date: string;
+    price: number;
 }
 
 export interface LastUpdates {
@@ -49,6 +75,79 @@ export interface LastUpdates {
     market: string | null;
 }
 
+export type ImportStrategy = 'append' | 'skip_duplicates' | 'replace';
+
+export interface CsvOptions {
+    delimiter: ';' | ',' | '\t';
+    encoding: 'utf-8' | 'windows-1254';
+    decimal_comma: boolean;
+}
+
+export interface TableImportStats {
+    source_rows: number;
+    new_rows: number;
+    duplicates: number;
+    conflicts: number;
+    invalid: number;
+    written: number;
+}
+
+export interface ImportRow {
+    row: number;
+    status: 'new' | 'duplicate' | 'conflict' | 'invalid';
+    date: string | null;
+    symbol: string | null;
+    reason: string | null;
+}
+
+export interface ImportIssue {
+    row: number;
+    severity: 'error' | 'warning';
+    field: string | null;
+    message_tr: string;
+    message_en: string;
+}
+
+export interface TransactionImportReport {
+    strategy: ImportStrategy;
+    dry_run: boolean;
+    skip_invalid: boolean;
+    backup_path: string | null;
+    transactions: TableImportStats;
+    rows: ImportRow[];
+    issues: ImportIssue[];
+}
+
+export type TransactionSide = 'buy' | 'sell' | 'dividend';
+
+export interface ColumnMap {
+    date: string;
+    symbol: string;
+    side: string;
+    quantity: string;
+    price: string;
+    fees: string | null;
+    currency: string | null;
+    broker: string | null;
+    notes: string | null;
+}
+
+export interface ImportProfile {
+    name: string;
+    columns: ColumnMap;
+    side_values: Record<string, TransactionSide>;
+    asset_type: string | null;
+    csv: CsvOptions;
+    sheet: string | null;
+    last_used_at: string | null;
+}
+
+export interface SpreadsheetPreview {
+    sheets: string[];
+    headers: string[];
+    rows: string[][];
+}
+
 interface PortfolioStore {
     summary: PortfolioSummary | null;
     holdings: Holding[];